@@ -0,0 +1,129 @@
+use crate::{FlowGraph, StructureTree};
+use crate::bidirected::BiGraph;
+use crate::haplotypes::HaplotypeMatrix;
+use crate::paths::{PathIndex, parse_pansn};
+use crate::regions::RegionTable;
+
+// one pass of a path through a region, in the path's coordinates
+#[derive(Clone,Debug,PartialEq)]
+pub struct BedRecord {
+    pub start: usize, // 0-based, first base inside the region
+    pub end: usize, // after the last base
+    pub region: usize, // index in the region table
+    pub id: usize, // region id in the structure tree
+    pub depth: usize, // 0 for the root
+    pub class: usize, // cycle equivalence class of the boundary edges
+}
+
+// the regions of a structure tree projected onto one path
+#[derive(Clone,Debug)]
+pub struct Bed {
+    pub path: String,
+    pub chrom: String, // contig of the path, from its PanSN name
+    pub records: Vec<BedRecord>, // by start, outer regions first
+    pub untraversed: Vec<usize>, // table indexes of the regions with segments, the root aside, the path never enters
+}
+
+impl Bed {
+    // the regions on each named path; the graph must be bigraph.to_flow_graph() and the tree built from it;
+    // a region gets a record each time the path passes through it, and the root, which every path is in,
+    // gets none
+    pub fn new(graph: &FlowGraph, tree: &StructureTree, bigraph: &BiGraph, paths: &[&str]) -> Result<Vec<Bed>, String> {
+        let indexes = paths.iter()
+            .map(|name| bigraph.paths.path_index(name).ok_or(format!("no path named {}", name)))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let table = RegionTable::new(graph, tree)?;
+        let matrix = HaplotypeMatrix::new(bigraph, graph, &table);
+        let layout = bigraph.flow_layout();
+        let mut beds = Vec::new();
+        for p in indexes {
+            let path = &bigraph.paths.paths[p];
+            let index = PathIndex::new(path, &bigraph.sequences);
+            let mut records = Vec::new();
+            let mut untraversed = Vec::new();
+            for (r, region) in matrix.regions.iter().enumerate().skip(1) {
+                let traversals = region.traversals_of(p);
+                // regions around the source or sink alone hold nothing a path could take
+                if traversals.is_empty() && table.all_nodes(r).iter().any(|n| layout.segment(*n).is_some()) {
+                    untraversed.push(r);
+                }
+                for t in traversals {
+                    records.push(BedRecord {
+                        start: index.start(t.start),
+                        end: index.start(t.end),
+                        region: r,
+                        id: table.regions[r].id,
+                        depth: table.depth(r),
+                        class: table.regions[r].class,
+                    });
+                }
+            }
+            records.sort_by_key(|b| (b.start, b.depth));
+            beds.push(Bed { path: path.name.clone(), chrom: parse_pansn(&path.name).2.to_string(), records, untraversed });
+        }
+        Ok(beds)
+    }
+
+    // BED6+2: chrom, start, end, the region id as name, score 0 and no strand, then depth and class
+    pub fn to_bed(&self) -> String {
+        let mut bed = String::new();
+        for b in self.records.iter() {
+            bed.push_str(format!("{}\t{}\t{}\t{}\t0\t.\t{}\t{}\n", self.chrom, b.start, b.end, b.id, b.depth, b.class).as_str());
+        }
+        bed
+    }
+
+    // the regions the path never enters as tab separated text: region id, depth and class
+    pub fn untraversed_to_tsv(&self, table: &RegionTable) -> String {
+        let mut tsv = String::new();
+        for r in self.untraversed.iter() {
+            tsv.push_str(format!("{}\t{}\t{}\n", table.regions[*r].id, table.depth(*r), table.regions[*r].class).as_str());
+        }
+        tsv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn nested_bubbles() {
+        // s -> a -> (b1 -> (x | y) -> b2 | c) -> d -> t, the reference starting at 1000 and taking x
+        let gfa = "S\ts\tACG\nS\ta\tT\nS\tb1\tGA\nS\tx\tC\nS\ty\tT\nS\tb2\tAG\nS\tc\tA\nS\td\tC\nS\tt\tGT\n\
+            L\ts\t+\ta\t+\t0M\nL\ta\t+\tb1\t+\t0M\nL\tb1\t+\tx\t+\t0M\nL\tb1\t+\ty\t+\t0M\nL\tx\t+\tb2\t+\t0M\n\
+            L\ty\t+\tb2\t+\t0M\nL\tb2\t+\td\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tc\t+\td\t+\t0M\nL\td\t+\tt\t+\t0M\n\
+            W\tGRCh38\t0\tchr1\t1000\t1012\t>s>a>b1>x>b2>d>t\n\
+            W\tHG002\t1\tchr1\t0\t7\t<t<d<c<a<s\n";
+        let bigraph = BiGraph::from_gfa(gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let beds = Bed::new(&graph, &tree, &bigraph, &["GRCh38#0#chr1", "HG002#1#chr1"]).unwrap();
+        let (reference, other) = (&beds[0], &beds[1]);
+        assert_eq!(reference.chrom, "chr1");
+
+        let region_of = |names: &[&str]| {
+            let mut nodes = names.iter().map(|n| bigraph.flow_node(bigraph.node_index(n).unwrap())).collect::<Vec<usize>>();
+            nodes.sort();
+            (0..table.len()).find(|r| table.all_nodes(*r) == nodes).unwrap()
+        };
+        let (outer, inner, y, c) = (region_of(&["a", "b1", "x", "y", "b2", "c", "d"]), region_of(&["b1", "x", "y", "b2"]), region_of(&["y"]), region_of(&["c"]));
+        let span = |bed: &Bed, r: usize| bed.records.iter().find(|b| b.region == r).map(|b| (b.start, b.end));
+        assert_eq!(span(reference, outer), Some((1003, 1010)));
+        assert_eq!(span(reference, inner), Some((1004, 1009)));
+        let mut missed = vec![y, c];
+        missed.sort();
+        assert_eq!(reference.untraversed, missed);
+        // the other haplotype walks backwards, so its coordinates count from t
+        assert_eq!(span(other, outer), Some((2, 5)));
+        assert!(other.untraversed.contains(&inner));
+
+        let line = format!("chr1\t1004\t1009\t{}\t0\t.\t{}\t{}\n", table.regions[inner].id, table.depth(inner), table.regions[inner].class);
+        assert!(reference.to_bed().contains(&line), "{}", reference.to_bed());
+        assert!(reference.to_bed().lines().all(|l| l.split('\t').count() == 8));
+        assert_eq!(reference.untraversed_to_tsv(&table).lines().count(), 2);
+        assert!(Bed::new(&graph, &tree, &bigraph, &["CHM13"]).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use petgraph::graph::NodeIndex;
+
+use crate::{FlowGraph, add_graph_node, add_graph_edge};
+use crate::paths::{Path, PathStore, parse_p_steps, parse_w_steps};
+use crate::sequence::PackedSequence;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum Orientation {
+    Forward, // + in GFA
+    Reverse, // - in GFA
+}
+
+impl Orientation {
+    pub fn parse(s: &str) -> Option<Orientation> {
+        match s {
+            "+" => Some(Orientation::Forward),
+            "-" => Some(Orientation::Reverse),
+            _ => None,
+        }
+    }
+
+    pub fn flip(self) -> Orientation {
+        match self {
+            Orientation::Forward => Orientation::Reverse,
+            Orientation::Reverse => Orientation::Forward,
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Orientation::Forward => write!(f, "+"),
+            Orientation::Reverse => write!(f, "-"),
+        }
+    }
+}
+
+// a node read in one orientation, as in a step of a GFA walk
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct Handle {
+    pub node: usize, // node index
+    pub orientation: Orientation,
+}
+
+impl Handle {
+    pub fn new(node: usize, orientation: Orientation) -> Handle {
+        Handle { node, orientation }
+    }
+
+    pub fn forward(node: usize) -> Handle {
+        Handle::new(node, Orientation::Forward)
+    }
+
+    pub fn flip(self) -> Handle {
+        Handle::new(self.node, self.orientation.flip())
+    }
+
+    // the side a walk enters the node through, as a vertex of the bi-edged graph: the left side 2v of a
+    // forward node, the right side 2v + 1 of a reversed one
+    pub fn entry_side(self) -> usize {
+        match self.orientation {
+            Orientation::Forward => 2 * self.node,
+            Orientation::Reverse => 2 * self.node + 1,
+        }
+    }
+
+    // the side a walk leaves the node through
+    pub fn exit_side(self) -> usize {
+        self.flip().entry_side()
+    }
+}
+
+// a bidirected graph as in GFA: nodes with two sides, and links each joining a side of one node to a side
+// of another, which a walk may follow in either direction
+#[derive(Clone,Debug,Default)]
+pub struct BiGraph {
+    pub names: Vec<String>, // segment name of each node index
+    pub sequences: Vec<PackedSequence>, // sequence of each node index, empty when the segment has none
+    pub links: Vec<(Handle, Handle)>, // each joins the exit side of the first handle to the entry side of the second
+    pub paths: PathStore,
+    index: HashMap<String, usize>,
+}
+
+impl BiGraph {
+    pub fn new() -> BiGraph {
+        BiGraph::default()
+    }
+
+    // the node of a segment, added if it is new
+    pub fn add_node(&mut self, name: &str) -> usize {
+        if let Some(n) = self.index.get(name) {
+            return *n;
+        }
+        let n = self.names.len();
+        self.names.push(name.to_string());
+        self.sequences.push(PackedSequence::default());
+        self.index.insert(name.to_string(), n);
+        n
+    }
+
+    pub fn add_link(&mut self, from: Handle, to: Handle) {
+        self.links.push((from, to));
+    }
+
+    pub fn node_index(&self, name: &str) -> Option<usize> {
+        self.index.get(name).cloned()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.names.len()
+    }
+
+    // a handle written as in GFA, the segment name followed by its orientation
+    pub fn handle_name(&self, h: Handle) -> String {
+        format!("{}{}", self.names[h.node], h.orientation)
+    }
+
+    // the segments, links, paths and walks of a GFA 1 file; other lines are ignored, and a link or path may
+    // come before the segments it uses
+    pub fn from_gfa(text: &str) -> Result<BiGraph, String> {
+        let mut graph = BiGraph::new();
+        for (i, line) in text.lines().enumerate() {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            match fields[0] {
+                "S" if fields.len() >= 3 => {
+                    let n = graph.add_node(fields[1]);
+                    if fields[2] != "*" {
+                        graph.sequences[n] = PackedSequence::new(fields[2]).map_err(|e| format!("line {}: {}", i + 1, e))?;
+                    }
+                }
+                "L" if fields.len() >= 5 => {
+                    let orientation = |s: &str| Orientation::parse(s).ok_or(format!("line {}: bad orientation {}", i + 1, s));
+                    let from = Handle::new(graph.add_node(fields[1]), orientation(fields[2])?);
+                    let to = Handle::new(graph.add_node(fields[3]), orientation(fields[4])?);
+                    graph.add_link(from, to);
+                }
+                "P" if fields.len() >= 3 => {
+                    let steps = parse_p_steps(&mut graph, fields[2]).map_err(|e| format!("line {}: {}", i + 1, e))?;
+                    graph.paths.add(Path { name: fields[1].to_string(), steps, offset: 0 });
+                }
+                "W" if fields.len() >= 7 => {
+                    let steps = parse_w_steps(&mut graph, fields[6]).map_err(|e| format!("line {}: {}", i + 1, e))?;
+                    // the start may be left out as *
+                    let offset = match fields[4] {
+                        "*" => 0,
+                        start => start.parse().map_err(|_| format!("line {}: bad start {}", i + 1, start))?,
+                    };
+                    let name = format!("{}#{}#{}", fields[1], fields[2], fields[3]);
+                    graph.paths.add(Path { name, steps, offset });
+                }
+                "S" | "L" | "P" | "W" => return Err(format!("line {}: too few fields", i + 1)),
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+
+    // the graph in GFA 1, links without overlap and every path as a P line
+    pub fn to_gfa(&self) -> String {
+        let mut gfa = String::new();
+        gfa.push_str("H\tVN:Z:1.0\n");
+        for (name, sequence) in self.names.iter().zip(self.sequences.iter()) {
+            let sequence = if sequence.is_empty() { "*".to_string() } else { sequence.to_string() };
+            gfa.push_str(format!("S\t{}\t{}\n", name, sequence).as_str());
+        }
+        for (a, b) in self.links.iter() {
+            gfa.push_str(format!("L\t{}\t{}\t{}\t{}\t0M\n", self.names[a.node], a.orientation, self.names[b.node], b.orientation).as_str());
+        }
+        for path in self.paths.paths.iter() {
+            let steps = path.steps.iter().map(|h| self.handle_name(*h)).collect::<Vec<String>>();
+            gfa.push_str(format!("P\t{}\t{}\t*\n", path.name, steps.join(",")).as_str());
+        }
+        gfa
+    }
+
+    // sides without any link, the ends of linear sequences
+    pub fn free_sides(&self) -> Vec<usize> {
+        let mut linked = vec![false; 2 * self.node_count()];
+        for (a, b) in self.links.iter() {
+            linked[a.exit_side()] = true;
+            linked[b.entry_side()] = true;
+        }
+        (0..linked.len()).filter(|s| !linked[*s]).collect()
+    }
+
+    // the bi-edged graph: the sides of node v are vertices 2v (left) and 2v + 1 (right), joined by a black
+    // edge at edge index v; link i is the grey edge at index node_count + i; when some sides are free, a
+    // root vertex 2 * node_count is joined to each of them by an edge after the links, so that the ends of
+    // linear sequences close cycles through it; vertex ids are their indexes
+    pub fn bi_edged(&self) -> FlowGraph {
+        let mut graph = FlowGraph::new_undirected();
+        let sides = (0..2 * self.node_count()).map(|s| add_graph_node(&mut graph, s)).collect::<Vec<NodeIndex>>();
+        for v in 0..self.node_count() {
+            add_graph_edge(&mut graph, sides[2 * v], sides[2 * v + 1]);
+        }
+        for (a, b) in self.links.iter() {
+            add_graph_edge(&mut graph, sides[a.exit_side()], sides[b.entry_side()]);
+        }
+        let free = self.free_sides();
+        if !free.is_empty() {
+            let root = add_graph_node(&mut graph, sides.len());
+            for s in free {
+                add_graph_edge(&mut graph, root, sides[s]);
+            }
+        }
+        graph
+    }
+
+    // the flow graph node of a segment on the strand to_flow_graph reads it along
+    pub fn flow_node(&self, node: usize) -> usize {
+        node + 1
+    }
+
+    // the strand of each segment in to_flow_graph: links spread a strand over each connected component from
+    // its first segment read forward, so that a link reads from one handle along its strand to the next;
+    // a segment that a link needs on both strands, as an inversion does, gets a second node for the other
+    pub fn flow_layout(&self) -> FlowLayout {
+        let n = self.node_count();
+        let mut adjacent = vec![Vec::new(); n];
+        for (a, b) in self.links.iter() {
+            adjacent[a.node].push((a.orientation, *b));
+            adjacent[b.node].push((b.orientation, *a));
+        }
+        let mut strand = vec![None; n];
+        for start in 0..n {
+            if strand[start].is_some() {
+                continue;
+            }
+            strand[start] = Some(Orientation::Forward);
+            let mut stack = vec![start];
+            while let Some(v) = stack.pop() {
+                let s = strand[v].unwrap();
+                for (o, other) in adjacent[v].iter() {
+                    // the link reads along both strands or against both
+                    let t = if *o == s { other.orientation } else { other.orientation.flip() };
+                    if strand[other.node].is_none() {
+                        strand[other.node] = Some(t);
+                        stack.push(other.node);
+                    }
+                }
+            }
+        }
+        let mut layout = FlowLayout {
+            strands: strand.into_iter().map(|s| s.unwrap()).collect(),
+            flipped: Vec::new(),
+            second: vec![None; n],
+        };
+        for (a, b) in self.links.iter() {
+            if layout.link(*a, *b).is_none() {
+                // the link reads along one strand and against the other: add the missing end of a to b
+                let h = if layout.along(*a).is_some() { *b } else { *a };
+                layout.second[h.node] = Some(n + 2 + layout.flipped.len());
+                layout.flipped.push(h.node);
+            }
+        }
+        layout
+    }
+
+    // the graph for the structure tree, laid out as flow_layout says: node v on its strand is flow node v + 1
+    // and the i-th flipped segment on its other strand is node_count + 2 + i, between a source 0 and a sink
+    // node_count + 1; ids are indexes and nodes carry their sequence as read along their strand; a link is an
+    // edge in the direction a walk takes it; the source leads to every node without edges in, and every node
+    // without edges out leads to the sink, which returns to the source; bi_edged keeps the orientation of
+    // every link instead
+    pub fn to_flow_graph(&self) -> FlowGraph {
+        let n = self.node_count();
+        let layout = self.flow_layout();
+        let count = n + 2 + layout.flipped.len();
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..count).map(|v| add_graph_node(&mut graph, v)).collect::<Vec<NodeIndex>>();
+        for f in 0..count {
+            if let Some(h) = layout.segment(f) {
+                let sequence = &self.sequences[h.node];
+                graph[nodes[f]].borrow_mut().sequence = match h.orientation {
+                    Orientation::Forward => sequence.clone(),
+                    // the complement of an IUPAC code is one too
+                    Orientation::Reverse => PackedSequence::new(&sequence.reverse_complement()).unwrap(),
+                };
+            }
+        }
+        let (mut has_in, mut has_out) = (vec![false; count], vec![false; count]);
+        for (a, b) in self.links.iter() {
+            let (from, to) = layout.link(*a, *b).unwrap();
+            add_graph_edge(&mut graph, nodes[from], nodes[to]);
+            has_out[from] = true;
+            has_in[to] = true;
+        }
+        let segments = (0..count).filter(|f| layout.segment(*f).is_some()).collect::<Vec<usize>>();
+        let heads = segments.iter().cloned().filter(|f| !has_in[*f]).collect::<Vec<usize>>();
+        let tails = segments.iter().cloned().filter(|f| !has_out[*f]).collect::<Vec<usize>>();
+        // a graph that is all cycles still needs a way in and a way out
+        for f in if heads.is_empty() && n > 0 { vec![self.flow_node(0)] } else { heads } {
+            add_graph_edge(&mut graph, nodes[0], nodes[f]);
+        }
+        for f in if tails.is_empty() && n > 0 { vec![self.flow_node(n - 1)] } else { tails } {
+            add_graph_edge(&mut graph, nodes[f], nodes[n + 1]);
+        }
+        add_graph_edge(&mut graph, nodes[n + 1], nodes[0]);
+        graph
+    }
+}
+
+// where to_flow_graph puts each segment, from BiGraph::flow_layout
+#[derive(Clone,Debug,PartialEq)]
+pub struct FlowLayout {
+    pub strands: Vec<Orientation>, // the strand flow node v + 1 reads segment v along
+    pub flipped: Vec<usize>, // segments also on the other strand, at flow node node_count + 2 + i
+    second: Vec<Option<usize>>, // flow node of each segment on its other strand
+}
+
+impl FlowLayout {
+    // the flow node that reads a handle along the flow, if there is one
+    pub fn along(&self, h: Handle) -> Option<usize> {
+        if self.strands[h.node] == h.orientation {
+            Some(h.node + 1)
+        } else {
+            self.second[h.node]
+        }
+    }
+
+    // the flow node of a path step: the one that reads it along the flow, else its segment's node, which
+    // the step walks backwards
+    pub fn node(&self, h: Handle) -> usize {
+        self.along(h).unwrap_or(h.node + 1)
+    }
+
+    // the handle a flow node reads along the flow, None for the source and sink
+    pub fn segment(&self, f: usize) -> Option<Handle> {
+        let n = self.strands.len();
+        match f {
+            0 => None,
+            f if f <= n => Some(Handle::new(f - 1, self.strands[f - 1])),
+            f => self.flipped.get(f.checked_sub(n + 2)?).map(|v| Handle::new(*v, self.strands[*v].flip())),
+        }
+    }
+
+    // the edge (from, to) of the link from a to b, read along the flow one way round or the other
+    pub fn link(&self, a: Handle, b: Handle) -> Option<(usize, usize)> {
+        match (self.along(a), self.along(b)) {
+            (Some(from), Some(to)) => Some((from, to)),
+            _ => match (self.along(b.flip()), self.along(a.flip())) {
+                (Some(from), Some(to)) => Some((from, to)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gfa() {
+        let text = "H\tVN:Z:1.0\nS\ta\tACG\nS\tb\tT\nL\ta\t+\tb\t-\t0M\nL\tb\t-\tc\t+\t0M\nP\tx\ta+,b-\t*\n";
+        let graph = BiGraph::from_gfa(text).unwrap();
+        assert_eq!(graph.names, vec!["a", "b", "c"]);
+        assert_eq!((graph.sequences[0].to_string(), graph.sequences[2].len()), ("ACG".to_string(), 0));
+        assert_eq!(graph.links, vec![(Handle::forward(0), Handle::forward(1).flip()), (Handle::forward(1).flip(), Handle::forward(2))]);
+        let again = BiGraph::from_gfa(&graph.to_gfa()).unwrap();
+        assert_eq!((again.links, again.sequences), (graph.links.clone(), graph.sequences.clone()));
+        // a's left side and c's right side are free; the link a+ b- joins the right sides of a and b
+        assert_eq!(graph.free_sides(), vec![0, 5]);
+        let bi_edged = graph.bi_edged();
+        assert_eq!(bi_edged.node_count(), 7);
+        assert_eq!(bi_edged.edge_count(), 3 + 2 + 2);
+        assert!(BiGraph::from_gfa("L\ta\t+\tb\n").is_err());
+        assert_eq!(BiGraph::from_gfa("S\ta\tAC\nS\tb\tAé\n").unwrap_err(), "line 2: bad base é");
+        assert!(BiGraph::from_gfa("L\ta\t+\tb\tx\t0M\n").is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let text = "S\ta\t*\nS\tb\t*\nL\ta\t+\tb\t+\t0M\nP\tref\ta+,b+\t*\nW\tHG002\t1\tchr1\t100\t102\t<b<a\n";
+        let graph = BiGraph::from_gfa(text).unwrap();
+        assert_eq!(graph.paths.names(), vec!["ref", "HG002#1#chr1"]);
+        let walk = graph.paths.get("HG002#1#chr1").unwrap();
+        assert_eq!(walk.steps, vec![Handle::forward(1).flip(), Handle::forward(0).flip()]);
+        assert_eq!(walk.offset, 100);
+        let again = BiGraph::from_gfa(&graph.to_gfa()).unwrap();
+        assert_eq!(again.paths.get("HG002#1#chr1").unwrap().steps, walk.steps);
+        assert!(BiGraph::from_gfa("P\tx\ta+,b\t*\n").is_err());
+        assert!(BiGraph::from_gfa("P\tx\ta+,bé\t*\n").is_err());
+        assert_eq!(BiGraph::from_gfa("W\tx\t1\tchr1\tfirst\t2\t>a\n").unwrap_err(), "line 1: bad start first");
+        assert_eq!(BiGraph::from_gfa("W\tx\t1\tchr1\t*\t*\t>a\n").unwrap().paths.paths[0].offset, 0);
+
+        // source, a, b, sink, with the return edge
+        let flow = graph.to_flow_graph();
+        assert_eq!(flow.node_count(), 4);
+        assert_eq!(flow.edge_count(), 4);
+        assert!(flow[NodeIndex::new(1)].borrow().sequence.is_empty());
+        let layout = graph.flow_layout();
+        assert_eq!((layout.segment(graph.flow_node(1)), layout.segment(0), layout.segment(3)), (Some(Handle::forward(1)), None, None));
+        // b- a- is a+ b+ read on the other strand
+        let reversed = BiGraph::from_gfa("L\tb\t-\ta\t-\t0M\n").unwrap().to_flow_graph();
+        assert!(reversed.edge_weights().any(|e| (e.borrow().from, e.borrow().to) == (2, 1)));
+        // a+ b- reads b on the other strand throughout, so b needs no second node
+        let turned = BiGraph::from_gfa("S\ta\tAC\nS\tb\tGGT\nL\ta\t+\tb\t-\t0M\n").unwrap();
+        let layout = turned.flow_layout();
+        assert_eq!((layout.strands.clone(), layout.flipped.clone()), (vec![Orientation::Forward, Orientation::Reverse], vec![]));
+        let flow = turned.to_flow_graph();
+        assert_eq!(flow.node_count(), 4);
+        assert_eq!(flow[NodeIndex::new(2)].borrow().sequence.to_string(), "ACC");
+        assert!(flow.edge_weights().any(|e| (e.borrow().from, e.borrow().to) == (1, 2)));
+        // a+ b+ c+ next to a+ b- c+ reads b on both strands, so b gets a second node
+        let inverted = BiGraph::from_gfa("S\ta\t*\nS\tb\tAAC\nS\tc\t*\nL\ta\t+\tb\t+\t0M\nL\tb\t+\tc\t+\t0M\nL\ta\t+\tb\t-\t0M\nL\tb\t-\tc\t+\t0M\n").unwrap();
+        let layout = inverted.flow_layout();
+        assert_eq!(layout.flipped, vec![1]);
+        assert_eq!((layout.node(Handle::forward(1)), layout.node(Handle::forward(1).flip())), (2, 5));
+        assert_eq!(layout.segment(5), Some(Handle::forward(1).flip()));
+        let flow = inverted.to_flow_graph();
+        assert_eq!((flow.node_count(), flow.edge_count()), (6, 7));
+        assert_eq!(flow[NodeIndex::new(5)].borrow().sequence.to_string(), "GTT");
+        let edges: Vec<(usize, usize)> = flow.edge_weights().map(|e| (e.borrow().from, e.borrow().to)).collect();
+        assert!(edges.contains(&(1, 5)) && edges.contains(&(5, 3)));
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use crate::{FlowGraph, Edge, add_graph_node, cycle_equivalence, dfs_tree};
+use crate::digraph::DiGraph;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// biconnected components (blocks) of a multigraph given as endpoints by edge index, None edges being
+// ignored; every edge is in exactly one block, a bridge is a block of one edge, and an articulation point
+// is a node in more than one block
+#[derive(Clone,Debug)]
+pub struct Blocks {
+    pub blocks: Vec<Vec<usize>>, // sorted edge indexes of each block
+    pub nodes: Vec<Vec<usize>>, // sorted node indexes of each block
+    pub edge_block: Vec<Option<usize>>, // block of each edge by edge index
+    pub articulation_points: Vec<usize>, // sorted node indexes
+    pub bridges: Vec<usize>, // sorted edge indexes
+}
+
+impl Blocks {
+    // Hopcroft and Tarjan's depth first search with a stack of edges, in linear time
+    pub fn new(edges: &[Option<(usize, usize)>]) -> Blocks {
+        let node_count = edges.iter().flatten().map(|(a, b)| a.max(b) + 1).max().unwrap_or(0);
+        let mut incident = vec![Vec::new(); node_count];
+        let mut blocks = Vec::new();
+        for (e, ends) in edges.iter().enumerate() {
+            match ends {
+                // a loop lies on no path between other nodes, so it is a block of its own
+                Some((a, b)) if a == b => blocks.push(vec![e]),
+                Some((a, b)) => {
+                    incident[*a].push(e);
+                    incident[*b].push(e);
+                }
+                None => {}
+            }
+        }
+        let mut number = vec![0; node_count];
+        let mut low = vec![0; node_count];
+        let mut count = 0;
+        let mut edge_stack = Vec::new();
+        for root in 0..node_count {
+            if number[root] != 0 || incident[root].is_empty() {
+                continue;
+            }
+            count += 1;
+            number[root] = count;
+            low[root] = count;
+            // frames of (node, tree edge into it, position of the next incident edge)
+            let mut stack = vec![(root, usize::MAX, 0)];
+            while let Some((v, parent_edge, i)) = stack.pop() {
+                if i < incident[v].len() {
+                    stack.push((v, parent_edge, i + 1));
+                    let e = incident[v][i];
+                    if e == parent_edge {
+                        continue;
+                    }
+                    let (a, b) = edges[e].unwrap();
+                    let w = if a == v { b } else { a };
+                    if number[w] == 0 {
+                        edge_stack.push(e);
+                        count += 1;
+                        number[w] = count;
+                        low[w] = count;
+                        stack.push((w, e, 0));
+                    } else if number[w] < number[v] {
+                        edge_stack.push(e);
+                        low[v] = low[v].min(number[w]);
+                    }
+                    continue;
+                }
+                if let Some((u, _, _)) = stack.last() {
+                    let u = *u;
+                    low[u] = low[u].min(low[v]);
+                    if low[v] >= number[u] {
+                        let mut block = Vec::new();
+                        while let Some(e) = edge_stack.pop() {
+                            block.push(e);
+                            if e == parent_edge {
+                                break;
+                            }
+                        }
+                        blocks.push(block);
+                    }
+                }
+            }
+        }
+        let mut edge_block = vec![None; edges.len()];
+        let mut nodes = Vec::new();
+        let mut in_blocks = vec![0; node_count];
+        let mut bridges = Vec::new();
+        for (b, block) in blocks.iter_mut().enumerate() {
+            block.sort();
+            let mut block_nodes = block.iter()
+                .flat_map(|e| { let (a, b) = edges[*e].unwrap(); vec![a, b] })
+                .collect::<Vec<usize>>();
+            block_nodes.sort();
+            block_nodes.dedup();
+            for e in block.iter() {
+                edge_block[*e] = Some(b);
+            }
+            if block_nodes.len() > 1 {
+                for n in block_nodes.iter() {
+                    in_blocks[*n] += 1;
+                }
+            }
+            if block.len() == 1 && block_nodes.len() == 2 {
+                bridges.push(block[0]);
+            }
+            nodes.push(block_nodes);
+        }
+        bridges.sort();
+        let articulation_points = (0..node_count).filter(|n| in_blocks[*n] > 1).collect();
+        Blocks { blocks, nodes, edge_block, articulation_points, bridges }
+    }
+
+    // the blocks of a flow graph without its capping backedges
+    pub fn from_flow_graph(graph: &FlowGraph) -> Blocks {
+        Blocks::new(&DiGraph::from_flow_graph(graph).edges)
+    }
+
+    pub fn is_bridge(&self, e: usize) -> bool {
+        self.bridges.binary_search(&e).is_ok()
+    }
+
+    pub fn is_articulation_point(&self, n: usize) -> bool {
+        self.articulation_points.binary_search(&n).is_ok()
+    }
+
+    // the blocks holding a node, more than one exactly for an articulation point
+    pub fn blocks_of(&self, n: usize) -> Vec<usize> {
+        (0..self.blocks.len()).filter(|b| self.nodes[*b].binary_search(&n).is_ok()).collect()
+    }
+
+    // for each block, the node of the block nearest to node t: t itself in a block holding it, otherwise the
+    // articulation point on the way to t; None for a block not connected to t
+    pub fn nearest(&self, t: usize) -> Vec<Option<usize>> {
+        let tree = self.block_cut_tree();
+        let mut nearest = vec![None; self.blocks.len()];
+        let start = match tree.vertices.iter().position(|v| *v == BlockCutVertex::Cut(t)) {
+            Some(v) => vec![v],
+            None => self.blocks_of(t),
+        };
+        let mut seen = vec![false; tree.vertices.len()];
+        let mut queue = std::collections::VecDeque::new();
+        for v in start {
+            seen[v] = true;
+            queue.push_back((v, t));
+        }
+        while let Some((v, toward)) = queue.pop_front() {
+            let toward = match tree.vertices[v] {
+                BlockCutVertex::Block(b) => {
+                    nearest[b] = Some(toward);
+                    toward
+                }
+                BlockCutVertex::Cut(n) => n,
+            };
+            for w in tree.adjacency[v].iter() {
+                if !seen[*w] {
+                    seen[*w] = true;
+                    queue.push_back((*w, toward));
+                }
+            }
+        }
+        nearest
+    }
+
+    // the block-cut tree: a vertex for each block and each articulation point, with an edge between a
+    // block and each articulation point in it; a forest when the graph is not connected
+    pub fn block_cut_tree(&self) -> BlockCutTree {
+        let mut tree = BlockCutTree { vertices: Vec::new(), adjacency: Vec::new() };
+        for b in 0..self.blocks.len() {
+            tree.vertices.push(BlockCutVertex::Block(b));
+            tree.adjacency.push(Vec::new());
+        }
+        for n in self.articulation_points.iter() {
+            let v = tree.vertices.len();
+            tree.vertices.push(BlockCutVertex::Cut(*n));
+            tree.adjacency.push(Vec::new());
+            for b in self.blocks_of(*n) {
+                tree.adjacency[v].push(b);
+                tree.adjacency[b].push(v);
+            }
+        }
+        tree
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum BlockCutVertex {
+    Block(usize), // block index
+    Cut(usize), // node index of an articulation point
+}
+
+#[derive(Clone,Debug)]
+pub struct BlockCutTree {
+    pub vertices: Vec<BlockCutVertex>, // the blocks first, by block index, then the articulation points
+    pub adjacency: Vec<Vec<usize>>, // vertex indexes
+}
+
+impl BlockCutTree {
+    // the tree in dot format, blocks labelled with their node ids and articulation points by id
+    pub fn to_dot(&self, graph: &FlowGraph, blocks: &Blocks) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut dot = String::new();
+        dot.push_str("graph {\n");
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            match vertex {
+                BlockCutVertex::Block(b) => {
+                    let ids = blocks.nodes[*b].iter().map(|n| id(*n).to_string()).collect::<Vec<String>>();
+                    dot.push_str(format!("v{} [shape=box,label=\"{}\"];\n", v, ids.join(" ")).as_str());
+                }
+                BlockCutVertex::Cut(n) => {
+                    dot.push_str(format!("v{} [label=\"{}\"];\n", v, id(*n)).as_str());
+                }
+            }
+            for w in self.adjacency[v].iter().filter(|w| **w > v) {
+                dot.push_str(format!("v{} -- v{};\n", v, w).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// 2-edge-connected components: the node sets left connected once the bridges are removed
+#[derive(Clone,Debug)]
+pub struct TwoEdgeComponents {
+    pub components: Vec<Vec<usize>>, // sorted node indexes, in order of their lowest node
+    pub node_component: Vec<Option<usize>>, // component of each node by node index, None for a node without edges
+    pub bridges: Vec<usize>, // sorted edge indexes
+}
+
+impl TwoEdgeComponents {
+    pub fn new(edges: &[Option<(usize, usize)>]) -> TwoEdgeComponents {
+        let blocks = Blocks::new(edges);
+        TwoEdgeComponents::from_blocks(edges, &blocks)
+    }
+
+    pub fn from_blocks(edges: &[Option<(usize, usize)>], blocks: &Blocks) -> TwoEdgeComponents {
+        let node_count = edges.iter().flatten().map(|(a, b)| a.max(b) + 1).max().unwrap_or(0);
+        let mut adjacency = vec![Vec::new(); node_count];
+        let mut has_edges = vec![false; node_count];
+        for (e, ends) in edges.iter().enumerate() {
+            if let Some((a, b)) = ends {
+                has_edges[*a] = true;
+                has_edges[*b] = true;
+                if !blocks.is_bridge(e) {
+                    adjacency[*a].push(*b);
+                    adjacency[*b].push(*a);
+                }
+            }
+        }
+        let mut node_component = vec![None; node_count];
+        let mut components = Vec::new();
+        for start in 0..node_count {
+            if !has_edges[start] || node_component[start].is_some() {
+                continue;
+            }
+            let c = components.len();
+            node_component[start] = Some(c);
+            let mut members = vec![start];
+            let mut stack = vec![start];
+            while let Some(v) = stack.pop() {
+                for w in adjacency[v].iter() {
+                    if node_component[*w].is_none() {
+                        node_component[*w] = Some(c);
+                        members.push(*w);
+                        stack.push(*w);
+                    }
+                }
+            }
+            members.sort();
+            components.push(members);
+        }
+        TwoEdgeComponents { components, node_component, bridges: blocks.bridges.clone() }
+    }
+
+    pub fn from_flow_graph(graph: &FlowGraph) -> TwoEdgeComponents {
+        TwoEdgeComponents::new(&DiGraph::from_flow_graph(graph).edges)
+    }
+}
+
+// cycle equivalence one block at a time: no cycle leaves a block, so the classes of the whole graph are
+// those of its blocks, with each bridge in a class of its own instead of breaking the bracket lists
+// a block that flow passes through, entering at the node nearest the source and leaving at the node
+// nearest the sink, gets a return edge of its own so that its classes are ordered as in a flow graph;
+// each block is copied into a graph of its own, and the classes and tree and backedge marks are copied
+// back with the class numbers shifted to stay distinct; no capping backedges are added to the graph
+pub fn cycle_equivalence_by_block(graph: &mut FlowGraph) {
+    by_block(graph, true);
+}
+
+// the plain undirected classes one block at a time, for graphs without a source and sink such as the
+// bi-edged graph of a bidirected graph: no block gets a return edge of its own
+pub fn cycle_equivalence_undirected(graph: &mut FlowGraph) {
+    by_block(graph, false);
+}
+
+fn by_block(graph: &mut FlowGraph, with_returns: bool) {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let blocks = Blocks::new(&digraph.edges);
+    let entries = blocks.nearest(digraph.entry);
+    let exits = blocks.nearest(digraph.exit);
+    let mut next_class = 1;
+    for (b, block) in blocks.blocks.iter().enumerate() {
+        if blocks.is_bridge(block[0]) || blocks.nodes[b].len() < 2 {
+            let mut edge = graph.edge_weight(EdgeIndex::new(block[0])).unwrap().borrow_mut();
+            edge.class = next_class;
+            edge.is_tree_edge = blocks.is_bridge(block[0]);
+            edge.is_backedge = !edge.is_tree_edge;
+            next_class += 1;
+            continue;
+        }
+        let mut sub = FlowGraph::new_undirected();
+        let mut index = BTreeMap::new();
+        for n in blocks.nodes[b].iter() {
+            let id = graph[NodeIndex::new(*n)].borrow().id;
+            index.insert(*n, add_graph_node(&mut sub, id));
+        }
+        let mut copies = Vec::new();
+        for e in block.iter() {
+            let (from, to) = {
+                let edge = graph.edge_weight(EdgeIndex::new(*e)).unwrap().borrow();
+                (index[&edge.from], index[&edge.to])
+            };
+            let copy = Rc::new(RefCell::new(Edge::new(from.index(), to.index())));
+            sub.add_edge(from, to, copy.clone());
+            copies.push((*e, copy));
+        }
+        let has_return = !with_returns || digraph.return_edge.is_some_and(|r| blocks.edge_block[r] == Some(b));
+        if let (Some(entry), Some(exit), false) = (entries[b], exits[b], has_return) {
+            if entry != exit {
+                let (from, to) = (index[&exit], index[&entry]);
+                sub.add_edge(from, to, Rc::new(RefCell::new(Edge::new(from.index(), to.index()))));
+            }
+        }
+        let rev_order = dfs_tree(&mut sub);
+        cycle_equivalence(&mut sub, &rev_order);
+        let mut highest = 0;
+        for (e, copy) in copies {
+            let copy = copy.borrow();
+            let mut edge = graph.edge_weight(EdgeIndex::new(e)).unwrap().borrow_mut();
+            edge.class = copy.class + next_class - 1;
+            edge.is_tree_edge = copy.is_tree_edge;
+            edge.is_backedge = copy.is_backedge;
+            highest = highest.max(copy.class);
+        }
+        next_class += highest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::triconnected::is_biconnected;
+    use crate::test_utils::seeded_graphs;
+
+    // two diamonds joined by a bridge, with no return edge
+    fn make_bridged() -> FlowGraph {
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..8).map(|i| add_graph_node(&mut graph, i)).collect::<Vec<NodeIndex>>();
+        for (a, b) in [(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (4, 5), (4, 6), (5, 7), (6, 7)] {
+            add_graph_edge(&mut graph, nodes[a], nodes[b]);
+        }
+        graph
+    }
+
+    // depth, node ids, entry and exit of a region
+    type Shape = (usize, Vec<usize>, Option<usize>, Option<usize>);
+
+    // the regions in preorder
+    fn shape(tree: &StructureTree) -> Vec<Shape> {
+        let mut shapes = Vec::new();
+        let mut stack = vec![(tree.root.clone().unwrap(), 0)];
+        while let Some((region, depth)) = stack.pop() {
+            let region = region.borrow();
+            let mut ids = region.nodes.iter().map(|n| n.borrow().id).collect::<Vec<usize>>();
+            ids.sort();
+            shapes.push((depth, ids, region.entry.map(|e| e.index()), region.exit.map(|e| e.index())));
+            for child in region.children.iter().rev() {
+                stack.push((child.clone(), depth + 1));
+            }
+        }
+        shapes
+    }
+
+    #[test]
+    fn bridged_diamonds() {
+        let graph = make_bridged();
+        let blocks = Blocks::from_flow_graph(&graph);
+        assert_eq!(blocks.blocks.len(), 3);
+        assert_eq!(blocks.bridges, vec![4]);
+        assert_eq!(blocks.articulation_points, vec![3, 4]);
+        let tree = blocks.block_cut_tree();
+        assert_eq!(tree.vertices.len(), 5);
+        assert_eq!(tree.adjacency.iter().map(|a| a.len()).sum::<usize>(), 8);
+        let two_edge = TwoEdgeComponents::from_flow_graph(&graph);
+        assert_eq!(two_edge.components, vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]]);
+
+        // each branch of a diamond is a class, as it would be with a return edge across the diamond,
+        // and the bridge is a class of its own
+        let mut graph = make_bridged();
+        let tree = build_structure_tree_with(&mut graph, &StructureTreeOptions { per_block: true, ..Default::default() });
+        let classes = graph.edge_weights().map(|e| e.borrow().class).collect::<Vec<usize>>();
+        let mut distinct = classes.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 5);
+        for (a, b) in [(0, 2), (1, 3), (5, 7), (6, 8)] {
+            assert_eq!(classes[a], classes[b]);
+        }
+        assert!(graph.edge_weights().all(|e| !e.borrow().is_capping));
+        let shapes = shape(&tree);
+        assert_eq!(shapes[0].0, 0);
+        let branches = shapes[1..].iter().map(|s| (s.0, s.1.clone())).collect::<Vec<(usize, Vec<usize>)>>();
+        assert_eq!(branches, vec![(1, vec![1]), (1, vec![5]), (1, vec![6]), (1, vec![2])]);
+    }
+
+    #[test]
+    fn random_graphs() {
+        for graph in seeded_graphs(38, 12, 5).take(200) {
+            let digraph = DiGraph::from_flow_graph(&graph);
+            let blocks = Blocks::new(&digraph.edges);
+            assert!(blocks.bridges.is_empty());
+            assert_eq!(blocks.blocks.len() == 1, is_biconnected(&digraph.edges));
+            // the block-cut tree is a tree
+            let tree = blocks.block_cut_tree();
+            let links = tree.adjacency.iter().map(|a| a.len()).sum::<usize>() / 2;
+            assert_eq!(links + 1, tree.vertices.len());
+            for b in 0..blocks.blocks.len() {
+                let edges = digraph.edges.iter().enumerate()
+                    .map(|(e, ends)| if blocks.edge_block[e] == Some(b) { *ends } else { None })
+                    .collect::<Vec<Option<(usize, usize)>>>();
+                assert!(is_biconnected(&edges));
+            }
+            // cutting the graph into blocks leaves the regions as they were
+            let mut whole = graph.clone();
+            let expected = shape(&build_structure_tree(&mut whole));
+            let mut by_block = deep_copy(&graph);
+            let found = shape(&build_structure_tree_with(&mut by_block, &StructureTreeOptions { per_block: true, ..Default::default() }));
+            assert_eq!(found, expected);
+        }
+    }
+
+    fn deep_copy(graph: &FlowGraph) -> FlowGraph {
+        let mut copy = FlowGraph::new_undirected();
+        for n in graph.node_indices() {
+            add_graph_node(&mut copy, graph[n].borrow().id);
+        }
+        for e in graph.edge_weights() {
+            let e = e.borrow();
+            add_graph_edge(&mut copy, NodeIndex::new(e.from), NodeIndex::new(e.to));
+        }
+        copy
+    }
+}
@@ -0,0 +1,250 @@
+use petgraph::graph::NodeIndex;
+
+use crate::{FlowGraph, add_graph_node, add_graph_edge};
+use crate::blocks::Blocks;
+use crate::equivalence::CycleEquivalence;
+
+// 3-edge-connected components of a graph annotated by cycle_equivalence: two nodes are in the same
+// component when no one or two edges separate them
+// a pair of edges separates nodes exactly when the edges are cycle equivalent, and removing the k edges
+// of a class cuts the graph into k pieces strung around a cycle; so the endpoints of an edge alone in its
+// class are 3-edge-connected, and so are the two ends facing each other across each piece of a class,
+// found by walking one cycle through the class; bridges, in a class of their own when the classes were
+// found block by block, separate their endpoints
+#[derive(Clone,Debug)]
+pub struct ThreeEdgeComponents {
+    pub components: Vec<Vec<usize>>, // sorted node indexes, in order of their lowest node
+    pub node_component: Vec<usize>, // component of each node by node index
+}
+
+impl ThreeEdgeComponents {
+    pub fn new(graph: &FlowGraph) -> ThreeEdgeComponents {
+        let query = CycleEquivalence::new(graph);
+        let blocks = Blocks::from_flow_graph(graph);
+        let mut parent = (0..graph.node_count()).collect::<Vec<usize>>();
+        fn find(parent: &mut [usize], mut n: usize) -> usize {
+            while parent[n] != n {
+                parent[n] = parent[parent[n]];
+                n = parent[n];
+            }
+            n
+        }
+        let mut union = |a: NodeIndex, b: NodeIndex| {
+            let (a, b) = (find(&mut parent, a.index()), find(&mut parent, b.index()));
+            parent[a.max(b)] = a.min(b);
+        };
+        for class in query.class_ids() {
+            let members = query.members(class);
+            if members.len() == 1 {
+                if !blocks.is_bridge(members[0].index()) {
+                    let (a, b) = graph.edge_endpoints(members[0]).unwrap();
+                    union(a, b);
+                }
+                continue;
+            }
+            let cycle = match query.cycle_through(members[0], members[0]) {
+                Some(cycle) => cycle,
+                None => continue,
+            };
+            // tail and head of each class edge in the direction of the walk
+            let mut node = graph.edge_endpoints(members[0]).unwrap().0;
+            let mut ends = Vec::new();
+            for e in cycle.iter() {
+                let (a, b) = graph.edge_endpoints(*e).unwrap();
+                let next = if a == node { b } else { a };
+                if query.class(*e) == Some(class) {
+                    ends.push((node, next));
+                }
+                node = next;
+            }
+            for i in 0..ends.len() {
+                union(ends[i].1, ends[(i + 1) % ends.len()].0);
+            }
+        }
+        let mut node_component = vec![usize::MAX; graph.node_count()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for n in 0..graph.node_count() {
+            let root = find(&mut parent, n);
+            if node_component[root] == usize::MAX {
+                node_component[root] = components.len();
+                components.push(Vec::new());
+            }
+            node_component[n] = node_component[root];
+            components[node_component[n]].push(n);
+        }
+        ThreeEdgeComponents { components, node_component }
+    }
+}
+
+// an edge of the cactus, kept from the original graph
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct CactusEdge {
+    pub ends: (usize, usize), // cactus nodes, in the direction of the original edge
+    pub edge: usize, // original edge index
+    pub class: usize, // cycle equivalence class of the original edge
+}
+
+// the cactus graph: each 3-edge-connected component contracted to a node, keeping the edges between
+// components; each class of k edges becomes a cycle of length k and each bridge an edge on no cycle, so
+// every edge lies on at most one cycle
+#[derive(Clone,Debug)]
+pub struct Cactus {
+    pub nodes: Vec<Vec<usize>>, // original node indexes of each cactus node, sorted
+    pub edges: Vec<CactusEdge>, // in original edge index order
+    pub node_of: Vec<usize>, // cactus node of each original node by node index
+}
+
+impl Cactus {
+    // the cactus of a graph that has been run through cycle_equivalence; capping backedges are ignored
+    pub fn new(graph: &FlowGraph) -> Cactus {
+        let components = ThreeEdgeComponents::new(graph);
+        let mut edges = Vec::new();
+        for e in graph.edge_indices() {
+            let edge = graph[e].borrow();
+            if edge.is_capping {
+                continue;
+            }
+            let (a, b) = (components.node_component[edge.from], components.node_component[edge.to]);
+            if a != b {
+                edges.push(CactusEdge { ends: (a, b), edge: e.index(), class: edge.class });
+            }
+        }
+        Cactus { nodes: components.components, edges, node_of: components.node_component }
+    }
+
+    // the cycles of the cactus, as the indexes into edges of each, one per class of two or more edges
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let ends = self.edges.iter().map(|e| Some(e.ends)).collect::<Vec<Option<(usize, usize)>>>();
+        let blocks = Blocks::new(&ends);
+        blocks.blocks.into_iter().filter(|b| b.len() > 1).collect()
+    }
+
+    // the cactus as a flow graph, node ids being cactus node indexes and edges keeping their direction
+    pub fn to_flow_graph(&self) -> FlowGraph {
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..self.nodes.len()).map(|i| add_graph_node(&mut graph, i)).collect::<Vec<NodeIndex>>();
+        for e in self.edges.iter() {
+            add_graph_edge(&mut graph, nodes[e.ends.0], nodes[e.ends.1]);
+        }
+        graph
+    }
+
+    // the ids of the original nodes of a cactus node
+    fn ids(&self, graph: &FlowGraph, n: usize) -> Vec<String> {
+        self.nodes[n].iter().map(|m| graph[NodeIndex::new(*m)].borrow().id.to_string()).collect()
+    }
+
+    // the cactus in dot format, each node labelled with the ids of its original nodes and each edge
+    // with its class
+    pub fn to_dot(&self, graph: &FlowGraph) -> String {
+        let mut dot = String::new();
+        dot.push_str("graph {\n");
+        for n in 0..self.nodes.len() {
+            dot.push_str(format!("c{} [label=\"{}\"];\n", n, self.ids(graph, n).join(" ")).as_str());
+        }
+        for e in self.edges.iter() {
+            dot.push_str(format!("c{} -- c{} [label=\"{}\"];\n", e.ends.0, e.ends.1, e.class).as_str());
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the cactus in GFA 1.0, a segment without sequence per cactus node listing its original node ids
+    // in an ND tag, and a link per edge with its class in a CL tag
+    pub fn to_gfa(&self, graph: &FlowGraph) -> String {
+        let mut gfa = String::new();
+        gfa.push_str("H\tVN:Z:1.0\n");
+        for n in 0..self.nodes.len() {
+            gfa.push_str(format!("S\tc{}\t*\tND:Z:{}\n", n, self.ids(graph, n).join(",")).as_str());
+        }
+        for e in self.edges.iter() {
+            gfa.push_str(format!("L\tc{}\t+\tc{}\t+\t0M\tCL:i:{}\n", e.ends.0, e.ends.1, e.class).as_str());
+        }
+        gfa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::blocks::cycle_equivalence_by_block;
+    use petgraph::graph::EdgeIndex;
+    use crate::test_utils::{reachable_without, seeded_graphs};
+
+    // nodes are 3-edge-connected when no set of at most two edges separates them
+    fn check(graph: &FlowGraph) {
+        let components = ThreeEdgeComponents::new(graph);
+        let edges = graph.edge_indices().filter(|e| !graph[*e].borrow().is_capping).collect::<Vec<EdgeIndex>>();
+        for a in 0..graph.node_count() {
+            for b in a + 1..graph.node_count() {
+                let mut separated = !reachable_without(graph, NodeIndex::new(a), &[], false)[b];
+                for (i, e) in edges.iter().enumerate() {
+                    for f in edges[i..].iter() {
+                        separated = separated || !reachable_without(graph, NodeIndex::new(a), &[*e, *f], false)[b];
+                    }
+                }
+                assert_eq!(components.node_component[a] == components.node_component[b], !separated, "nodes {} {}", a, b);
+            }
+        }
+        // the cycles of the cactus are the classes of two or more edges
+        let cactus = Cactus::new(graph);
+        let query = CycleEquivalence::new(graph);
+        let mut expected = query.class_ids().into_iter()
+            .map(|c| query.members(c).iter().map(|e| e.index()).collect::<Vec<usize>>())
+            .filter(|m| m.len() > 1)
+            .collect::<Vec<Vec<usize>>>();
+        expected.sort();
+        let mut cycles = cactus.cycles().into_iter()
+            .map(|c| c.into_iter().map(|i| cactus.edges[i].edge).collect::<Vec<usize>>())
+            .collect::<Vec<Vec<usize>>>();
+        cycles.iter_mut().for_each(|c| c.sort());
+        cycles.sort();
+        assert_eq!(cycles, expected);
+    }
+
+    #[test]
+    fn parallel_paths() {
+        // three paths of two edges between 0 and 1 keep 0 and 1 together, while each middle node is
+        // cut off by its two edges
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..5).map(|i| add_graph_node(&mut graph, i)).collect::<Vec<NodeIndex>>();
+        for (a, b) in [(0, 2), (2, 1), (0, 3), (3, 1), (0, 4), (4, 1)] {
+            add_graph_edge(&mut graph, nodes[a], nodes[b]);
+        }
+        let rev_order = dfs_tree(&mut graph);
+        cycle_equivalence(&mut graph, &rev_order);
+        check(&graph);
+        let cactus = Cactus::new(&graph);
+        assert_eq!(cactus.nodes, vec![vec![0, 1], vec![2], vec![3], vec![4]]);
+        assert_eq!(cactus.cycles().len(), 3);
+        let gfa = cactus.to_gfa(&graph);
+        assert!(gfa.starts_with("H\tVN:Z:1.0\nS\tc0\t*\tND:Z:0,1\n"));
+        assert_eq!(gfa.lines().filter(|l| l.starts_with('L')).count(), 6);
+    }
+
+    #[test]
+    fn bridges() {
+        // two triangles joined by a bridge
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..6).map(|i| add_graph_node(&mut graph, i)).collect::<Vec<NodeIndex>>();
+        for (a, b) in [(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 5), (5, 3)] {
+            add_graph_edge(&mut graph, nodes[a], nodes[b]);
+        }
+        cycle_equivalence_by_block(&mut graph);
+        check(&graph);
+        let cactus = Cactus::new(&graph);
+        assert_eq!(cactus.nodes.len(), 6);
+        assert_eq!(cactus.cycles().len(), 2);
+        assert_eq!(cactus.to_flow_graph().edge_count(), 7);
+    }
+
+    #[test]
+    fn random_graphs() {
+        for mut graph in seeded_graphs(39, 9, 5).take(100) {
+            let rev_order = dfs_tree(&mut graph);
+            cycle_equivalence(&mut graph, &rev_order);
+            check(&graph);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::{FlowGraph, Granularity, SeSeRegion, StructureTree};
+
+// the edges of one cycle equivalence class in dominance order, e1 < e2 < ... < ek
+// each adjacent pair (e_i, e_i+1) bounds a canonical SESE region, and the regions
+// between them are siblings in the structure tree that together form a chain
+#[derive(Debug)]
+pub struct ClassChain {
+    pub class: usize,
+    pub edges: Vec<EdgeIndex>,
+    // the region of the structure tree bounded by each adjacent pair of edges, None if the tree has no such region
+    pub regions: Vec<Option<Rc<RefCell<SeSeRegion>>>>,
+}
+
+impl ClassChain {
+    // the (entry, exit) edge pairs bounding the canonical regions of the chain
+    pub fn pairs(&self) -> Vec<(EdgeIndex, EdgeIndex)> {
+        self.edges.windows(2).map(|w| (w[0], w[1])).collect()
+    }
+
+    // a chain needs at least two edges to bound a region
+    pub fn is_trivial(&self) -> bool {
+        self.edges.len() < 2
+    }
+}
+
+// order the edges of every class by dominance
+// edges are taken in directed depth first order from the source node (index 0), following edges from `from` to `to`
+// if e1 dominates e2 then the tail of e2 can only be reached through e1, so e1 is always examined first
+// edges not reachable from the source keep their edge index order at the end of their class
+pub fn order_class_edges(graph: &FlowGraph) -> HashMap<usize, Vec<EdgeIndex>> {
+    let mut order = Vec::with_capacity(graph.edge_count());
+    let mut examined = vec![false; graph.edge_count()];
+    let mut visited = vec![false; graph.node_count()];
+    if graph.node_count() > 0 {
+        let out_edges = |n: NodeIndex| {
+            let mut out = graph.edges(n)
+                .filter(|e| {
+                    let edge = e.weight().borrow();
+                    !edge.is_capping && edge.from == n.index()
+                })
+                .map(|e| e.id())
+                .collect::<Vec<EdgeIndex>>();
+            out.sort_unstable();
+            out.dedup();
+            out
+        };
+        let source = NodeIndex::new(0);
+        visited[0] = true;
+        // stack of (node, its outgoing edges, position of the next edge to examine)
+        let mut stack = vec![(source, out_edges(source), 0)];
+        while let Some((_, out, i)) = stack.last_mut() {
+            if *i == out.len() {
+                stack.pop();
+                continue;
+            }
+            let e = out[*i];
+            *i += 1;
+            if examined[e.index()] {
+                continue;
+            }
+            examined[e.index()] = true;
+            order.push(e);
+            let to = NodeIndex::new(graph[e].borrow().to);
+            if !visited[to.index()] {
+                visited[to.index()] = true;
+                stack.push((to, out_edges(to), 0));
+            }
+        }
+    }
+    for e in graph.edge_indices() {
+        if !examined[e.index()] && !graph[e].borrow().is_capping {
+            order.push(e);
+        }
+    }
+    let mut classes = HashMap::<usize, Vec<EdgeIndex>>::new();
+    for e in order {
+        classes.entry(graph[e].borrow().class).or_default().push(e);
+    }
+    classes
+}
+
+// compute the ordered chain of every class and link each adjacent pair to its region in the tree
+// the graph must be the one the tree was built from, so that edge indices agree
+pub fn class_chains(graph: &FlowGraph, tree: &StructureTree) -> Vec<ClassChain> {
+    // regions by their unordered pair of boundary edges, so that the lookup does not depend on how they were recorded
+    let mut by_boundary = HashMap::<(EdgeIndex, EdgeIndex), Rc<RefCell<SeSeRegion>>>::new();
+    for region in tree.regions() {
+        let (entry, exit) = (region.borrow().entry, region.borrow().exit);
+        if let (Some(a), Some(b)) = (entry, exit) {
+            by_boundary.insert((a.min(b), a.max(b)), region.clone());
+        }
+    }
+    let mut chains = order_class_edges(graph)
+        .into_iter()
+        .map(|(class, edges)| {
+            let regions = edges.windows(2)
+                .map(|w| by_boundary.get(&(w[0].min(w[1]), w[0].max(w[1]))).cloned())
+                .collect();
+            ClassChain { class, edges, regions }
+        })
+        .collect::<Vec<ClassChain>>();
+    chains.sort_by_key(|c| c.class);
+    chains
+}
+
+// regroup the canonical regions of the tree by chain, for a granularity other than Canonical
+// the regions of a chain are siblings, each one's exit edge being the next one's entry edge; a run of two
+// or more is put under a new region from the first entry to the last exit, marked as not canonical, which
+// either holds the run as its children (Sequential) or takes over their nodes and children (Maximal)
+// new regions are numbered from next_id
+pub fn compose_chains(tree: &StructureTree, granularity: Granularity, mut next_id: usize) {
+    if granularity == Granularity::Canonical {
+        return;
+    }
+    for parent in tree.regions() {
+        let children = parent.borrow().children.clone();
+        let mut by_entry = HashMap::<EdgeIndex, Rc<RefCell<SeSeRegion>>>::new();
+        let mut exits = HashMap::<EdgeIndex, usize>::new();
+        for child in children.iter() {
+            let child_ = child.borrow();
+            if let (Some(entry), Some(exit)) = (child_.entry, child_.exit) {
+                by_entry.insert(entry, child.clone());
+                exits.insert(exit, child_.class);
+            }
+        }
+        // runs start at a region whose entry is not the exit of a sibling of the same class
+        let mut runs = Vec::new();
+        for child in children.iter() {
+            let (class, entry) = (child.borrow().class, child.borrow().entry);
+            let entry = match entry {
+                Some(e) => e,
+                None => continue,
+            };
+            if exits.get(&entry) == Some(&class) {
+                continue;
+            }
+            let mut run = vec![child.clone()];
+            loop {
+                let exit = run.last().unwrap().borrow().exit;
+                let next = match exit.and_then(|e| by_entry.get(&e)) {
+                    Some(next) if next.borrow().class == class && !run.iter().any(|r| Rc::ptr_eq(r, next)) => next.clone(),
+                    _ => break,
+                };
+                run.push(next);
+            }
+            if run.len() > 1 {
+                runs.push(run);
+            }
+        }
+        for run in runs {
+            let class = run[0].borrow().class;
+            let composite = Rc::new(RefCell::new(SeSeRegion::new(next_id, class)));
+            next_id += 1;
+            {
+                let mut c = composite.borrow_mut();
+                c.canonical = false;
+                c.entry = run[0].borrow().entry;
+                c.exit = run.last().unwrap().borrow().exit;
+                c.parent = Some(parent.clone());
+                for member in run.iter() {
+                    if granularity == Granularity::Maximal {
+                        let mut member = member.borrow_mut();
+                        c.nodes.append(&mut member.nodes);
+                        for child in member.children.drain(..) {
+                            child.borrow_mut().parent = Some(composite.clone());
+                            c.children.push(child);
+                        }
+                    } else {
+                        member.borrow_mut().parent = Some(composite.clone());
+                        c.children.push(member.clone());
+                    }
+                }
+            }
+            let mut parent_ = parent.borrow_mut();
+            let at = parent_.children.iter().position(|r| Rc::ptr_eq(r, &run[0])).unwrap();
+            parent_.children[at] = composite;
+            parent_.children.retain(|r| !run[1..].iter().any(|m| Rc::ptr_eq(r, m)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::regions::RegionTable;
+    use crate::test_utils::{reachable_without, seeded_graphs, tree_with};
+
+    // node ids of a region and everything below it, sorted
+    fn all_ids(region: &Rc<RefCell<SeSeRegion>>) -> Vec<usize> {
+        let mut ids = region.borrow().nodes.iter().map(|n| n.borrow().id).collect::<Vec<usize>>();
+        for child in region.borrow().children.iter() {
+            ids.extend(all_ids(child));
+        }
+        ids.sort();
+        ids
+    }
+
+    // every region lists its parent, and the nodes of the graph are each in exactly one region
+    fn check(graph: &FlowGraph, tree: &StructureTree) {
+        for region in tree.regions() {
+            for child in region.borrow().children.iter() {
+                assert!(Rc::ptr_eq(child.borrow().parent.as_ref().unwrap(), &region));
+            }
+        }
+        let mut ids = graph.node_indices().map(|n| graph[n].borrow().id).collect::<Vec<usize>>();
+        ids.sort();
+        assert_eq!(all_ids(tree.root.as_ref().unwrap()), ids);
+        RegionTable::new(graph, tree).unwrap();
+    }
+
+    fn graph_of(n: usize, edges: &[(usize, usize)]) -> FlowGraph {
+        let mut graph = FlowGraph::new_undirected();
+        for id in 0..n {
+            add_graph_node(&mut graph, id);
+        }
+        for (from, to) in edges.iter() {
+            add_graph_edge(&mut graph, NodeIndex::new(*from), NodeIndex::new(*to));
+        }
+        graph
+    }
+
+    // every edge of a class dominates the edges after it, and every adjacent pair bounds the region recorded for it
+    fn check_chains(graph: &FlowGraph, tree: &StructureTree) {
+        let chains = class_chains(graph, tree);
+        for chain in chains.iter() {
+            assert!(chain.edges.iter().all(|e| graph[*e].borrow().class == chain.class));
+            for (i, a) in chain.edges.iter().enumerate() {
+                for b in chain.edges[i + 1..].iter() {
+                    assert!(!reachable_without(graph, NodeIndex::new(0), &[*a], true)[graph[*b].borrow().from], "{:?} does not dominate {:?}", a, b);
+                }
+            }
+            for ((entry, exit), region) in chain.pairs().into_iter().zip(chain.regions.iter()) {
+                let region = region.as_ref().unwrap().borrow();
+                assert_eq!((region.entry, region.exit), (Some(entry), Some(exit)));
+                assert_eq!(region.class, chain.class);
+            }
+        }
+        let pairs = chains.iter().map(|c| c.pairs().len()).sum::<usize>();
+        assert_eq!(pairs + 1, tree.regions().len());
+    }
+
+    #[test]
+    fn ordered_classes() {
+        let mut graph = make_example_fig1();
+        let tree = tree_with(&mut graph, Granularity::Canonical);
+        check_chains(&graph, &tree);
+        for mut graph in seeded_graphs(27, 10, 6).take(50) {
+            let tree = tree_with(&mut graph, Granularity::Canonical);
+            check_chains(&graph, &tree);
+        }
+    }
+
+    #[test]
+    fn sequence() {
+        // s -> a -> b -> t -> s: one class, whose regions follow each other with no node between their boundaries
+        let mut graph = graph_of(4, &[(0, 1), (1, 2), (2, 3), (3, 0)]);
+        let tree = tree_with(&mut graph, Granularity::Canonical);
+        check_chains(&graph, &tree);
+        let chains = class_chains(&graph, &tree);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].edges, (0..4).map(EdgeIndex::new).collect::<Vec<EdgeIndex>>());
+        let root = tree.root.as_ref().unwrap();
+        assert_eq!(root.borrow().children.len(), 3);
+        for (child, id) in root.borrow().children.iter().zip(1..) {
+            assert_eq!(all_ids(child), vec![id]);
+        }
+    }
+
+    #[test]
+    fn parallel_edges() {
+        // s -> a -> b -> a -> t -> s: a -> b and b -> a join the same two nodes, and only their indexes tell them apart
+        let mut graph = graph_of(4, &[(0, 1), (1, 2), (2, 1), (1, 3), (3, 0)]);
+        let tree = tree_with(&mut graph, Granularity::Canonical);
+        check_chains(&graph, &tree);
+        let chain = class_chains(&graph, &tree).into_iter().find(|c| c.edges.contains(&EdgeIndex::new(1))).unwrap();
+        assert_eq!(chain.edges, vec![EdgeIndex::new(1), EdgeIndex::new(2)]);
+        let region = chain.regions[0].as_ref().unwrap();
+        assert_eq!(all_ids(region), vec![2]);
+    }
+
+    #[test]
+    fn fig1() {
+        let canonical = tree_with(&mut make_example_fig1(), Granularity::Canonical);
+        let mut graph = make_example_fig1();
+        let sequential = tree_with(&mut graph, Granularity::Sequential);
+        check(&graph, &sequential);
+        let mut graph = make_example_fig1();
+        let maximal = tree_with(&mut graph, Granularity::Maximal);
+        check(&graph, &maximal);
+
+        let count = |tree: &StructureTree, canonical: bool| tree.regions().iter().filter(|r| r.borrow().canonical == canonical).count();
+        let composites = count(&sequential, false);
+        assert!(composites > 0);
+        assert_eq!(count(&canonical, false), 0);
+        assert_eq!(count(&sequential, true), canonical.regions().len());
+        assert_eq!(count(&maximal, false), composites);
+        // a composite in the sequential tree holds the run of canonical regions it replaces
+        for region in sequential.regions().iter().filter(|r| !r.borrow().canonical) {
+            let region = region.borrow();
+            assert!(region.nodes.is_empty() && region.children.len() > 1);
+            assert!(region.children.iter().all(|c| c.borrow().class == region.class && c.borrow().canonical));
+            assert_eq!(region.entry, region.children[0].borrow().entry);
+            assert_eq!(region.exit, region.children.last().unwrap().borrow().exit);
+        }
+        // no two sibling regions of one class are left in the maximal tree
+        for region in maximal.regions() {
+            let mut classes = region.borrow().children.iter().map(|c| c.borrow().class).collect::<Vec<usize>>();
+            let count = classes.len();
+            classes.sort();
+            classes.dedup();
+            assert_eq!(classes.len(), count);
+        }
+    }
+
+    #[test]
+    fn random_graphs() {
+        for graph in seeded_graphs(41, 12, 6).take(100) {
+            for granularity in [Granularity::Sequential, Granularity::Maximal] {
+                let mut copy = FlowGraph::new_undirected();
+                for n in graph.node_indices() {
+                    add_graph_node(&mut copy, graph[n].borrow().id);
+                }
+                for e in graph.edge_weights() {
+                    let e = e.borrow();
+                    add_graph_edge(&mut copy, NodeIndex::new(e.from), NodeIndex::new(e.to));
+                }
+                let tree = tree_with(&mut copy, granularity);
+                check(&copy, &tree);
+            }
+        }
+    }
+}
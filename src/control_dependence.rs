@@ -0,0 +1,166 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use crate::{FlowGraph, StructureTree};
+use crate::digraph::DiGraph;
+use crate::dominators::{CfgDominance, postdominators};
+use crate::regions::{LocalEdgeKind, RegionTable};
+
+// control dependences of every node of a flow graph
+// a node is control dependent on edge a->b when it postdominates b but does not strictly postdominate a
+// nodes that run whenever the graph runs depend on no edge
+#[derive(Clone,Debug,PartialEq)]
+pub struct ControlDependence {
+    pub dependences: Vec<Vec<usize>>, // sorted edge indexes for each node index
+}
+
+impl ControlDependence {
+    fn from_lists(mut dependences: Vec<Vec<usize>>) -> ControlDependence {
+        for d in dependences.iter_mut() {
+            d.sort_unstable();
+            d.dedup();
+        }
+        ControlDependence { dependences }
+    }
+
+    // the control dependence graph in dot format, with an edge from each branch node to the nodes
+    // that depend on one of its outgoing edges, labelled with that edge's head
+    pub fn to_dot(&self, graph: &FlowGraph) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut dot = String::new();
+        dot.push_str("digraph {\n");
+        for n in graph.node_indices() {
+            dot.push_str(format!("n{} [label=\"{}\"];\n", id(n.index()), id(n.index())).as_str());
+        }
+        for (n, deps) in self.dependences.iter().enumerate() {
+            for e in deps.iter() {
+                let edge = graph[EdgeIndex::new(*e)].borrow();
+                dot.push_str(format!("n{} -> n{} [label=\"{}->{}\"];\n", id(edge.from), id(n), id(edge.from), id(edge.to)).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the control dependences as json, one entry per node with the edges it depends on
+    pub fn to_json(&self, graph: &FlowGraph) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut nodes = Vec::new();
+        for (n, deps) in self.dependences.iter().enumerate() {
+            let edges = deps.iter()
+                .map(|e| {
+                    let edge = graph[EdgeIndex::new(*e)].borrow();
+                    format!("{{\"edge\":{},\"from\":{},\"to\":{}}}", e, id(edge.from), id(edge.to))
+                })
+                .collect::<Vec<String>>();
+            nodes.push(format!("{{\"id\":{},\"index\":{},\"control_dependences\":[{}]}}", id(n), n, edges.join(",")));
+        }
+        format!("{{\"nodes\":[{}]}}\n", nodes.join(","))
+    }
+}
+
+// conventional control dependence from postdominance frontiers
+// a node depends on the edges m->s out of each m in its postdominance frontier for which it postdominates s
+pub fn control_dependence_by_frontiers(graph: &FlowGraph) -> ControlDependence {
+    let analysis = CfgDominance::new(graph);
+    let digraph = &analysis.digraph;
+    let mut dependences = vec![Vec::new(); digraph.node_count()];
+    for (n, frontier) in analysis.postdominance_frontiers.iter().enumerate() {
+        for m in frontier.iter() {
+            for e in digraph.out_edges[*m].iter() {
+                if digraph.is_return_edge(*e) {
+                    continue;
+                }
+                let (_, s) = digraph.endpoints(*e).unwrap();
+                if analysis.postdominates(n, s) {
+                    dependences[n].push(*e);
+                }
+            }
+        }
+    }
+    ControlDependence::from_lists(dependences)
+}
+
+// control dependence computed region by region over the program structure tree
+// each region is reduced to its local graph, with child regions collapsed to single vertices and
+// a start to end bypass standing for the region not being entered
+// dependences found on that small graph are exact for the region's own nodes and child vertices,
+// and whatever depends on the entry itself gets the dependences of the region from its parent
+pub fn control_dependence(graph: &FlowGraph, tree: &StructureTree) -> Result<ControlDependence, String> {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let table = RegionTable::new(graph, tree)?;
+    let mut dependences = vec![Vec::new(); digraph.node_count()];
+    let mut inherited = vec![Vec::<usize>::new(); table.len()];
+    for r in 0..table.len() {
+        let local = table.local_graph(&digraph, r);
+        let pdom = postdominators(&local.successors(true), local.end);
+        let mut local_deps = vec![Vec::new(); local.vertex_count()];
+        let mut inherits = vec![false; local.vertex_count()];
+        for edge in local.edges.iter() {
+            let (x, y) = (edge.from, edge.to);
+            if !pdom.is_reachable(x) || pdom.dominates(y, x) {
+                continue;
+            }
+            // walk up the postdominator tree from y to the immediate postdominator of x
+            let stop = pdom.idom(x);
+            let mut w = Some(y);
+            while let Some(v) = w {
+                if Some(v) == stop {
+                    break;
+                }
+                match edge.kind {
+                    LocalEdgeKind::Edge(e) | LocalEdgeKind::Exit(Some(e)) => local_deps[v].push(e),
+                    LocalEdgeKind::Entry(_) => inherits[v] = true,
+                    LocalEdgeKind::Exit(None) | LocalEdgeKind::Bypass => {}
+                }
+                w = pdom.idom(v);
+            }
+        }
+        let region_deps = inherited[r].clone();
+        let resolve = |v: usize| {
+            let mut deps = local_deps[v].clone();
+            if inherits[v] {
+                deps.extend(region_deps.iter().cloned());
+            }
+            deps
+        };
+        for (i, n) in local.nodes.iter().enumerate() {
+            dependences[*n] = resolve(i);
+        }
+        for (i, c) in local.children.iter().enumerate() {
+            inherited[*c] = resolve(local.child_vertex(i));
+        }
+    }
+    Ok(ControlDependence::from_lists(dependences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::examples;
+
+    #[test]
+    fn structure_tree_matches_frontiers() {
+        for mut graph in examples() {
+            let tree = build_structure_tree(&mut graph);
+            assert_eq!(control_dependence(&graph, &tree).unwrap(), control_dependence_by_frontiers(&graph));
+        }
+    }
+
+    #[test]
+    fn fig1_dependences() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let cd = control_dependence(&graph, &tree).unwrap();
+        let edge = |a: usize, b: usize| graph.find_edge(NodeIndex::new(a), NodeIndex::new(b)).unwrap().index();
+        // 0, 15 and 4 run whenever the graph runs or 0 takes the 0->2 branch
+        assert!(cd.dependences[0].is_empty());
+        assert!(cd.dependences[15].is_empty());
+        assert_eq!(cd.dependences[4], vec![edge(0, 2)]);
+        // 13 runs on the 0->1 branch
+        assert_eq!(cd.dependences[13], vec![edge(0, 1)]);
+        // 10 runs after 4->6 and after 8->10
+        assert_eq!(cd.dependences[10], vec![edge(4, 6), edge(8, 10)]);
+        assert!(cd.to_json(&graph).contains("\"id\":10,\"index\":10"));
+    }
+}
@@ -0,0 +1,173 @@
+use petgraph::graph::EdgeIndex;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::{FlowGraph, SeSeRegion, StructureTree};
+use crate::blocks::Blocks;
+use crate::chains::{ClassChain, class_chains};
+
+// all minimal 2-edge cuts of a graph annotated by cycle_equivalence, kept compactly as class chains:
+// two distinct edges form a cut exactly when they are in the same class, so a class of k edges holds
+// k(k-1)/2 cuts, and the cut (e_i, e_j) with i < j encloses the canonical regions of the pairs between
+// them; a bridge is a cut on its own and with any other edge, and is not paired up here
+#[derive(Debug)]
+pub struct TwoEdgeCuts {
+    pub chains: Vec<ClassChain>, // classes of two or more edges in dominance order, by class number
+    // for each chain, the chain of the innermost cut enclosing it, None when only the graph encloses it
+    pub parents: Vec<Option<usize>>,
+    pub bridges: Vec<EdgeIndex>,
+    position: HashMap<EdgeIndex, (usize, usize)>, // chain and position in it of each edge in a chain
+}
+
+impl TwoEdgeCuts {
+    // the graph must be the one the tree was built from, so that edge indices agree
+    pub fn new(graph: &FlowGraph, tree: &StructureTree) -> TwoEdgeCuts {
+        let chains = class_chains(graph, tree).into_iter().filter(|c| !c.is_trivial()).collect::<Vec<ClassChain>>();
+        let mut position = HashMap::new();
+        let mut by_class = HashMap::new();
+        for (c, chain) in chains.iter().enumerate() {
+            by_class.insert(chain.class, c);
+            for (i, e) in chain.edges.iter().enumerate() {
+                position.insert(*e, (c, i));
+            }
+        }
+        // the regions of a chain are siblings, so the parent of any of them is the region enclosing the chain
+        let parents = chains.iter()
+            .map(|chain| {
+                let region = chain.regions.iter().flatten().next()?;
+                let parent = region.borrow().parent.clone()?;
+                let class = parent.borrow().class;
+                by_class.get(&class).cloned()
+            })
+            .collect();
+        let bridges = Blocks::from_flow_graph(graph).bridges.into_iter().map(EdgeIndex::new).collect();
+        TwoEdgeCuts { chains, parents, bridges, position }
+    }
+
+    // the number of cuts, which can be quadratic in the number of edges
+    pub fn count(&self) -> usize {
+        self.chains.iter().map(|c| c.edges.len() * (c.edges.len() - 1) / 2).sum()
+    }
+
+    // every cut, class by class, each pair in dominance order
+    pub fn cuts(&self) -> impl Iterator<Item = (EdgeIndex, EdgeIndex)> + '_ {
+        self.chains.iter().flat_map(|chain| {
+            (0..chain.edges.len()).flat_map(move |i| (i + 1..chain.edges.len()).map(move |j| (chain.edges[i], chain.edges[j])))
+        })
+    }
+
+    // do the two edges together disconnect the graph, with neither doing so alone?
+    pub fn is_cut(&self, a: EdgeIndex, b: EdgeIndex) -> bool {
+        match (self.position.get(&a), self.position.get(&b)) {
+            (Some((c, i)), Some((d, j))) => c == d && i != j,
+            _ => false,
+        }
+    }
+
+    // the chain holding an edge, if it is in any cut
+    pub fn chain_of(&self, e: EdgeIndex) -> Option<&ClassChain> {
+        self.position.get(&e).map(|(c, _)| &self.chains[*c])
+    }
+
+    // the canonical regions enclosed by a cut, in order; together they form the SESE region bounded by
+    // the two edges, which is canonical only when the edges are adjacent in their chain
+    pub fn regions_between(&self, a: EdgeIndex, b: EdgeIndex) -> Option<Vec<Rc<RefCell<SeSeRegion>>>> {
+        if !self.is_cut(a, b) {
+            return None;
+        }
+        let (c, i) = self.position[&a];
+        let (_, j) = self.position[&b];
+        let (i, j) = (i.min(j), i.max(j));
+        self.chains[c].regions[i..j].iter().cloned().collect()
+    }
+
+    // does the cut (a, b) enclose the cut (c, d)? cuts of one class nest by position in the chain, and a
+    // chain inside one region of another chain is enclosed by each cut of that chain around the region
+    pub fn encloses(&self, (a, b): (EdgeIndex, EdgeIndex), (c, d): (EdgeIndex, EdgeIndex)) -> bool {
+        if !self.is_cut(a, b) || !self.is_cut(c, d) {
+            return false;
+        }
+        let ((outer, i), (_, j)) = (self.position[&a], self.position[&b]);
+        let ((inner, k), (_, l)) = (self.position[&c], self.position[&d]);
+        let (i, j) = (i.min(j), i.max(j));
+        let (k, l) = (k.min(l), k.max(l));
+        if outer == inner {
+            return i <= k && l <= j && (i, j) != (k, l);
+        }
+        // climb from the inner chain to a chain directly inside the outer one, then find its region
+        let mut chain = inner;
+        while let Some(parent) = self.parents[chain] {
+            if parent == outer {
+                let region = match self.chains[chain].regions.iter().flatten().next() {
+                    Some(r) => r.borrow().parent.clone(),
+                    None => return false,
+                };
+                let region = match region {
+                    Some(r) => r,
+                    None => return false,
+                };
+                return self.chains[outer].regions[i..j].iter().flatten().any(|r| Rc::ptr_eq(r, &region));
+            }
+            chain = parent;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::{reachable_without, seeded_graphs};
+
+    #[test]
+    fn fig1() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let cuts = TwoEdgeCuts::new(&graph, &tree);
+        assert_eq!(cuts.cuts().count(), cuts.count());
+        assert!(cuts.bridges.is_empty());
+        // a cut between the ends of a chain encloses every region of the chain
+        for chain in cuts.chains.iter() {
+            let (first, last) = (chain.edges[0], *chain.edges.last().unwrap());
+            let regions = cuts.regions_between(first, last).unwrap();
+            assert_eq!(regions.len(), chain.edges.len() - 1);
+            for pair in chain.pairs() {
+                assert!(cuts.encloses((first, last), pair) || chain.edges.len() == 2);
+            }
+        }
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut nested = 0;
+        for mut graph in seeded_graphs(40, 10, 5).take(100) {
+            let tree = build_structure_tree(&mut graph);
+            let cuts = TwoEdgeCuts::new(&graph, &tree);
+            let edges = graph.edge_indices().filter(|e| !graph[*e].borrow().is_capping).collect::<Vec<EdgeIndex>>();
+            let mut found = 0;
+            for (i, a) in edges.iter().enumerate() {
+                for b in edges[i + 1..].iter() {
+                    let cut = reachable_without(&graph, NodeIndex::new(0), &[*a, *b], false).contains(&false);
+                    assert_eq!(cuts.is_cut(*a, *b), cut);
+                    found += cut as usize;
+                }
+            }
+            assert_eq!(found, cuts.count());
+            // every cut encloses the cuts of the chains nested inside its regions
+            for (c, parent) in cuts.parents.iter().enumerate() {
+                if let Some(p) = parent {
+                    nested += 1;
+                    let outer = &cuts.chains[*p];
+                    let inner = &cuts.chains[c];
+                    let whole = (outer.edges[0], *outer.edges.last().unwrap());
+                    assert!(cuts.encloses(whole, (inner.edges[0], inner.edges[1])));
+                    assert!(!cuts.encloses((inner.edges[0], inner.edges[1]), whole));
+                }
+            }
+        }
+        assert!(nested > 0);
+    }
+}
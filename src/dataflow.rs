@@ -0,0 +1,424 @@
+use std::collections::BTreeSet;
+
+use crate::{FlowGraph, StructureTree};
+use crate::digraph::DiGraph;
+use crate::dominators::is_reducible;
+use crate::regions::{LocalGraph, RegionTable};
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+// a dataflow problem over the nodes of a flow graph
+// values form a join semilattice and each node has a monotone transfer function
+// the region solver also needs the transfer functions themselves to compose and join,
+// so that a whole region can be summarized by one of them
+pub trait DataflowProblem {
+    type Value: Clone + PartialEq;
+    type Transfer: Clone + PartialEq;
+
+    fn direction(&self) -> Direction;
+    fn bottom(&self) -> Self::Value;
+    // value flowing into the graph, at the source going forward and at the sink going backward
+    fn boundary(&self) -> Self::Value;
+    fn join(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    fn transfer(&self, node: usize) -> Self::Transfer;
+    fn apply(&self, f: &Self::Transfer, value: &Self::Value) -> Self::Value;
+    fn identity(&self) -> Self::Transfer;
+    // f followed by g
+    fn compose(&self, f: &Self::Transfer, g: &Self::Transfer) -> Self::Transfer;
+    // the function mapping each value to the join of what f and g map it to
+    fn join_transfers(&self, f: &Self::Transfer, g: &Self::Transfer) -> Self::Transfer;
+}
+
+// solution of a problem, indexed by node index
+// inputs are the values before each node's transfer function and outputs the values after it,
+// in the direction of the problem, so for a backward problem the input is the value at the node's end
+#[derive(Clone,Debug,PartialEq)]
+pub struct DataflowResult<V> {
+    pub inputs: Vec<V>,
+    pub outputs: Vec<V>,
+}
+
+// round robin worklist over a small graph, generic over what is propagated so that it serves both
+// for values and for transfer functions
+// start receives init besides whatever flows into it; every vertex is stepped at least once
+fn solve<T: Clone + PartialEq>(
+    succs: &[Vec<usize>],
+    start: usize,
+    init: T,
+    bottom: &T,
+    join: impl Fn(&T, &T) -> T,
+    mut step: impl FnMut(usize, &T) -> T,
+) -> (Vec<T>, Vec<T>) {
+    let n = succs.len();
+    let mut preds = vec![Vec::new(); n];
+    for (v, ws) in succs.iter().enumerate() {
+        for w in ws.iter() {
+            preds[*w].push(v);
+        }
+    }
+    let mut inputs = vec![bottom.clone(); n];
+    let mut outputs = vec![bottom.clone(); n];
+    let mut queued = vec![true; n];
+    let mut worklist = (0..n).rev().collect::<Vec<usize>>();
+    let mut stepped = vec![false; n];
+    while let Some(v) = worklist.pop() {
+        queued[v] = false;
+        let mut input = if v == start { init.clone() } else { bottom.clone() };
+        for p in preds[v].iter() {
+            input = join(&input, &outputs[*p]);
+        }
+        let output = step(v, &input);
+        inputs[v] = input;
+        if stepped[v] && output == outputs[v] {
+            continue;
+        }
+        stepped[v] = true;
+        outputs[v] = output;
+        for w in succs[v].iter() {
+            if !queued[*w] {
+                queued[*w] = true;
+                worklist.push(*w);
+            }
+        }
+    }
+    (inputs, outputs)
+}
+
+// the plain iterative solver over the whole graph, for comparison with the region solver
+pub fn solve_worklist<P: DataflowProblem>(graph: &FlowGraph, problem: &P) -> DataflowResult<P::Value> {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let (succs, start) = match problem.direction() {
+        Direction::Forward => (digraph.successor_lists(), digraph.entry),
+        Direction::Backward => (digraph.predecessor_lists(), digraph.exit),
+    };
+    let (inputs, outputs) = solve(&succs, start, problem.boundary(), &problem.bottom(),
+                                  |a, b| problem.join(a, b),
+                                  |v, x| problem.apply(&problem.transfer(v), x));
+    DataflowResult { inputs, outputs }
+}
+
+// how a region is summarized for its parent
+#[derive(Clone,Debug)]
+enum Summary<T> {
+    Function(Option<T>), // the transfer function from entry to exit, None when the exit is never reached
+    Worklist, // unstructured, solved again from the actual entry value whenever the parent asks
+}
+
+// a region's local graph laid out in the direction of the problem
+struct LocalProblem {
+    local: LocalGraph,
+    succs: Vec<Vec<usize>>,
+    start: usize,
+    end: usize,
+}
+
+// elimination style solver over the program structure tree
+// bottom up, each structured region is summarized into one transfer function by iterating over its
+// local graph in the space of functions, with child regions standing in as their summaries
+// top down, the entry value of each region is pushed through its local graph to its own nodes and to
+// the entries of its children
+// regions with an irreducible local graph, or with a child that has no function summary, are marked
+// unstructured and fall back to a worklist over their local graph whenever a value reaches them
+pub struct RegionSolver<'a, P: DataflowProblem> {
+    problem: &'a P,
+    pub table: RegionTable,
+    locals: Vec<LocalProblem>,
+    summaries: Vec<Summary<P::Transfer>>,
+}
+
+impl<'a, P: DataflowProblem> RegionSolver<'a, P> {
+    pub fn new(graph: &FlowGraph, tree: &StructureTree, problem: &'a P) -> Result<RegionSolver<'a, P>, String> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let table = RegionTable::new(graph, tree)?;
+        let locals = (0..table.len())
+            .map(|r| {
+                let local = table.local_graph(&digraph, r);
+                let forward = local.successors(false);
+                match problem.direction() {
+                    Direction::Forward => LocalProblem { start: local.start, end: local.end, succs: forward, local },
+                    Direction::Backward => {
+                        let mut succs = vec![Vec::new(); forward.len()];
+                        for (v, ws) in forward.iter().enumerate() {
+                            for w in ws.iter() {
+                                succs[*w].push(v);
+                            }
+                        }
+                        LocalProblem { start: local.end, end: local.start, succs, local }
+                    }
+                }
+            })
+            .collect::<Vec<LocalProblem>>();
+        let mut solver = RegionSolver { problem, table, locals, summaries: Vec::new() };
+        solver.summaries = vec![Summary::Worklist; solver.table.len()];
+        for r in (0..solver.table.len()).rev() {
+            solver.summaries[r] = solver.summarize(r);
+        }
+        Ok(solver)
+    }
+
+    // table indexes of the regions solved by worklist
+    pub fn unstructured(&self) -> Vec<usize> {
+        (0..self.summaries.len()).filter(|r| matches!(self.summaries[*r], Summary::Worklist)).collect()
+    }
+
+    fn summarize(&self, r: usize) -> Summary<P::Transfer> {
+        let lp = &self.locals[r];
+        let children_summarized = lp.local.children.iter().all(|c| matches!(self.summaries[*c], Summary::Function(_)));
+        if !children_summarized || !is_reducible(&lp.succs, lp.start) {
+            return Summary::Worklist;
+        }
+        let problem = self.problem;
+        let (inputs, _) = solve(&lp.succs, lp.start, Some(problem.identity()), &None,
+            |a, b| match (a, b) {
+                (Some(f), Some(g)) => Some(problem.join_transfers(f, g)),
+                (Some(f), None) | (None, Some(f)) => Some(f.clone()),
+                (None, None) => None,
+            },
+            |v, f| {
+                let f = f.as_ref()?;
+                if lp.local.is_node(v) {
+                    Some(problem.compose(f, &problem.transfer(lp.local.nodes[v])))
+                } else if lp.local.is_child(v) {
+                    match &self.summaries[lp.local.children[v - lp.local.nodes.len()]] {
+                        Summary::Function(g) => g.as_ref().map(|g| problem.compose(f, g)),
+                        Summary::Worklist => unreachable!(),
+                    }
+                } else {
+                    Some(f.clone())
+                }
+            });
+        Summary::Function(inputs[lp.end].clone())
+    }
+
+    // solve one region's local graph from the value at its entry, giving inputs and outputs per vertex
+    fn solve_region(&self, r: usize, entry: &P::Value) -> (Vec<P::Value>, Vec<P::Value>) {
+        let lp = &self.locals[r];
+        let problem = self.problem;
+        solve(&lp.succs, lp.start, entry.clone(), &problem.bottom(),
+              |a, b| problem.join(a, b),
+              |v, x| {
+                  if lp.local.is_node(v) {
+                      problem.apply(&problem.transfer(lp.local.nodes[v]), x)
+                  } else if lp.local.is_child(v) {
+                      self.apply_summary(lp.local.children[v - lp.local.nodes.len()], x)
+                  } else {
+                      x.clone()
+                  }
+              })
+    }
+
+    // value leaving a region for a value entering it
+    fn apply_summary(&self, r: usize, entry: &P::Value) -> P::Value {
+        match &self.summaries[r] {
+            Summary::Function(Some(f)) => self.problem.apply(f, entry),
+            Summary::Function(None) => self.problem.bottom(),
+            Summary::Worklist => {
+                let (inputs, _) = self.solve_region(r, entry);
+                inputs[self.locals[r].end].clone()
+            }
+        }
+    }
+
+    pub fn solve(&self) -> DataflowResult<P::Value> {
+        let node_count = self.table.node_region.len();
+        let mut inputs = vec![self.problem.bottom(); node_count];
+        let mut outputs = vec![self.problem.bottom(); node_count];
+        let mut entries = vec![self.problem.bottom(); self.table.len()];
+        entries[0] = self.problem.boundary();
+        for r in 0..self.table.len() {
+            let (vertex_inputs, vertex_outputs) = self.solve_region(r, &entries[r]);
+            let local = &self.locals[r].local;
+            for (i, n) in local.nodes.iter().enumerate() {
+                inputs[*n] = vertex_inputs[i].clone();
+                outputs[*n] = vertex_outputs[i].clone();
+            }
+            for (i, c) in local.children.iter().enumerate() {
+                entries[*c] = vertex_inputs[local.child_vertex(i)].clone();
+            }
+        }
+        DataflowResult { inputs, outputs }
+    }
+}
+
+// solve a problem region by region over the structure tree
+pub fn solve_regions<P: DataflowProblem>(graph: &FlowGraph, tree: &StructureTree, problem: &P) -> Result<DataflowResult<P::Value>, String> {
+    Ok(RegionSolver::new(graph, tree, problem)?.solve())
+}
+
+// gen/kill transfer function over sets, x -> (x - kill) + gen
+#[derive(Clone,Debug,PartialEq)]
+pub struct GenKill<T: Ord> {
+    pub gen: BTreeSet<T>,
+    pub kill: BTreeSet<T>,
+}
+
+// bit vector style may problem: values are sets joined by union
+#[derive(Clone,Debug)]
+pub struct GenKillProblem<T: Ord> {
+    pub direction: Direction,
+    pub transfers: Vec<GenKill<T>>, // by node index
+    pub boundary: BTreeSet<T>,
+}
+
+impl<T: Ord + Clone> DataflowProblem for GenKillProblem<T> {
+    type Value = BTreeSet<T>;
+    type Transfer = GenKill<T>;
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn bottom(&self) -> BTreeSet<T> {
+        BTreeSet::new()
+    }
+
+    fn boundary(&self) -> BTreeSet<T> {
+        self.boundary.clone()
+    }
+
+    fn join(&self, a: &BTreeSet<T>, b: &BTreeSet<T>) -> BTreeSet<T> {
+        a.union(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: usize) -> GenKill<T> {
+        self.transfers[node].clone()
+    }
+
+    fn apply(&self, f: &GenKill<T>, value: &BTreeSet<T>) -> BTreeSet<T> {
+        value.difference(&f.kill).chain(f.gen.iter()).cloned().collect()
+    }
+
+    fn identity(&self) -> GenKill<T> {
+        GenKill { gen: BTreeSet::new(), kill: BTreeSet::new() }
+    }
+
+    fn compose(&self, f: &GenKill<T>, g: &GenKill<T>) -> GenKill<T> {
+        GenKill {
+            gen: f.gen.difference(&g.kill).chain(g.gen.iter()).cloned().collect(),
+            kill: f.kill.union(&g.kill).cloned().collect(),
+        }
+    }
+
+    fn join_transfers(&self, f: &GenKill<T>, g: &GenKill<T>) -> GenKill<T> {
+        GenKill {
+            gen: f.gen.union(&g.gen).cloned().collect(),
+            kill: f.kill.intersection(&g.kill).cloned().collect(),
+        }
+    }
+}
+
+// live variables, from the variables each node uses and defines
+pub fn liveness(uses: &[Vec<usize>], defs: &[Vec<usize>]) -> GenKillProblem<usize> {
+    let transfers = uses.iter().zip(defs.iter())
+        .map(|(u, d)| GenKill { gen: u.iter().cloned().collect(), kill: d.iter().cloned().collect() })
+        .collect();
+    GenKillProblem { direction: Direction::Backward, transfers, boundary: BTreeSet::new() }
+}
+
+// reaching definitions, each definition named by its (node index, variable)
+pub fn reaching_definitions(defs: &[Vec<usize>]) -> GenKillProblem<(usize, usize)> {
+    let transfers = defs.iter().enumerate()
+        .map(|(n, vars)| GenKill {
+            gen: vars.iter().map(|v| (n, *v)).collect(),
+            kill: defs.iter().enumerate()
+                .flat_map(|(m, ws)| ws.iter().filter(|w| vars.contains(w)).map(move |w| (m, *w)))
+                .filter(|(m, _)| *m != n)
+                .collect(),
+        })
+        .collect();
+    GenKillProblem { direction: Direction::Forward, transfers, boundary: BTreeSet::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::{examples, seeded_graphs, Rng};
+
+    // every node reachable from the source and reaching the sink, as the region solver assumes
+    fn is_well_formed(graph: &FlowGraph) -> bool {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let reaches_all = |succs: Vec<Vec<usize>>, start: usize| {
+            let mut seen = vec![false; succs.len()];
+            let mut stack = vec![start];
+            seen[start] = true;
+            while let Some(v) = stack.pop() {
+                for w in succs[v].iter() {
+                    if !seen[*w] {
+                        seen[*w] = true;
+                        stack.push(*w);
+                    }
+                }
+            }
+            seen.iter().all(|s| *s)
+        };
+        reaches_all(digraph.successor_lists(), digraph.entry) && reaches_all(digraph.predecessor_lists(), digraph.exit)
+    }
+
+    fn check_both_problems(graph: &FlowGraph, tree: &StructureTree, uses: &[Vec<usize>], defs: &[Vec<usize>]) {
+        let live = liveness(uses, defs);
+        assert_eq!(solve_regions(graph, tree, &live).unwrap(), solve_worklist(graph, &live));
+        let reaching = reaching_definitions(defs);
+        assert_eq!(solve_regions(graph, tree, &reaching).unwrap(), solve_worklist(graph, &reaching));
+    }
+
+    #[test]
+    fn examples_match_worklist() {
+        for mut graph in examples() {
+            assert!(is_well_formed(&graph));
+            let n = graph.node_count();
+            let defs = (0..n).map(|i| vec![i % 4]).collect::<Vec<Vec<usize>>>();
+            let uses = (0..n).map(|i| vec![(i + 1) % 4, (i * 3) % 5]).collect::<Vec<Vec<usize>>>();
+            let tree = build_structure_tree(&mut graph);
+            check_both_problems(&graph, &tree, &uses, &defs);
+        }
+    }
+
+    #[test]
+    fn fig1_liveness() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let mut uses = vec![Vec::new(); graph.node_count()];
+        let mut defs = vec![Vec::new(); graph.node_count()];
+        defs[0].push(0);
+        uses[10].push(0);
+        defs[6].push(0);
+        let result = solve_regions(&graph, &tree, &liveness(&uses, &defs)).unwrap();
+        let live = |n: usize| result.outputs[n].contains(&0);
+        // x is live from its definition at 0 down the 4 and 8 branches to its use at 10, but not after 6
+        assert!(live(4) && live(8) && live(10) && live(2));
+        assert!(!live(6) && !live(12) && !live(1));
+    }
+
+    #[test]
+    fn random_graphs_match_worklist() {
+        // a second generator picks the uses and definitions
+        let mut rng = Rng::new(11);
+        let mut checked = 0;
+        let mut unstructured = 0;
+        for mut graph in seeded_graphs(11, 14, 6) {
+            if !is_well_formed(&graph) {
+                continue;
+            }
+            let n = graph.node_count();
+            let defs = (0..n).map(|_| (0..4).filter(|_| rng.below(3) == 0).collect()).collect::<Vec<Vec<usize>>>();
+            let uses = (0..n).map(|_| (0..4).filter(|_| rng.below(3) == 0).collect()).collect::<Vec<Vec<usize>>>();
+            let tree = build_structure_tree(&mut graph);
+            check_both_problems(&graph, &tree, &uses, &defs);
+            let live = liveness(&uses, &defs);
+            if !RegionSolver::new(&graph, &tree, &live).unwrap().unstructured().is_empty() {
+                unstructured += 1;
+            }
+            checked += 1;
+            if checked == 150 {
+                break;
+            }
+        }
+        // the fallback has to have been exercised
+        assert!(unstructured > 0);
+    }
+}
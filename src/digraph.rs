@@ -0,0 +1,186 @@
+use crate::FlowGraph;
+
+// directed view of a FlowGraph, following each edge from its `from` node to its `to` node
+// the source is node index 0, as elsewhere in the crate
+// the flow graphs close their cycle with a return edge from the sink back to the source;
+// the directed analyses treat that edge as leaving the graph, so it has no head here
+#[derive(Clone,Debug)]
+pub struct DiGraph {
+    pub entry: usize, // node index of the source
+    pub exit: usize, // node index of the sink
+    pub return_edge: Option<usize>, // edge index of the sink to source edge, if there is one
+    pub edges: Vec<Option<(usize, usize)>>, // (from, to) by edge index, None for capping backedges
+    pub out_edges: Vec<Vec<usize>>, // outgoing edge indexes of each node, the return edge included
+    pub in_edges: Vec<Vec<usize>>, // incoming edge indexes of each node, the return edge excluded
+}
+
+impl DiGraph {
+    // build the directed view; the return edge is the first edge into the source,
+    // and its tail is the sink; without one the sink is the first node with no outgoing edges
+    pub fn from_flow_graph(graph: &FlowGraph) -> DiGraph {
+        let entry = 0;
+        let mut edges = Vec::with_capacity(graph.edge_count());
+        for e in graph.edge_indices() {
+            let edge = graph[e].borrow();
+            edges.push(if edge.is_capping { None } else { Some((edge.from, edge.to)) });
+        }
+        let return_edge = edges.iter().position(|e| matches!(e, Some((_, to)) if *to == entry));
+        let mut out_edges = vec![Vec::new(); graph.node_count()];
+        for (i, e) in edges.iter().enumerate() {
+            if let Some((from, _)) = e {
+                out_edges[*from].push(i);
+            }
+        }
+        let exit = match return_edge {
+            Some(r) => edges[r].unwrap().0,
+            None => out_edges.iter().position(|o| o.is_empty()).unwrap_or(graph.node_count().saturating_sub(1)),
+        };
+        DiGraph::new(entry, exit, return_edge, edges)
+    }
+
+    // build a directed graph from explicit parts
+    pub fn new(entry: usize, exit: usize, return_edge: Option<usize>, edges: Vec<Option<(usize, usize)>>) -> DiGraph {
+        let node_count = edges.iter()
+            .flatten()
+            .map(|(a, b)| a.max(b) + 1)
+            .max()
+            .unwrap_or(0)
+            .max(entry + 1)
+            .max(exit + 1);
+        let mut out_edges = vec![Vec::new(); node_count];
+        let mut in_edges = vec![Vec::new(); node_count];
+        for (i, e) in edges.iter().enumerate() {
+            if let Some((from, to)) = e {
+                out_edges[*from].push(i);
+                if Some(i) != return_edge {
+                    in_edges[*to].push(i);
+                }
+            }
+        }
+        DiGraph {
+            entry,
+            exit,
+            return_edge,
+            edges,
+            out_edges,
+            in_edges,
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.out_edges.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_return_edge(&self, e: usize) -> bool {
+        self.return_edge == Some(e)
+    }
+
+    // tail and head of an edge, None for capping backedges
+    pub fn endpoints(&self, e: usize) -> Option<(usize, usize)> {
+        self.edges.get(e).cloned().flatten()
+    }
+
+    // successor nodes, not following the return edge
+    pub fn successors(&self, n: usize) -> Vec<usize> {
+        self.out_edges[n].iter()
+            .filter(|e| !self.is_return_edge(**e))
+            .map(|e| self.edges[*e].unwrap().1)
+            .collect()
+    }
+
+    // predecessor nodes, not following the return edge
+    pub fn predecessors(&self, n: usize) -> Vec<usize> {
+        self.in_edges[n].iter()
+            .map(|e| self.edges[*e].unwrap().0)
+            .collect()
+    }
+
+    // successor lists of all nodes, not following the return edge
+    pub fn successor_lists(&self) -> Vec<Vec<usize>> {
+        (0..self.node_count()).map(|n| self.successors(n)).collect()
+    }
+
+    // successor lists of all nodes in the reversed graph
+    pub fn predecessor_lists(&self) -> Vec<Vec<usize>> {
+        (0..self.node_count()).map(|n| self.predecessors(n)).collect()
+    }
+
+    // the graph with a midpoint vertex on every edge, so that edges can be compared by dominance
+    // vertex n is node n and vertex node_count() + e is the midpoint of edge e
+    // the return edge midpoint has no successor, so it stands for leaving the graph
+    pub fn edge_split_successors(&self) -> Vec<Vec<usize>> {
+        let n = self.node_count();
+        let mut succs = vec![Vec::new(); n + self.edge_count()];
+        for (i, e) in self.edges.iter().enumerate() {
+            if let Some((from, to)) = e {
+                succs[*from].push(n + i);
+                if !self.is_return_edge(i) {
+                    succs[n + i].push(*to);
+                }
+            }
+        }
+        succs
+    }
+
+    // vertex standing for edge e in the edge split graph
+    pub fn edge_vertex(&self, e: usize) -> usize {
+        self.node_count() + e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn fig1_view() {
+        let digraph = DiGraph::from_flow_graph(&make_example_fig1());
+        assert_eq!((digraph.entry, digraph.exit), (0, 15));
+        assert_eq!(digraph.return_edge, Some(20));
+        assert_eq!(digraph.endpoints(20), Some((15, 0)));
+        assert_eq!((digraph.node_count(), digraph.edge_count()), (16, 21));
+        // the return edge leaves the sink but does not enter the source
+        assert_eq!(digraph.out_edges[15], vec![20]);
+        assert!(digraph.successors(15).is_empty());
+        assert!(digraph.predecessors(0).is_empty());
+        assert_eq!(digraph.successors(4), vec![6, 8]);
+        assert_eq!(digraph.predecessors(13), vec![1, 11]);
+        assert_eq!(digraph.successor_lists()[8], vec![10, 12]);
+        assert_eq!(digraph.predecessor_lists()[15], vec![13, 14]);
+    }
+
+    #[test]
+    fn edge_split() {
+        // 0 -> 1 -> 2, 2 -> 0 returning, and a capping backedge that has no endpoints
+        let digraph = DiGraph::new(0, 2, Some(2), vec![Some((0, 1)), Some((1, 2)), Some((2, 0)), None]);
+        assert!(digraph.is_return_edge(2) && !digraph.is_return_edge(0));
+        assert_eq!(digraph.endpoints(3), None);
+        assert_eq!(digraph.endpoints(7), None);
+        let split = digraph.edge_split_successors();
+        assert_eq!(split.len(), 3 + 4);
+        assert_eq!(split[0], vec![digraph.edge_vertex(0)]);
+        assert_eq!(split[digraph.edge_vertex(1)], vec![2]);
+        assert_eq!(split[2], vec![digraph.edge_vertex(2)]);
+        assert!(split[digraph.edge_vertex(2)].is_empty());
+        assert!(split[digraph.edge_vertex(3)].is_empty());
+    }
+
+    #[test]
+    fn sink_without_return_edge() {
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..4).map(|id| add_graph_node(&mut graph, id)).collect::<Vec<_>>();
+        add_graph_edge(&mut graph, nodes[0], nodes[1]);
+        add_graph_edge(&mut graph, nodes[1], nodes[3]);
+        add_graph_edge(&mut graph, nodes[1], nodes[2]);
+        add_graph_edge(&mut graph, nodes[2], nodes[3]);
+        let digraph = DiGraph::from_flow_graph(&graph);
+        assert_eq!(digraph.return_edge, None);
+        assert_eq!(digraph.exit, 3);
+        assert_eq!(digraph.in_edges[3], vec![1, 3]);
+    }
+}
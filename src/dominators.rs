@@ -0,0 +1,409 @@
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+
+// dominator trees for directed graphs given as successor lists over vertices 0..n
+// the iterative algorithm of Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+// postdominators are the dominators of the reversed graph rooted at the exit
+
+#[derive(Clone,Debug)]
+pub struct DominatorTree {
+    pub root: usize,
+    idom: Vec<Option<usize>>, // immediate dominator, None for the root and unreachable vertices
+    children: Vec<Vec<usize>>, // children in the dominator tree
+    pre: Vec<usize>, // preorder number in the dominator tree, usize::MAX if unreachable
+    post: Vec<usize>, // postorder number in the dominator tree
+}
+
+// reverse postorder of the vertices reachable from root
+fn reverse_postorder(succs: &[Vec<usize>], root: usize) -> Vec<usize> {
+    let mut order = Vec::new();
+    let mut visited = vec![false; succs.len()];
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    while let Some((v, i)) = stack.last_mut() {
+        if *i < succs[*v].len() {
+            let w = succs[*v][*i];
+            *i += 1;
+            if !visited[w] {
+                visited[w] = true;
+                stack.push((w, 0));
+            }
+        } else {
+            order.push(*v);
+            stack.pop();
+        }
+    }
+    order.reverse();
+    order
+}
+
+impl DominatorTree {
+    // compute the dominator tree of the vertices reachable from root
+    pub fn new(succs: &[Vec<usize>], root: usize) -> DominatorTree {
+        let n = succs.len();
+        let rpo = reverse_postorder(succs, root);
+        let mut rpo_number = vec![usize::MAX; n];
+        for (i, v) in rpo.iter().enumerate() {
+            rpo_number[*v] = i;
+        }
+        let mut preds = vec![Vec::new(); n];
+        for (v, ws) in succs.iter().enumerate() {
+            for w in ws.iter() {
+                preds[*w].push(v);
+            }
+        }
+        // idom as vertex, with the root pointing at itself while iterating
+        let mut idom = vec![usize::MAX; n];
+        idom[root] = root;
+        let intersect = |idom: &Vec<usize>, mut a: usize, mut b: usize| {
+            while a != b {
+                while rpo_number[a] > rpo_number[b] {
+                    a = idom[a];
+                }
+                while rpo_number[b] > rpo_number[a] {
+                    b = idom[b];
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for v in rpo.iter().skip(1) {
+                let mut new_idom = usize::MAX;
+                for p in preds[*v].iter() {
+                    if idom[*p] == usize::MAX {
+                        continue;
+                    }
+                    new_idom = if new_idom == usize::MAX { *p } else { intersect(&idom, *p, new_idom) };
+                }
+                if new_idom != idom[*v] {
+                    idom[*v] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let idom = (0..n)
+            .map(|v| if v == root || idom[v] == usize::MAX { None } else { Some(idom[v]) })
+            .collect::<Vec<Option<usize>>>();
+        let mut children = vec![Vec::new(); n];
+        for v in rpo.iter() {
+            if let Some(d) = idom[*v] {
+                children[d].push(*v);
+            }
+        }
+        // number the tree so that dominance is an interval test
+        let mut pre = vec![usize::MAX; n];
+        let mut post = vec![usize::MAX; n];
+        let mut counter = 0;
+        let mut post_counter = 0;
+        let mut stack = vec![(root, 0)];
+        pre[root] = counter;
+        counter += 1;
+        while let Some((v, i)) = stack.last_mut() {
+            if *i < children[*v].len() {
+                let c = children[*v][*i];
+                *i += 1;
+                pre[c] = counter;
+                counter += 1;
+                stack.push((c, 0));
+            } else {
+                post[*v] = post_counter;
+                post_counter += 1;
+                stack.pop();
+            }
+        }
+        DominatorTree {
+            root,
+            idom,
+            children,
+            pre,
+            post,
+        }
+    }
+
+    // immediate dominator of a vertex, None for the root and unreachable vertices
+    pub fn idom(&self, v: usize) -> Option<usize> {
+        self.idom[v]
+    }
+
+    // children of a vertex in the dominator tree
+    pub fn children(&self, v: usize) -> &[usize] {
+        &self.children[v]
+    }
+
+    pub fn is_reachable(&self, v: usize) -> bool {
+        self.pre[v] != usize::MAX
+    }
+
+    // does a dominate b? every vertex dominates itself
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        self.is_reachable(a) && self.is_reachable(b) && self.pre[a] <= self.pre[b] && self.post[b] <= self.post[a]
+    }
+
+    // does a strictly dominate b?
+    pub fn strictly_dominates(&self, a: usize, b: usize) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    // depth in the dominator tree, the root has depth 0
+    pub fn depth(&self, v: usize) -> Option<usize> {
+        if !self.is_reachable(v) {
+            return None;
+        }
+        let mut depth = 0;
+        let mut v = v;
+        while let Some(d) = self.idom[v] {
+            depth += 1;
+            v = d;
+        }
+        Some(depth)
+    }
+
+    // all dominators of a vertex, from the vertex itself up to the root
+    pub fn dominators_of(&self, v: usize) -> Vec<usize> {
+        if !self.is_reachable(v) {
+            return Vec::new();
+        }
+        let mut result = vec![v];
+        let mut v = v;
+        while let Some(d) = self.idom[v] {
+            result.push(d);
+            v = d;
+        }
+        result
+    }
+
+    // dominance frontier of every vertex, given the successor lists the tree was built from
+    // for a postdominator tree pass the reversed lists to get the postdominance frontiers
+    // each frontier is sorted
+    pub fn frontiers(&self, succs: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let n = succs.len();
+        let mut preds = vec![Vec::new(); n];
+        for (v, ws) in succs.iter().enumerate() {
+            if !self.is_reachable(v) {
+                continue;
+            }
+            for w in ws.iter() {
+                preds[*w].push(v);
+            }
+        }
+        let mut frontiers = vec![Vec::new(); n];
+        for (b, ps) in preds.iter().enumerate() {
+            if ps.len() < 2 && !(ps.len() == 1 && b == self.root) {
+                continue;
+            }
+            for p in ps.iter() {
+                // walk up from the predecessor until we reach b's immediate dominator
+                let mut runner = Some(*p);
+                while let Some(r) = runner {
+                    if Some(r) == self.idom[b] {
+                        break;
+                    }
+                    if !frontiers[r].contains(&b) {
+                        frontiers[r].push(b);
+                    }
+                    if r == b {
+                        break;
+                    }
+                    runner = self.idom[r];
+                }
+            }
+        }
+        for f in frontiers.iter_mut() {
+            f.sort_unstable();
+        }
+        frontiers
+    }
+
+    pub fn len(&self) -> usize {
+        self.idom.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.idom.is_empty()
+    }
+}
+
+// postdominator tree: dominators of the reversed graph rooted at exit
+pub fn postdominators(succs: &[Vec<usize>], exit: usize) -> DominatorTree {
+    let mut reversed = vec![Vec::new(); succs.len()];
+    for (v, ws) in succs.iter().enumerate() {
+        for w in ws.iter() {
+            reversed[*w].push(v);
+        }
+    }
+    DominatorTree::new(&reversed, exit)
+}
+
+// is the graph reducible from start, with every retreating edge of a depth first search going to a dominator?
+pub fn is_reducible(succs: &[Vec<usize>], start: usize) -> bool {
+    let dominators = DominatorTree::new(succs, start);
+    let mut on_stack = vec![false; succs.len()];
+    let mut visited = vec![false; succs.len()];
+    let mut stack = vec![(start, 0)];
+    visited[start] = true;
+    on_stack[start] = true;
+    while let Some((v, i)) = stack.pop() {
+        if i == succs[v].len() {
+            on_stack[v] = false;
+            continue;
+        }
+        stack.push((v, i + 1));
+        let w = succs[v][i];
+        if on_stack[w] && !dominators.dominates(w, v) {
+            return false;
+        }
+        if !visited[w] {
+            visited[w] = true;
+            on_stack[w] = true;
+            stack.push((w, 0));
+        }
+    }
+    true
+}
+
+// dominance analysis of a flow graph over its node indexes
+// the directed view follows each edge from `from` to `to` and ignores the sink to source return edge,
+// so the source dominates and the sink postdominates every node
+#[derive(Clone,Debug)]
+pub struct CfgDominance {
+    pub digraph: DiGraph,
+    pub dominators: DominatorTree,
+    pub postdominators: DominatorTree,
+    pub frontiers: Vec<Vec<usize>>,
+    pub postdominance_frontiers: Vec<Vec<usize>>,
+}
+
+impl CfgDominance {
+    pub fn new(graph: &FlowGraph) -> CfgDominance {
+        CfgDominance::from_digraph(DiGraph::from_flow_graph(graph))
+    }
+
+    pub fn from_digraph(digraph: DiGraph) -> CfgDominance {
+        let succs = digraph.successor_lists();
+        let preds = digraph.predecessor_lists();
+        let dominators = DominatorTree::new(&succs, digraph.entry);
+        let postdominators = DominatorTree::new(&preds, digraph.exit);
+        let frontiers = dominators.frontiers(&succs);
+        let postdominance_frontiers = postdominators.frontiers(&preds);
+        CfgDominance {
+            digraph,
+            dominators,
+            postdominators,
+            frontiers,
+            postdominance_frontiers,
+        }
+    }
+
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        self.dominators.dominates(a, b)
+    }
+
+    pub fn postdominates(&self, a: usize, b: usize) -> bool {
+        self.postdominators.dominates(a, b)
+    }
+
+    pub fn idom(&self, n: usize) -> Option<usize> {
+        self.dominators.idom(n)
+    }
+
+    pub fn ipdom(&self, n: usize) -> Option<usize> {
+        self.postdominators.idom(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn fig1_dominators() {
+        let analysis = CfgDominance::new(&make_example_fig1());
+        let idoms = [(1, 0), (2, 0), (3, 1), (4, 2), (5, 3), (6, 4), (7, 5), (8, 4),
+                     (9, 5), (10, 4), (11, 5), (12, 4), (13, 1), (14, 12), (15, 0)];
+        for (n, d) in idoms.iter() {
+            assert_eq!(analysis.idom(*n), Some(*d), "idom of {}", n);
+        }
+        assert_eq!(analysis.idom(0), None);
+        assert!(analysis.dominates(1, 11));
+        assert!(!analysis.dominates(2, 15));
+        assert_eq!(analysis.dominators.depth(14), Some(4));
+        assert_eq!(analysis.dominators.dominators_of(7), vec![7, 5, 3, 1, 0]);
+    }
+
+    #[test]
+    fn fig1_postdominators() {
+        let analysis = CfgDominance::new(&make_example_fig1());
+        let ipdoms = [(0, 15), (1, 13), (2, 4), (3, 5), (4, 12), (5, 11), (6, 10), (7, 11),
+                      (8, 12), (9, 11), (10, 12), (11, 13), (12, 14), (13, 15), (14, 15)];
+        for (n, d) in ipdoms.iter() {
+            assert_eq!(analysis.ipdom(*n), Some(*d), "ipdom of {}", n);
+        }
+        assert_eq!(analysis.ipdom(15), None);
+        assert!(analysis.postdominates(13, 3));
+        assert!(!analysis.postdominates(10, 8));
+    }
+
+    #[test]
+    fn fig1_frontiers() {
+        let analysis = CfgDominance::new(&make_example_fig1());
+        let expected: [&[usize]; 16] = [&[], &[15], &[15], &[13], &[15], &[13], &[10], &[11],
+                                         &[10, 12], &[11], &[12], &[13], &[15], &[15], &[15], &[]];
+        for (n, df) in expected.iter().enumerate() {
+            assert_eq!(analysis.frontiers[n], df.to_vec(), "frontier of {}", n);
+        }
+        // 10 is reached from 4 through 6 and from 8, but neither branch node is postdominated by it
+        assert_eq!(analysis.postdominance_frontiers[10], vec![4, 8]);
+        assert_eq!(analysis.postdominance_frontiers[6], vec![4]);
+    }
+
+    #[test]
+    fn diamond_dominance() {
+        let analysis = CfgDominance::new(&make_example_diamond());
+        assert_eq!(analysis.digraph.exit, 5);
+        assert_eq!(analysis.idom(4), Some(1));
+        assert_eq!(analysis.idom(5), Some(4));
+        assert_eq!(analysis.ipdom(1), Some(4));
+        assert_eq!(analysis.ipdom(0), Some(1));
+        assert_eq!(analysis.frontiers[2], vec![4]);
+        assert_eq!(analysis.frontiers[3], vec![4]);
+        assert!(analysis.frontiers[1].is_empty());
+        assert_eq!(analysis.postdominance_frontiers[2], vec![1]);
+        assert_eq!(analysis.postdominance_frontiers[3], vec![1]);
+    }
+
+    #[test]
+    fn successor_lists() {
+        // 0 -> 1 -> {2, 3} -> 4, with 5 unreachable and pointing into 4
+        let succs = vec![vec![1], vec![2, 3], vec![4], vec![4], vec![], vec![4]];
+        let tree = DominatorTree::new(&succs, 0);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.idom(4), Some(1));
+        let mut children = tree.children(1).to_vec();
+        children.sort();
+        assert_eq!(children, vec![2, 3, 4]);
+        assert!(!tree.is_reachable(5));
+        assert_eq!(tree.idom(5), None);
+        assert!(!tree.dominates(5, 5) && !tree.dominates(0, 5));
+        assert!(tree.dominates(2, 2) && !tree.strictly_dominates(2, 2));
+        assert_eq!(tree.depth(5), None);
+        assert!(tree.dominators_of(5).is_empty());
+        // the unreachable vertex adds no frontier
+        assert_eq!(tree.frontiers(&succs)[2], vec![4]);
+        assert!(tree.frontiers(&succs)[5].is_empty());
+        let post = postdominators(&succs, 4);
+        assert_eq!(post.idom(0), Some(1));
+        assert_eq!(post.idom(1), Some(4));
+        assert_eq!(post.idom(5), Some(4));
+    }
+
+    #[test]
+    fn reducibility() {
+        // a loop with a single header, then the same loop entered at both of its nodes
+        assert!(is_reducible(&[vec![1], vec![2], vec![1, 3], vec![]], 0));
+        assert!(!is_reducible(&[vec![1, 2], vec![2], vec![1]], 0));
+    }
+}
+
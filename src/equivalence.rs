@@ -0,0 +1,210 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::FlowGraph;
+
+// query object over the classes that cycle_equivalence leaves on the edges
+// equivalence is a comparison of class numbers, so it is O(1) per query
+// the graph is snapshotted so that witness cycles can be searched without holding borrows
+#[derive(Clone,Debug)]
+pub struct CycleEquivalence {
+    classes: Vec<usize>, // class of each edge by edge index, 0 for capping backedges
+    endpoints: Vec<(NodeIndex, NodeIndex)>, // endpoints of each edge by edge index
+    members: HashMap<usize, Vec<EdgeIndex>>, // edges in each class, in edge index order
+    adjacency: Vec<Vec<(NodeIndex, EdgeIndex)>>, // incident (other node, edge) pairs, without capping backedges
+}
+
+impl CycleEquivalence {
+    // build the query object from a graph that has been run through cycle_equivalence
+    pub fn new(graph: &FlowGraph) -> CycleEquivalence {
+        let mut classes = Vec::with_capacity(graph.edge_count());
+        let mut endpoints = Vec::with_capacity(graph.edge_count());
+        let mut members = HashMap::<usize, Vec<EdgeIndex>>::new();
+        let mut adjacency = vec![Vec::new(); graph.node_count()];
+        for e in graph.edge_indices() {
+            let (a, b) = graph.edge_endpoints(e).unwrap();
+            endpoints.push((a, b));
+            let edge = graph[e].borrow();
+            // capping backedges are an artifact of the algorithm, not part of the graph
+            if edge.is_capping {
+                classes.push(0);
+                continue;
+            }
+            classes.push(edge.class);
+            members.entry(edge.class).or_default().push(e);
+            adjacency[a.index()].push((b, e));
+            if a != b {
+                adjacency[b.index()].push((a, e));
+            }
+        }
+        CycleEquivalence {
+            classes,
+            endpoints,
+            members,
+            adjacency,
+        }
+    }
+
+    // the class of an edge, or None for capping backedges and unknown edges
+    pub fn class(&self, e: EdgeIndex) -> Option<usize> {
+        match self.classes.get(e.index()) {
+            Some(&class) if class > 0 => Some(class),
+            _ => None,
+        }
+    }
+
+    // all edges of a class, in edge index order
+    pub fn members(&self, class: usize) -> &[EdgeIndex] {
+        self.members.get(&class).map(|m| m.as_slice()).unwrap_or(&[])
+    }
+
+    // the class numbers in use, in increasing order
+    pub fn class_ids(&self) -> Vec<usize> {
+        let mut ids = self.members.keys().cloned().filter(|c| *c > 0).collect::<Vec<usize>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    // are the two edges cycle equivalent?
+    pub fn equivalent(&self, a: EdgeIndex, b: EdgeIndex) -> bool {
+        match (self.class(a), self.class(b)) {
+            (Some(x), Some(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    // a cycle that contains exactly one of the two edges, as the list of its edges in order around the cycle
+    // returns None when no such cycle exists, which is the case exactly when the edges are cycle equivalent
+    pub fn witness_cycle(&self, a: EdgeIndex, b: EdgeIndex) -> Option<Vec<EdgeIndex>> {
+        if a == b {
+            return None;
+        }
+        self.cycle_through(a, b).or_else(|| self.cycle_through(b, a))
+    }
+
+    // find a cycle through edge e that does not use edge avoid
+    // the cycle starts with e and follows a shortest path from e's second endpoint back to its first
+    pub fn cycle_through(&self, e: EdgeIndex, avoid: EdgeIndex) -> Option<Vec<EdgeIndex>> {
+        let (from, to) = *self.endpoints.get(e.index())?;
+        // capping backedges are not part of any cycle of the graph
+        self.class(e)?;
+        if from == to {
+            return Some(vec![e]);
+        }
+        // breadth first search from `to` to `from` that skips e and avoid
+        let mut reached_by = vec![None; self.adjacency.len()];
+        let mut seen = vec![false; self.adjacency.len()];
+        let mut queue = VecDeque::new();
+        seen[to.index()] = true;
+        queue.push_back(to);
+        while let Some(n) = queue.pop_front() {
+            if n == from {
+                break;
+            }
+            for (other, edge) in self.adjacency[n.index()].iter() {
+                if *edge == e || *edge == avoid || seen[other.index()] {
+                    continue;
+                }
+                seen[other.index()] = true;
+                reached_by[other.index()] = Some((n, *edge));
+                queue.push_back(*other);
+            }
+        }
+        if !seen[from.index()] {
+            return None;
+        }
+        // walk back from `from` to `to`, which lists the path edges in cycle order after e
+        let mut cycle = vec![e];
+        let mut n = from;
+        while let Some((prev, edge)) = reached_by[n.index()] {
+            cycle.push(edge);
+            n = prev;
+        }
+        cycle[1..].reverse();
+        Some(cycle)
+    }
+
+    // render a cycle as the external node ids it passes through, for debugging
+    pub fn describe_cycle(&self, graph: &FlowGraph, cycle: &[EdgeIndex]) -> String {
+        let mut result = String::new();
+        for e in cycle.iter() {
+            let (a, b) = self.endpoints[e.index()];
+            result.push_str(&format!("({}, {}) c:{} ", graph[a].borrow().id, graph[b].borrow().id, self.classes[e.index()]));
+        }
+        result.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::seeded_graphs;
+
+    fn classified(mut graph: FlowGraph) -> (FlowGraph, CycleEquivalence) {
+        let rev_order = dfs_tree(&mut graph);
+        cycle_equivalence(&mut graph, &rev_order);
+        let query = CycleEquivalence::new(&graph);
+        (graph, query)
+    }
+
+    // consecutive edges of the cycle share an endpoint and the last one closes it
+    fn is_closed(query: &CycleEquivalence, cycle: &[EdgeIndex]) -> bool {
+        let (first, _) = query.endpoints[cycle[0].index()];
+        let mut at = query.endpoints[cycle[0].index()].1;
+        for e in cycle[1..].iter() {
+            let (a, b) = query.endpoints[e.index()];
+            at = if a == at { b } else if b == at { a } else { return false };
+        }
+        at == first
+    }
+
+    #[test]
+    fn witness_cycles() {
+        for (graph, query) in seeded_graphs(26, 10, 6).take(30).map(classified) {
+            let edges = graph.edge_indices().filter(|e| query.class(*e).is_some()).collect::<Vec<EdgeIndex>>();
+            for a in edges.iter() {
+                for b in edges.iter() {
+                    if let Some(cycle) = query.cycle_through(*a, *b) {
+                        assert!(is_closed(&query, &cycle), "{}", query.describe_cycle(&graph, &cycle));
+                        assert_eq!(cycle[0], *a);
+                        assert!(!cycle.contains(b) || a == b);
+                        // a cycle through an edge goes through its whole class
+                        for m in query.members(query.class(*a).unwrap()) {
+                            assert!(cycle.contains(m), "{}", query.describe_cycle(&graph, &cycle));
+                        }
+                    }
+                    match query.witness_cycle(*a, *b) {
+                        Some(cycle) => {
+                            assert!(!query.equivalent(*a, *b));
+                            assert!(cycle.contains(a) != cycle.contains(b));
+                        }
+                        None => assert!(query.equivalent(*a, *b)),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn classes_of_fig1() {
+        let (graph, query) = classified(make_example_fig1());
+        // every edge but capping backedges has a class, and the classes split the edges
+        let classes = query.class_ids();
+        let total = classes.iter().map(|c| query.members(*c).len()).sum::<usize>();
+        assert_eq!(total, graph.edge_indices().filter(|e| !graph[*e].borrow().is_capping).count());
+        for c in classes {
+            let members = query.members(c);
+            assert!(members.windows(2).all(|w| w[0] < w[1]));
+            assert!(members.iter().all(|m| query.equivalent(members[0], *m)));
+        }
+        // the return edge 15 -> 0 is separated from any edge of another class by a cycle through one of them
+        let back = graph.find_edge(NodeIndex::new(15), NodeIndex::new(0)).unwrap();
+        let other = graph.edge_indices().find(|e| query.class(*e).is_some() && !query.equivalent(back, *e)).unwrap();
+        let cycle = query.witness_cycle(back, other).unwrap();
+        assert!(is_closed(&query, &cycle));
+        assert_eq!(query.describe_cycle(&graph, &cycle).split(") c:").count(), cycle.len() + 1);
+        assert_eq!(query.class(EdgeIndex::new(graph.edge_count() + 10)), None);
+    }
+}
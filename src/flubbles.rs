@@ -0,0 +1,276 @@
+use petgraph::graph::EdgeIndex;
+
+use crate::bidirected::{BiGraph, Handle, Orientation};
+use crate::blocks::cycle_equivalence_undirected;
+use crate::node_regions::NodeStructureTree;
+use crate::traversals::TraversalOptions;
+use crate::variants::classify_flubbles;
+
+// a flubble: a part of a bidirected graph that walks can only enter through one side of its entry node
+// and only leave through one side of its exit node
+#[derive(Clone,Debug)]
+pub struct Flubble {
+    pub entry: Handle, // the entry node in the orientation a walk into the flubble leaves it
+    pub exit: Handle, // the exit node in the orientation a walk out of the flubble enters it
+    pub class: usize, // cycle equivalence class of the black edges of the boundary nodes
+    pub level: usize, // 0 for a flubble inside no other
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub nodes: Vec<usize>, // node indexes directly inside, not in a child; boundary nodes are in the parent
+}
+
+impl Flubble {
+    // a flubble with nothing inside, two nodes joined directly
+    pub fn is_trivial(&self) -> bool {
+        self.nodes.is_empty() && self.children.is_empty()
+    }
+}
+
+// the flubble tree of a bidirected graph, after Mwaniki, Garrison and Pisanti: cycle equivalence on the
+// bi-edged graph, where each node is a black edge between its two sides, and the regions bounded by black
+// edges adjacent in a class, nested as in the program structure tree
+// the bi-edged graph has no direction, so the classes are put in order by an undirected depth first
+// search from the root vertex joined to the free sides: it crosses the edges of a class in their order
+// around the cycles through them
+#[derive(Clone,Debug)]
+pub struct FlubbleTree {
+    pub flubbles: Vec<Flubble>, // preorder
+    pub top: Vec<usize>, // flubbles inside no other
+    pub outside: Vec<usize>, // node indexes in no flubble
+    pub node_flubble: Vec<Option<usize>>, // flubble directly holding each node by node index
+}
+
+impl FlubbleTree {
+    pub fn new(graph: &BiGraph) -> FlubbleTree {
+        let node_count = graph.node_count();
+        let mut bi_edged = graph.bi_edged();
+        cycle_equivalence_undirected(&mut bi_edged);
+
+        // undirected depth first order of the edges, each with the vertex it is first examined from
+        let mut incident = vec![Vec::new(); bi_edged.node_count()];
+        for e in bi_edged.edge_indices() {
+            let edge = bi_edged[e].borrow();
+            incident[edge.from].push(e.index());
+            if edge.to != edge.from {
+                incident[edge.to].push(e.index());
+            }
+        }
+        let mut order = Vec::new();
+        let mut examined = vec![false; bi_edged.edge_count()];
+        let mut visited = vec![false; bi_edged.node_count()];
+        let roots = (2 * node_count..bi_edged.node_count()).chain(0..2 * node_count);
+        for root in roots {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((v, i)) = stack.last_mut() {
+                if *i == incident[*v].len() {
+                    stack.pop();
+                    continue;
+                }
+                let (v, e) = (*v, incident[*v][*i]);
+                *i += 1;
+                if examined[e] {
+                    continue;
+                }
+                examined[e] = true;
+                order.push((e, v));
+                let edge = bi_edged[EdgeIndex::new(e)].borrow();
+                let w = if edge.from == v { edge.to } else { edge.from };
+                if !visited[w] {
+                    visited[w] = true;
+                    stack.push((w, 0));
+                }
+            }
+        }
+
+        let (tree, sides) = NodeStructureTree::nest(&bi_edged, node_count, &order);
+        // region 0 is the whole graph, so flubble f is region f + 1
+        let orientation = |side: usize, left: Orientation| if side.is_multiple_of(2) { left } else { left.flip() };
+        let mut flubbles: Vec<Flubble> = Vec::new();
+        for (r, region) in tree.regions.iter().enumerate().skip(1) {
+            let (entry, exit) = (region.entry.unwrap(), region.exit.expect("every class closes its regions"));
+            let parent = region.parent.filter(|p| *p > 0).map(|p| p - 1);
+            flubbles.push(Flubble {
+                entry: Handle::new(entry, orientation(sides[r].0, Orientation::Reverse)),
+                exit: Handle::new(exit, orientation(sides[r].1, Orientation::Forward)),
+                class: region.class,
+                level: parent.map_or(0, |p| flubbles[p].level + 1),
+                parent,
+                children: region.children.iter().map(|c| c - 1).collect(),
+                nodes: region.nodes.clone(),
+            });
+        }
+        let top = tree.regions[0].children.iter().map(|c| c - 1).collect();
+        let outside = tree.regions[0].nodes.clone();
+        let node_flubble = tree.node_region.iter().map(|r| r.checked_sub(1)).collect();
+        FlubbleTree { flubbles, top, outside, node_flubble }
+    }
+
+    pub fn len(&self) -> usize {
+        self.flubbles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flubbles.is_empty()
+    }
+
+    // the nodes strictly inside a flubble, its children's included, sorted
+    pub fn inside(&self, f: usize) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![f];
+        while let Some(g) = stack.pop() {
+            nodes.extend(self.flubbles[g].nodes.iter().cloned());
+            for c in self.flubbles[g].children.iter() {
+                nodes.push(self.flubbles[*c].entry.node);
+                nodes.push(self.flubbles[*c].exit.node);
+                stack.push(*c);
+            }
+        }
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    // the tree in dot format, each flubble labelled with its boundary handles, level and variant type
+    pub fn to_dot(&self, graph: &BiGraph) -> String {
+        let types = classify_flubbles(graph, self, &TraversalOptions::default());
+        let mut dot = String::new();
+        dot.push_str("digraph {\n");
+        for (i, f) in self.flubbles.iter().enumerate() {
+            let label = format!("{}..{}|{}", graph.handle_name(f.entry), graph.handle_name(f.exit), f.level);
+            let label = types[i].map_or(label.clone(), |t| format!("{}|{}", label, t));
+            dot.push_str(format!("f{} [label=\"{}\"];\n", i, label).as_str());
+            for c in f.children.iter() {
+                dot.push_str(format!("f{} -> f{};\n", i, c).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the tree as json, one entry per flubble with its boundary handles, level, parent, children, the
+    // names of the nodes directly inside and its variant type
+    pub fn to_json(&self, graph: &BiGraph) -> String {
+        let types = classify_flubbles(graph, self, &TraversalOptions::default());
+        let mut entries = Vec::new();
+        for (i, f) in self.flubbles.iter().enumerate() {
+            let parent = f.parent.map_or("null".to_string(), |p| p.to_string());
+            let children = f.children.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+            let nodes = f.nodes.iter().map(|n| format!("\"{}\"", graph.names[*n])).collect::<Vec<String>>();
+            let variant_type = types[i].map_or("null".to_string(), |t| format!("\"{}\"", t));
+            entries.push(format!(
+                "{{\"id\":{},\"entry\":\"{}\",\"exit\":\"{}\",\"level\":{},\"parent\":{},\"children\":[{}],\"nodes\":[{}],\"type\":{}}}",
+                i, graph.handle_name(f.entry), graph.handle_name(f.exit), f.level, parent, children.join(","), nodes.join(","), variant_type
+            ));
+        }
+        format!("{{\"flubbles\":[{}]}}\n", entries.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Rng;
+
+    // sides reachable from a side of the bi-edged graph without crossing the removed edges
+    fn reachable(graph: &BiGraph, from: usize, removed: &[usize]) -> Vec<bool> {
+        let bi_edged = graph.bi_edged();
+        let mut seen = vec![false; bi_edged.node_count()];
+        seen[from] = true;
+        let mut stack = vec![from];
+        while let Some(v) = stack.pop() {
+            for e in bi_edged.edge_indices() {
+                let edge = bi_edged[e].borrow();
+                if removed.contains(&e.index()) || (edge.from != v && edge.to != v) {
+                    continue;
+                }
+                let w = if edge.from == v { edge.to } else { edge.from };
+                if !seen[w] {
+                    seen[w] = true;
+                    stack.push(w);
+                }
+            }
+        }
+        seen
+    }
+
+    // cutting the black edges of its boundary nodes separates each flubble, entered and left through the
+    // sides given by its handles, from the rest of the graph
+    fn check(graph: &BiGraph, tree: &FlubbleTree) {
+        for (i, f) in tree.flubbles.iter().enumerate() {
+            assert_ne!(f.entry.node, f.exit.node);
+            assert_eq!(f.level, f.parent.map_or(0, |p| tree.flubbles[p].level + 1));
+            let seen = reachable(graph, f.entry.exit_side(), &[f.entry.node, f.exit.node]);
+            assert!(seen[f.exit.entry_side()], "flubble {}", i);
+            assert!(!seen[f.entry.entry_side()] && !seen[f.exit.exit_side()], "flubble {}", i);
+            let inside = (0..graph.node_count()).filter(|v| seen[2 * v] && seen[2 * v + 1]).collect::<Vec<usize>>();
+            assert_eq!(inside, tree.inside(i));
+        }
+        let mut counted = tree.outside.len() + tree.flubbles.iter().map(|f| f.nodes.len()).sum::<usize>();
+        assert_eq!(counted, graph.node_count());
+        counted = tree.top.len();
+        assert_eq!(counted, tree.flubbles.iter().filter(|f| f.parent.is_none()).count());
+    }
+
+    #[test]
+    fn nested_bubbles() {
+        // a -> {b, c} -> d, with b itself a bubble b1 -> {b2, b3} -> b4 and c read in reverse
+        let text = "S\ta\t*\nS\tb1\t*\nS\tb2\t*\nS\tb3\t*\nS\tb4\t*\nS\tc\t*\nS\td\t*\n\
+            L\ta\t+\tb1\t+\t0M\nL\tb1\t+\tb2\t+\t0M\nL\tb1\t+\tb3\t+\t0M\nL\tb2\t+\tb4\t+\t0M\nL\tb3\t+\tb4\t+\t0M\n\
+            L\tb4\t+\td\t+\t0M\nL\ta\t+\tc\t-\t0M\nL\tc\t-\td\t+\t0M\n";
+        let graph = BiGraph::from_gfa(text).unwrap();
+        let tree = FlubbleTree::new(&graph);
+        check(&graph, &tree);
+        let name = |h: Handle| graph.handle_name(h);
+        let bounds = tree.flubbles.iter().map(|f| (name(f.entry), name(f.exit), f.level)).collect::<Vec<(String, String, usize)>>();
+        assert!(bounds.contains(&("a+".to_string(), "d+".to_string(), 0)), "{:?}", bounds);
+        assert!(bounds.contains(&("b1+".to_string(), "b4+".to_string(), 1)), "{:?}", bounds);
+        let outer = tree.flubbles.iter().position(|f| name(f.entry) == "a+").unwrap();
+        assert_eq!(tree.inside(outer), vec![1, 2, 3, 4, 5]);
+        assert!(tree.to_json(&graph).contains("\"entry\":\"a+\",\"exit\":\"d+\",\"level\":0,\"parent\":null"));
+        // without sequences no flubble varies
+        assert!(tree.to_json(&graph).contains("\"type\":null"));
+    }
+
+    #[test]
+    fn reversed_boundary() {
+        // the same bubble walked from d- to a-: boundary orientations follow the links
+        let text = "L\td\t-\tb\t+\t0M\nL\td\t-\tc\t+\t0M\nL\tb\t+\ta\t-\t0M\nL\tc\t+\ta\t-\t0M\n";
+        let graph = BiGraph::from_gfa(text).unwrap();
+        let tree = FlubbleTree::new(&graph);
+        check(&graph, &tree);
+        assert_eq!(tree.len(), 1);
+        let f = &tree.flubbles[0];
+        let ends = [graph.handle_name(f.entry), graph.handle_name(f.exit)];
+        assert!(ends == ["d-", "a-"] || ends == ["a+", "d+"], "{:?}", ends);
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut rng = Rng::new(44);
+        let mut nested = 0;
+        for _ in 0..200 {
+            let n = 2 + rng.below(9);
+            let mut graph = BiGraph::new();
+            for v in 0..n {
+                graph.add_node(&v.to_string());
+            }
+            let orientation = |rng: &mut Rng| if rng.below(4) == 0 { Orientation::Reverse } else { Orientation::Forward };
+            for v in 1..n {
+                let from = Handle::new(rng.below(v), orientation(&mut rng));
+                graph.add_link(from, Handle::new(v, orientation(&mut rng)));
+            }
+            for _ in 0..rng.below(n + 1) {
+                let from = Handle::new(rng.below(n), orientation(&mut rng));
+                graph.add_link(from, Handle::new(rng.below(n), orientation(&mut rng)));
+            }
+            let tree = FlubbleTree::new(&graph);
+            check(&graph, &tree);
+            nested += tree.flubbles.iter().filter(|f| f.level > 0 && !f.is_trivial()).count();
+        }
+        assert!(nested > 0);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use crate::FlowGraph;
+use crate::bidirected::{BiGraph, Handle};
+use crate::digraph::DiGraph;
+use crate::regions::RegionTable;
+
+// one pass of a path through a region
+#[derive(Clone,Debug,PartialEq)]
+pub struct PathTraversal {
+    pub path: usize,
+    pub allele: usize,
+    pub start: usize, // position of the first step inside the region in the path
+    pub end: usize, // position after the last
+    pub reversed: bool, // the path walks the region from exit to entry
+}
+
+// the traversals the paths take through one region: each distinct allele, and how often each path takes it
+#[derive(Clone,Debug)]
+pub struct RegionHaplotypes {
+    pub region: usize, // index in the region table
+    pub alleles: Vec<Vec<Handle>>, // steps inside the region read from entry to exit, in order of first use
+    pub counts: Vec<Vec<usize>>, // allele by path: times the path takes the allele
+    pub traversals: Vec<PathTraversal>, // by path, then along it
+}
+
+impl RegionHaplotypes {
+    // the alleles a path takes, each as often as it takes it
+    pub fn alleles_of(&self, path: usize) -> Vec<usize> {
+        (0..self.alleles.len()).flat_map(|a| std::iter::repeat_n(a, self.counts[a][path])).collect()
+    }
+
+    pub fn is_traversed_by(&self, path: usize) -> bool {
+        self.counts.iter().any(|c| c[path] > 0)
+    }
+
+    pub fn traversals_of(&self, path: usize) -> Vec<&PathTraversal> {
+        self.traversals.iter().filter(|t| t.path == path).collect()
+    }
+
+    // each allele written as in a GFA P line
+    pub fn to_strings(&self, bigraph: &BiGraph) -> Vec<String> {
+        self.alleles.iter().map(|a| a.iter().map(|h| bigraph.handle_name(*h)).collect::<Vec<String>>().join(",")).collect()
+    }
+}
+
+// whether the run of steps start..end walks a region from its exit edge to its entry edge: told by the
+// step before the run when it is across a boundary edge, then the step after it, else by the ends of the
+// run, else for a single step by whether it reads its flow node against the flow; a run that leaves
+// through the boundary it came in by keeps the direction it came in
+fn is_reversed(flow: &[usize], start: usize, end: usize, entry: (usize, usize), exit: (usize, usize), against: &[bool]) -> bool {
+    let (before, after) = (start.checked_sub(1).map(|i| flow[i]), flow.get(end).cloned());
+    if before == Some(exit.1) || before == Some(entry.0) {
+        return before == Some(exit.1);
+    }
+    if after == Some(entry.0) || after == Some(exit.1) {
+        return after == Some(entry.0);
+    }
+    let (first, last) = (flow[start], flow[end - 1]);
+    if first != last {
+        return first != entry.1 && (first == exit.0 || last == entry.1);
+    }
+    against[start]
+}
+
+// the paths of a bidirected graph projected onto the structure tree of its flow graph
+#[derive(Clone,Debug)]
+pub struct HaplotypeMatrix {
+    pub paths: Vec<String>, // path names, the columns of every region
+    pub regions: Vec<RegionHaplotypes>, // by table index; the root has no alleles
+}
+
+impl HaplotypeMatrix {
+    // the graph must be bigraph.to_flow_graph() and the table built from its structure tree; a path
+    // traverses a region each time a run of its steps stays inside it, and a run walking the region from
+    // exit to entry is read backwards with every step flipped
+    pub fn new(bigraph: &BiGraph, graph: &FlowGraph, table: &RegionTable) -> HaplotypeMatrix {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let layout = bigraph.flow_layout();
+        let path_count = bigraph.paths.len();
+        let mut regions = (0..table.len())
+            .map(|r| RegionHaplotypes { region: r, alleles: Vec::new(), counts: Vec::new(), traversals: Vec::new() })
+            .collect::<Vec<RegionHaplotypes>>();
+        let mut allele_index = vec![HashMap::new(); table.len()];
+
+        // the regions from the root down to each region
+        let mut chains: Vec<Vec<usize>> = Vec::with_capacity(table.len());
+        for r in 0..table.len() {
+            let mut chain = table.regions[r].parent.map_or(Vec::new(), |p| chains[p].clone());
+            chain.push(r);
+            chains.push(chain);
+        }
+
+        for (p, path) in bigraph.paths.paths.iter().enumerate() {
+            let flow = path.steps.iter().map(|h| layout.node(*h)).collect::<Vec<usize>>();
+            let against = path.steps.iter().map(|h| layout.along(*h).is_none()).collect::<Vec<bool>>();
+            let mut runs = Vec::new();
+            // the regions holding the previous step, each with the position its run started at
+            let mut open: Vec<(usize, usize)> = Vec::new();
+            for (i, n) in flow.iter().enumerate() {
+                let chain = &chains[table.node_region[*n]];
+                let common = open.iter().zip(chain.iter()).take_while(|((a, _), b)| a == *b).count();
+                runs.extend(open.drain(common..).map(|(r, start)| (r, start, i)));
+                open.extend(chain[common..].iter().map(|r| (*r, i)));
+            }
+            runs.extend(open.into_iter().map(|(r, start)| (r, start, flow.len())));
+
+            for (r, start, end) in runs {
+                let region = &table.regions[r];
+                let (entry, exit) = match (region.entry, region.exit) {
+                    (Some(entry), Some(exit)) => (digraph.edges[entry].unwrap(), digraph.edges[exit].unwrap()),
+                    _ => continue,
+                };
+                let mut allele = path.steps[start..end].to_vec();
+                let reversed = is_reversed(&flow, start, end, entry, exit, &against);
+                if reversed {
+                    allele = allele.into_iter().rev().map(|h| h.flip()).collect();
+                }
+                let haplotypes = &mut regions[r];
+                let a = *allele_index[r].entry(allele.clone()).or_insert_with(|| {
+                    haplotypes.alleles.push(allele);
+                    haplotypes.counts.push(vec![0; path_count]);
+                    haplotypes.alleles.len() - 1
+                });
+                haplotypes.counts[a][p] += 1;
+                haplotypes.traversals.push(PathTraversal { path: p, allele: a, start, end, reversed });
+            }
+        }
+        HaplotypeMatrix { paths: bigraph.paths.names().into_iter().map(String::from).collect(), regions }
+    }
+
+    // the matrix of every region with alleles as tab separated text: a header of path names, then a row
+    // per allele of region id, allele steps and counts
+    pub fn to_tsv(&self, bigraph: &BiGraph, table: &RegionTable) -> String {
+        let mut tsv = format!("region\tallele\t{}\n", self.paths.join("\t"));
+        for haplotypes in self.regions.iter() {
+            for (allele, counts) in haplotypes.to_strings(bigraph).iter().zip(haplotypes.counts.iter()) {
+                let counts = counts.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+                tsv.push_str(format!("{}\t{}\t{}\n", table.regions[haplotypes.region].id, allele, counts.join("\t")).as_str());
+            }
+        }
+        tsv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    // s -> a -> (b1 -> (x | y) -> b2 | c) -> d -> t, with the second haplotype of sample1 walked backwards
+    const GFA: &str = "S\ts\t*\nS\ta\t*\nS\tb1\t*\nS\tx\t*\nS\ty\t*\nS\tb2\t*\nS\tc\t*\nS\td\t*\nS\tt\t*\n\
+        L\ts\t+\ta\t+\t0M\nL\ta\t+\tb1\t+\t0M\nL\tb1\t+\tx\t+\t0M\nL\tb1\t+\ty\t+\t0M\nL\tx\t+\tb2\t+\t0M\n\
+        L\ty\t+\tb2\t+\t0M\nL\tb2\t+\td\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tc\t+\td\t+\t0M\nL\td\t+\tt\t+\t0M\n\
+        P\tref\ts+,a+,b1+,x+,b2+,d+,t+\t*\n\
+        W\tsample1\t1\tchr1\t0\t5\t>s>a>c>d>t\n\
+        W\tsample1\t2\tchr1\t0\t7\t<t<d<b2<y<b1<a<s\n\
+        P\tsample2#1#chr1\ts+,a+,b1+,x+,b2+,d+,t+\t*\n";
+
+    fn region_of(bigraph: &BiGraph, table: &RegionTable, names: &[&str]) -> usize {
+        let mut nodes = names.iter().map(|n| bigraph.flow_node(bigraph.node_index(n).unwrap())).collect::<Vec<usize>>();
+        nodes.sort();
+        (0..table.len()).find(|r| table.all_nodes(*r) == nodes).unwrap()
+    }
+
+    #[test]
+    fn nested_bubbles() {
+        let bigraph = BiGraph::from_gfa(GFA).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let matrix = HaplotypeMatrix::new(&bigraph, &graph, &table);
+        assert_eq!(matrix.paths, vec!["ref", "sample1#1#chr1", "sample1#2#chr1", "sample2#1#chr1"]);
+
+        let outer = &matrix.regions[region_of(&bigraph, &table, &["a", "b1", "x", "y", "b2", "c", "d"])];
+        assert_eq!(outer.to_strings(&bigraph), vec!["a+,b1+,x+,b2+,d+", "a+,c+,d+", "a+,b1+,y+,b2+,d+"]);
+        assert_eq!(outer.counts, vec![vec![1, 0, 0, 1], vec![0, 1, 0, 0], vec![0, 0, 1, 0]]);
+        assert_eq!(outer.alleles_of(2), vec![2]);
+        assert_eq!(outer.traversals_of(2), vec![&PathTraversal { path: 2, allele: 2, start: 1, end: 6, reversed: true }]);
+
+        let inner = &matrix.regions[region_of(&bigraph, &table, &["b1", "x", "y", "b2"])];
+        assert_eq!(inner.to_strings(&bigraph), vec!["b1+,x+,b2+", "b1+,y+,b2+"]);
+        assert!(!inner.is_traversed_by(1));
+        assert!(matrix.regions[0].alleles.is_empty());
+
+        let tsv = matrix.to_tsv(&bigraph, &table);
+        assert!(tsv.starts_with("region\tallele\tref\tsample1#1#chr1"));
+        assert!(tsv.contains("\tb1+,y+,b2+\t0\t0\t1\t0\n"));
+    }
+
+    #[test]
+    fn reversed_runs() {
+        // a region of nodes 2 to 4, entered by the edge 1 -> 2 and left by 4 -> 5
+        let (entry, exit) = ((1, 2), (4, 5));
+        let along = |flow: &[usize]| vec![false; flow.len()];
+        let against = |flow: &[usize]| vec![true; flow.len()];
+        // in and back out through the entry, or through the exit
+        let flow = [0, 1, 2, 3, 2, 1, 0];
+        assert!(!is_reversed(&flow, 2, 5, entry, exit, &along(&flow)));
+        let flow = [6, 5, 4, 3, 4, 5, 6];
+        assert!(is_reversed(&flow, 2, 5, entry, exit, &against(&flow)));
+        // with only one neighbour, at either end of the path
+        assert!(is_reversed(&[3, 2, 1], 0, 2, entry, exit, &against(&[3, 2, 1])));
+        assert!(!is_reversed(&[2, 3, 4, 5], 0, 3, entry, exit, &along(&[2, 3, 4, 5])));
+        // a single step, told by its neighbours, else by the way it reads its node
+        assert!(!is_reversed(&[1, 3, 5], 1, 2, entry, exit, &against(&[1, 3, 5])));
+        assert!(is_reversed(&[5, 3, 1], 1, 2, entry, exit, &along(&[5, 3, 1])));
+        assert!(is_reversed(&[3], 0, 1, entry, exit, &against(&[3])));
+        assert!(!is_reversed(&[3], 0, 1, entry, exit, &along(&[3])));
+    }
+
+    #[test]
+    fn revisits() {
+        // the path goes through the inner bubble by x, turns around on d and comes back through it by y
+        let gfa = format!("{}P\tloop\ts+,a+,b1+,x+,b2+,d+,d-,b2-,y-,b1-,a-,s-\t*\n", GFA);
+        let bigraph = BiGraph::from_gfa(&gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let matrix = HaplotypeMatrix::new(&bigraph, &graph, &table);
+        let p = bigraph.paths.path_index("loop").unwrap();
+
+        let inner = &matrix.regions[region_of(&bigraph, &table, &["b1", "x", "y", "b2"])];
+        let passes = inner.traversals_of(p);
+        assert_eq!(passes.len(), 2);
+        assert_eq!((passes[0].start, passes[0].end, passes[0].reversed), (2, 5, false));
+        assert_eq!((passes[1].start, passes[1].end, passes[1].reversed), (7, 10, true));
+        let alleles = inner.to_strings(&bigraph);
+        assert_eq!((alleles[passes[0].allele].as_str(), alleles[passes[1].allele].as_str()), ("b1+,x+,b2+", "b1+,y+,b2+"));
+        assert_eq!(inner.alleles_of(p), vec![0, 1]);
+
+        // the outer bubble holds the whole turn, which comes back out through its entry
+        let outer = &matrix.regions[region_of(&bigraph, &table, &["a", "b1", "x", "y", "b2", "c", "d"])];
+        let passes = outer.traversals_of(p);
+        assert_eq!(passes.len(), 1);
+        assert_eq!((passes[0].start, passes[0].end, passes[0].reversed), (1, 11, false));
+    }
+}
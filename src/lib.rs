@@ -0,0 +1,1208 @@
+use petgraph::Graph;
+use petgraph::Undirected;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+//use petgraph::Direction::{Incoming, Outgoing};
+use petgraph::visit::{DfsEvent, EdgeRef};
+
+use dot_writer::{DotWriter, Attributes, Shape};
+
+use std::fs::File;
+use std::io::Write;
+use std::process::Command;
+use std::fmt;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use sequence::PackedSequence;
+//use std::collections::LinkedList;
+
+//use std::collections::linked_list::{Cursor, CursorMut};
+
+pub mod bed;
+pub mod bidirected;
+pub mod blocks;
+pub mod cactus;
+pub mod chains;
+pub mod control_dependence;
+pub mod cuts;
+pub mod dataflow;
+pub mod digraph;
+pub mod dominators;
+pub mod equivalence;
+pub mod flubbles;
+pub mod haplotypes;
+pub mod loops;
+pub mod metrics;
+pub mod node_regions;
+pub mod paths;
+pub mod regions;
+pub mod rpst;
+pub mod sequence;
+pub mod spqr;
+pub mod ssa;
+pub mod structurize;
+pub mod superbubbles;
+#[cfg(test)]
+mod test_utils;
+pub mod traversals;
+pub mod triconnected;
+pub mod variants;
+pub mod vcf;
+pub mod verify;
+
+#[derive(Clone,Debug)]
+pub struct Node {
+    pub id: usize, // external id -- not used in algorithm
+    pub dfsnum: usize, // depth in DFS
+    pub blist: BracketList, // list of bracket edges
+    pub hi: usize, // highest dfsnum of any descendant
+    pub sequence: PackedSequence, // DNA of the node, empty for graphs without sequence
+}
+
+// implement default constructor for Node that takes only the id
+impl Node {
+    pub fn new(id_: usize) -> Node {
+        Node {
+            id: id_,
+            dfsnum: 0,
+            blist: BracketList::new(),
+            hi: usize::MAX,
+            sequence: PackedSequence::default(),
+        }
+    }
+}
+
+// display method for Node
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Node: id: {}, dfs: {}, hi: {}, blist: {}", self.id, self.dfsnum, self.hi, self.blist)
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct Edge {
+    pub from: usize, // index of from node in graph
+    pub to: usize, // index of to node in graph
+    pub class: usize, // cycle equivalence class
+    pub recent_size: usize, // size of bracket list when this edge was most recently the topmost bracket
+    pub recent_class: usize, // equivalence class number of tree edge for which this edge was most recently the topmost bracket
+    pub is_tree_edge: bool, // is this edge a tree edge?
+    pub is_backedge: bool, // is this edge a backedge?
+    pub is_capping: bool, // is this edge a capping backedge?
+    // pointer to bracket list cell where this edge is stored, which in big-O would make deleting it faster
+    // but now we're using a vector implementation and iterating over it to remove entries, which has other efficiencies
+    //blist_cell: Option<CursorMut<EdgeList>>,
+}
+
+// implement default constructor for Edge that takes only from and to
+impl Edge {
+    pub fn new(_from: usize, _to: usize) -> Edge {
+        Edge {
+            from: _from,
+            to: _to,
+            class: 0,
+            recent_size: 0,
+            recent_class: 0,
+            is_tree_edge: false,
+            is_backedge: false,
+            is_capping: false,
+//            blist_cell: None,
+        }
+    }
+}
+
+// display method for Edge that shows all attributes
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Edge: from: {}, to: {}, class: {}, recent_size: {}, recent_class: {}, is_backedge: {}, is_capping: {}", self.from, self.to, self.class, self.recent_size, self.recent_class, self.is_backedge, self.is_capping)
+    }
+}
+
+pub type EdgeList = Vec<Rc<RefCell<Edge>>>;
+
+pub type FlowGraph = Graph::<Rc<RefCell<Node>>, Rc<RefCell<Edge>>, Undirected>;
+
+#[derive(Clone,Debug,Default)]
+pub struct BracketList {
+    pub brackets: EdgeList,
+}
+
+// display method for BracketList
+impl fmt::Display for BracketList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut result = String::new();
+        for bracket in self.brackets.iter() {
+            // show the to and from ids of the edges
+            result.push_str(&format!("({}, {}), ", bracket.borrow().from, bracket.borrow().to));
+        }
+        write!(f, "{}", result)
+    }
+}
+
+impl BracketList {
+    pub fn new() -> Self {
+        Self { brackets: EdgeList::new() }
+    }
+
+    pub fn size(&self) -> usize {
+        self.brackets.len()
+    }
+
+    pub fn push(&mut self, edge: Rc<RefCell<Edge>>) {
+        self.brackets.push(edge);
+    }
+
+    pub fn top(&self) -> Option<Rc<RefCell<Edge>>> {
+        self.brackets.last().cloned()
+    }
+
+    pub fn delete(&mut self, edge: Rc<RefCell<Edge>>) {
+        for (i, bracket) in self.brackets.iter().enumerate() {
+            if Rc::ptr_eq(&edge, bracket) {
+                self.brackets.remove(i);
+                break;
+            }
+        }
+    }
+    
+    pub fn concat(&mut self, other: &Self) {
+        self.brackets.append(&mut other.brackets.clone());
+    }
+}
+
+// function that writes the petgraph to a dot format file
+pub fn write_dot(graph: &FlowGraph,
+             file_name: &str,
+             ftype: &str) {
+    println!("write_dot: {}", file_name);
+    let mut output_bytes = Vec::new();
+    {
+        let mut writer = DotWriter::from(&mut output_bytes);
+        //writer.set_pretty_print(false);
+        let mut agraph = writer.graph();
+        for edge in graph.edge_references() {
+            let e = edge.weight().borrow();
+            let f = graph[NodeIndex::new(e.from)].borrow().id.to_string();
+            let t = graph[NodeIndex::new(e.to)].borrow().id.to_string();
+            // label the edge with a compact description of key attributes
+            let mut label = String::new();
+            //label.push('[');
+            if e.is_tree_edge {
+                // use tree emoji
+                label.push('🌿');
+            }
+            if e.is_backedge {
+                // use back emoji
+                label.push('🙃');
+            }
+            if e.is_capping {
+                // use hat emoji
+                label.push('🎩');
+            }
+            if e.class > 0 {
+                label.push_str(" c:");
+                label.push_str(&e.class.to_string());
+            }
+            if e.recent_class > 0 {
+                label.push_str(" r:");
+                label.push_str(&e.recent_class.to_string());
+            }
+            if e.recent_size > 0 {
+                label.push_str(" s:");
+                label.push_str(&e.recent_size.to_string());
+            }
+            //label.push(']');
+            agraph.edge(&f, &t).attributes().set("label", label.as_str(), true);
+        }
+        for node in graph.node_indices() {
+            let n = graph[node].borrow();
+            let id = n.id.to_string();
+            // build a label that displays all attributes compactly
+            // pretty print the blist so that it fits in the node
+            let mut label = String::new();
+            label.push_str(&format!("id: {}, dfs: {}, hi: {}\nblist: {}", n.id, n.dfsnum, n.hi, n.blist));
+            //let mut blist = String::new();
+            //for bracket in n.blist.brackets.iter() {
+            //    blist.push_str(&format!("{} ", bracket.borrow()));
+            //}
+            //label.push_str(&format!("{{{}}}", blist));
+            agraph.node_named(id)
+                .set_shape(Shape::Rectangle)
+                .set_label(label.as_str());
+        }
+    }
+    let mut file = File::create(file_name).unwrap();
+    file.write_all(&output_bytes).unwrap();
+    // run dot to generate pdf
+    Command::new("dot")
+        .arg(format!("-T{}", ftype).as_str())
+        .arg(file_name)
+        .arg("-o")
+        .arg(file_name.to_owned() + format!(".{}", ftype).as_str())
+        .output()
+        .expect("failed to execute process");
+}
+
+pub fn cycle_equivalence(graph: &mut FlowGraph,
+                     rev_order: &[NodeIndex]) {
+    let mut curr_class = 1;
+    //closure for next_class()
+    let mut next_class = || {
+        let result = curr_class;
+        curr_class += 1;
+        result
+    };
+
+    // hack... collect all nodes in a vector by dfsnum
+    let order = rev_order.iter().rev().cloned().collect::<Vec<NodeIndex>>();
+
+    // perform an undirected depth-fist search on G
+    // for each node n in reverse depth-first order do
+    // /* compute n.hi */
+    //    hi_0 := min {t.dfsnum | (n, t) is a backedge }; // where t is a predecessor of n in the DFS tree
+    //    hi_1 := min {c.hi | c is a child of n };
+    //    n.hi := min {hi_0, hi_1};
+    //    hichild := any child c of n having c.hi == hi_1;
+    //    hi_2 := min {c.hi | c is a child of n other than hichild };
+    for ni in rev_order.iter() {
+        let node = graph[*ni].clone();
+        let nid = node.borrow().id;
+        let ndfsnum = node.borrow().dfsnum;
+        // undirected edges
+        let mut edges = Vec::new(); // all edges
+        let mut children = Vec::new(); // just children in dfs tree
+        for edge in graph.edges(*ni) {
+            let edge = edge.weight().clone();
+            let e = edge.borrow();
+            // the traversal is undirected, so set from equal to the other node index
+            // use nid to check which is the other node
+            let from = if graph[NodeIndex::new(e.from)].borrow().id == nid { e.to } else { e.from };
+            let other = graph[NodeIndex::new(from)].clone();
+            // collect children
+            if e.is_tree_edge && other.borrow().dfsnum > ndfsnum {
+                children.push(graph[NodeIndex::new(from)].clone());
+            }
+            // collect all edges
+            edges.push((edge.clone(), graph[NodeIndex::new(from)].clone(), from));
+        }
+        let mut hi_0 = usize::MAX;
+        let mut hi_1 = usize::MAX;
+        let mut hi_2 = usize::MAX;
+        let mut hichild = usize::MAX;
+        for (edge, other, _) in edges.iter() {
+            let edge = edge.borrow();
+            let other = other.borrow();
+            // get min of hi_0 over backedges from n to its ancestors
+            if edge.is_backedge && other.dfsnum < ndfsnum {
+                // print the backedge and dfsnum
+                hi_0 = hi_0.min(other.dfsnum);
+            }
+            // the other is a child of current node
+            // the edge should be a tree edge no?
+            if other.dfsnum > ndfsnum && edge.is_tree_edge {
+                // print the tree edge and other.hi
+                hi_1 = hi_1.min(other.hi);
+            }
+        }
+        node.borrow_mut().hi = hi_0.min(hi_1);
+        for child in children.iter() {
+            let child = child.borrow();
+            if child.hi == hi_1 {
+                hichild = child.id;
+                break;
+            }
+        }
+        for child in children.iter() {
+            let child = child.borrow();
+            if child.id != hichild {
+                hi_2 = hi_2.min(child.hi);
+            }
+        }
+        // /* compute bracketlist */
+        // n.blist := create();
+        // for each child c of n do
+        //   n.blist := concat(c.blist, n.blist);
+        // endfor
+        for child in children.iter() {
+            let child = child.borrow();
+            node.borrow_mut().blist.concat(&child.blist.clone());
+        }
+        // for each capping backedge d from a descendent of n to n, delete backedge d from n.blist
+        for (edge_, other, _) in edges.iter() {
+            let edge = edge_.borrow();
+            let other = other.borrow();
+            if other.dfsnum > ndfsnum && edge.is_backedge && edge.is_capping {
+                node.borrow_mut().blist.delete(edge_.clone());
+            }
+        }
+        // for each backedge b from a descendant of n to n
+        // delete it from the node bracketlist n.blist
+        // if b.class is not defined (==0), then set b.class to be a new class
+        for (edge_, other, _) in edges.iter() {
+            let mut edge = edge_.borrow_mut();
+            let other = other.borrow();
+            if other.dfsnum > ndfsnum && edge.is_backedge {
+                // delete it from the node bracketlist n.blist
+                node.borrow_mut().blist.delete(edge_.clone());
+                if edge.class == 0 {
+                    edge.class = next_class();
+                }
+            }
+        }
+        // for each backedge e from n to an ancestor of n
+        // push the edge onto the node bracketlist n.blist
+        for (edge_, other, _) in edges.iter() {
+            let edge = edge_.borrow();
+            let other = other.borrow();
+            if other.dfsnum < ndfsnum && edge.is_backedge {
+                node.borrow_mut().blist.push(edge_.clone());
+            }
+        }
+        // if hi_2 < hi_0 then we create a capping backedge and add it to the graph
+        // hi_2 can be n itself when a second child only has brackets ending at n, and then no capping is needed
+        if hi_2 < hi_0 && hi_2 < ndfsnum {
+            // the capping edge runs between node indexes, not external ids
+            let mut e = Edge::new(ni.index(), order[hi_2].index());
+            e.is_backedge = true;
+            e.is_capping = true;
+            //e.class = next_class();
+            let edge = Rc::new(RefCell::new(e));
+            graph.add_edge(*ni, order[hi_2], edge.clone());
+            node.borrow_mut().blist.push(edge.clone());
+            // add it to our edge list
+            edges.push((edge.clone(), node.clone(), hi_2));
+        }
+        // determine the class for edge from parent(n) to n
+        // if n is not the root of dfs tree
+        
+        if ndfsnum != 0 {
+            // find the parent, which will be a node with a tree edge to this node where the dfsnum is less than this node's dfsnum
+            // let e be the tree edge from parent(n) to n
+            let mut e = Rc::new(RefCell::new(Edge::new(0, 0)));
+            for (edge_, other, _) in edges.iter() {
+                let edge = edge_.borrow();
+                let other = other.borrow();
+                if edge.is_tree_edge && other.dfsnum < ndfsnum {
+                    e = edge_.clone();
+                    break;
+                }
+            }
+            // set b to the top of the node blist
+            let b = node.borrow().blist.top().unwrap();
+            // if b recent size is not the size of the node blist
+            if b.borrow().recent_size != node.borrow().blist.size() {
+                // set b.recent_size to the size of the node blist
+                b.borrow_mut().recent_size = node.borrow().blist.size();
+                // set b.class to a new class
+                b.borrow_mut().recent_class = next_class();
+            }
+            // set e.class to b.recent_class
+            e.borrow_mut().class = b.borrow().recent_class;
+            if b.borrow().recent_size == 1 {
+                b.borrow_mut().class = e.borrow().class;
+            }
+        }
+    }
+}
+
+// region structure
+#[derive(Debug)]
+pub struct SeSeRegion {
+    pub id: usize,
+    pub parent: Option<Rc<RefCell<SeSeRegion>>>,
+    pub children: Vec<Rc<RefCell<SeSeRegion>>>,
+//    backedges: Vec<Rc<RefCell<Edge>>>,
+    pub nodes: Vec<Rc<RefCell<Node>>>,
+    pub class: usize,
+    pub entry: Option<EdgeIndex>, // boundary edge through which the traversal entered the region
+    pub exit: Option<EdgeIndex>, // boundary edge through which the traversal left the region
+    pub canonical: bool, // false for a sequential composition or maximal region made of several canonical regions
+}
+
+impl SeSeRegion {
+    pub fn new(id_: usize, class_: usize) -> SeSeRegion {
+        SeSeRegion {
+            id: id_,
+            parent: None,
+            children: Vec::new(),
+//            backedges: Vec::new(),
+            nodes: Vec::new(),
+            class: class_,
+            entry: None,
+            exit: None,
+            canonical: true,
+        }
+    }
+}
+
+/*
+impl fmt::Display for SeSeRegion {
+    // fmt display for SeSeRegion
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SeSeRegion {{ id: {}, parent: {:?}, children: {:?}, nodes: {:?}, class: {} }}", self.id, self.parent, self.children, self.nodes, self.class)
+    }
+}
+*/
+
+// structuretree of regions
+#[derive(Debug,Default)]
+pub struct StructureTree {
+    pub root: Option<Rc<RefCell<SeSeRegion>>>,
+}
+
+impl StructureTree {
+    pub fn new() -> StructureTree {
+        StructureTree {
+            root: None,
+        }
+    }
+    // all regions of the tree in preorder, parents before their children
+    pub fn regions(&self) -> Vec<Rc<RefCell<SeSeRegion>>> {
+        let mut result = Vec::new();
+        let mut stack = Vec::new();
+        if let Some(root) = &self.root {
+            stack.push(root.clone());
+        }
+        while let Some(region) = stack.pop() {
+            for child in region.borrow().children.iter().rev() {
+                stack.push(child.clone());
+            }
+            result.push(region);
+        }
+        result
+    }
+    // print the structure tree to dot format to the specified file
+    // and write it to an image using graphviz
+    pub fn print_dot(&self, _graph: &FlowGraph, filename: &str) {
+        let mut dot = String::new();
+        dot.push_str("digraph {\n");
+        dot.push_str("node [shape=record];\n");
+        //let mut iter = 0;
+        let mut stack = Vec::new();
+        let mut seen = HashSet::new();
+        if let Some(root) = &self.root {
+            stack.push(root.clone());
+            seen.insert(root.borrow().id);
+        }
+        while let Some(node) = stack.pop() {
+            let node = node.borrow();
+            // write the tree simply, using the format region_{} with the id for the graphviz node
+            // edges correspond to parent -> child relationships
+            dot.push_str(format!("region_{} [label=\"{{", node.id).as_str());
+            dot.push_str(format!("id: {}", node.id).as_str());
+            dot.push_str(format!("|class: {}", node.class).as_str());
+            if !node.canonical {
+                dot.push_str("|composite");
+            }
+            dot.push_str("|nodes:");
+            for n in node.nodes.iter() {
+                dot.push_str(format!(" {}", n.borrow().id).as_str());
+            }
+            dot.push_str("}\"];\n");
+            if let Some(parent) = &node.parent {
+                let parent = parent.borrow();
+                dot.push_str(format!("region_{} -> region_{};\n", parent.id, node.id).as_str());
+            }
+            for child in node.children.iter() {
+                if !seen.contains(&child.borrow().id) {
+                    stack.push(child.clone());
+                }
+            }
+        }
+        dot.push_str("}\n");
+        let mut file = File::create(filename).unwrap();
+        file.write_all(dot.as_bytes()).unwrap();
+        // write the dot file to a PDF
+        let mut command = Command::new("dot");
+        command.arg("-Tpdf");
+        command.arg(filename);
+        command.arg("-o");
+        command.arg(format!("{}.pdf", filename));
+        command.output().unwrap();
+    }
+}
+
+pub enum GraphEntity {
+    Node(Rc<RefCell<Node>>, NodeIndex),
+    Edge(Rc<RefCell<Edge>>, (NodeIndex, NodeIndex), EdgeIndex),
+}
+
+use std::collections::HashSet;
+
+// options for build_structure_tree_with
+#[derive(Clone,Debug,Default)]
+pub struct StructureTreeOptions {
+    pub write_dot: bool, // write the intermediate graphs and the tree to dot files in the working directory
+    pub per_block: bool, // find cycle equivalence classes in each biconnected block on its own, so bridges are allowed
+    pub granularity: Granularity, // which regions the tree is made of
+}
+
+// the regions a structure tree is made of
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum Granularity {
+    #[default]
+    Canonical, // canonical regions, bounded by adjacent edges of a class
+    Sequential, // canonical regions, each chain of two or more under a sequential composition region
+    Maximal, // maximal regions, each chain of canonical regions merged into one region
+}
+
+pub fn build_structure_tree(graph: &mut FlowGraph) -> StructureTree  {
+    build_structure_tree_with(graph, &StructureTreeOptions::default())
+}
+
+// write the above pseudocode as a rust function, assume we can use our annotations on the graph edges for cycle equivalence classes
+pub fn build_structure_tree_with(graph: &mut FlowGraph, options: &StructureTreeOptions) -> StructureTree  {
+
+    let maybe_write_dot = |graph: &FlowGraph, name: &str, ftype: &str| {
+        if options.write_dot {
+            write_dot(graph, name, ftype);
+        }
+    };
+
+    maybe_write_dot(graph, "graph.dot", "pdf");
+    if options.per_block {
+        blocks::cycle_equivalence_by_block(graph);
+    } else {
+        let dfs_rev_order = dfs_tree(graph);
+        maybe_write_dot(graph, "graph2.dot", "pdf");
+        cycle_equivalence(&mut *graph, &dfs_rev_order);
+    }
+    maybe_write_dot(graph, "graph3.dot", "pdf");
+
+    //let dfs_order = dfs_rev_order.iter().rev();
+
+    // copy the graph, skipping capping backedges
+    // empty graph
+    let mut graph_copy = FlowGraph::new_undirected();
+    // copy each node in the graph
+    for node in graph.node_indices() {
+        graph_copy.add_node(graph[node].clone());
+    }
+
+    for edge in graph.edge_references() {
+        let e = edge.weight().borrow();
+        if !e.is_capping {
+            graph_copy.add_edge(NodeIndex::new(e.from), NodeIndex::new(e.to), edge.weight().clone());
+        }
+    }
+
+    // overwrite the graph with the copy that has no capping edges
+    let graph = &mut graph_copy;
+
+    // write the graph to dot so we can see what we removed
+    maybe_write_dot(graph, "graph4.dot", "pdf");
+
+    // Perform depth-first traversal of the control flow graph
+    // the traversal is directed, following each edge from `from` to `to`, so that the edges of each class
+    // are examined in dominance order: the tail of an edge dominated by another can only be reached through it
+    // get the source node of the graph as the lowest node in the graph
+    let source = NodeIndex::new(0);
+
+    // outgoing edges of each node, in edge index order
+    let mut out_edges = vec![Vec::<EdgeIndex>::new(); graph.node_count()];
+    for edge in graph.edge_references() {
+        out_edges[edge.weight().borrow().from].push(edge.id());
+    }
+
+    // we will then store the ordered list of both edges and nodes that we encounter
+    // nodes are recorded when first discovered and edges when examined from their tail
+    let mut dfs_order = Vec::<GraphEntity>::new();
+    let mut visited = vec![false; graph.node_count()];
+    // start from the source, then from any node it does not reach
+    let starts = std::iter::once(source).chain(graph.node_indices());
+    for start in starts {
+        if visited[start.index()] {
+            continue;
+        }
+        visited[start.index()] = true;
+        dfs_order.push(GraphEntity::Node(graph[start].clone(), start));
+        // stack of (node, position of the next outgoing edge to examine)
+        let mut dfs_stack = vec![(start, 0)];
+        while let Some((node, i)) = dfs_stack.last_mut() {
+            if *i == out_edges[node.index()].len() {
+                dfs_stack.pop();
+                continue;
+            }
+            let id = out_edges[node.index()][*i];
+            *i += 1;
+            let from = *node;
+            let to = NodeIndex::new(graph[id].borrow().to);
+            dfs_order.push(GraphEntity::Edge(graph[id].clone(), (from, to), id));
+            if !visited[to.index()] {
+                visited[to.index()] = true;
+                dfs_order.push(GraphEntity::Node(graph[to].clone(), to));
+                dfs_stack.push((to, 0));
+            }
+        }
+    }
+
+    // Compute cycle equivalence classes for edges in O(E) time
+    //cycle_equivalence(&*graph);
+    // Initialize the root of the program structure tree
+    let mut program_structure_tree = StructureTree::new();
+    // closure to get the next region id
+    let mut curr_id = 0;
+    let mut next_region_id = || {
+        let id = curr_id;
+        curr_id += 1;
+        id
+    };
+
+    /*
+    Since cycle equivalent edges are totally ordered in the control flow graph by dominance and postdominance, each adjacent pair of edges in this order encloses a canonical SESE region.
+    To find canonical regions, we first compute cycle equivalence classes for edges in O(E) time using the algorithm in Figure 4.
+    Any depth-first traversal of the original control flow graph will visit edges in a given cycle equivalence class in order; during this traversal, entry and exit edges of canonical SESE regions are identified.
+    Canonical regions can be organized into a program structure tree such that a region’s parent is the closest containing region and its children are all the regions immediately contained within the region.
+    We discover the nesting relationship during the same depth-first traversal that determines canonical regions.
+    The depth-first search keeps track of the most recently entered region (i.e. the current region).
+    When a region is first entered, we set its parent to the current region and then update the current region to be the region just entered.
+    When a region is exited, the current region is set to be the exited region’s parent.
+    From Theorem 1, it follows that the pushing and popping follows a stack discipline.
+    The topmost SESE region on this stack when DFS reaches the entry node of a SESE region R1 is the name of the smallest SESE region containing R1.
+    Once the depth-first traversal is complete, the program structure tree has been built.
+     */
+
+    let mut seen_classes = HashSet::<usize>::new();
+    let mut firsts = HashSet::<usize>::new();
+    let mut lasts = HashSet::<usize>::new();
+
+
+    for (i, entity) in dfs_order.iter().enumerate() {
+        if let GraphEntity::Edge(edge, _, _) = entity {
+            let edge = edge.borrow();
+            if !seen_classes.contains(&edge.class) {
+                seen_classes.insert(edge.class);
+                firsts.insert(i);
+            }
+        }
+    }
+
+    seen_classes.clear();
+    // in reverse order
+    for (i, entity) in dfs_order.iter().rev().enumerate() {
+        if let GraphEntity::Edge(edge, _, _) = entity {
+            let edge = edge.borrow();
+            if !seen_classes.contains(&edge.class) {
+                seen_classes.insert(edge.class);
+                lasts.insert(dfs_order.len()-1-i);
+            }
+        }
+    }
+    
+    // map from node to the innermost region containing it
+    // an edge is processed in the region of its tail, which also restores the right region when the search backtracks
+    let mut region_map = HashMap::<NodeIndex, Rc<RefCell<SeSeRegion>>>::new();
+    let base_region = Rc::new(RefCell::new(SeSeRegion::new(next_region_id(), 0)));
+    program_structure_tree.root = Some(base_region.clone());
+
+    for (i, entity) in dfs_order.iter().enumerate() {
+        match entity {
+            GraphEntity::Node(node, idx) => {
+                // a node is discovered in the region of the edge that reached it, or the base region for a start
+                let region = region_map.entry(*idx).or_insert_with(|| base_region.clone()).clone();
+                region.borrow_mut().nodes.push(node.clone());
+            }
+            GraphEntity::Edge(edge_, (from, to), id) => {
+                let edge = edge_.borrow();
+                let is_sese_entry = !lasts.contains(&i);
+                let is_sese_exit = !firsts.contains(&i);
+                let mut current_region = region_map.get(from).unwrap().clone();
+                if is_sese_exit {
+                    // the tail of an exit edge lies directly in the region it leaves
+                    current_region.borrow_mut().exit = Some(*id);
+                    // When a region is exited, the current region is set to be the exited region’s parent.
+                    let parent = current_region.borrow().parent.as_ref().unwrap_or_else(|| panic!("exit from the base region")).clone();
+                    current_region = parent;
+                }
+                if is_sese_entry {
+                    // When a region is first entered, we set its parent to the current region
+                    let region = Rc::new(RefCell::new(SeSeRegion::new(next_region_id(), edge.class)));
+                    region.borrow_mut().entry = Some(*id);
+                    region.borrow_mut().parent = Some(current_region.clone());
+                    current_region.borrow_mut().children.push(region.clone());
+                    current_region = region;
+                }
+                // the edge that discovers a node decides its region
+                region_map.entry(*to).or_insert_with(|| current_region.clone());
+            }
+        }
+    }
+
+    chains::compose_chains(&program_structure_tree, options.granularity, next_region_id());
+
+    // print the program structure tree
+    if options.write_dot {
+        program_structure_tree.print_dot(&*graph, "structure_tree.dot");
+    }
+
+    // Return the built program structure tree
+    program_structure_tree
+}
+
+// import hashmap
+use std::collections::HashMap;
+//use petgraph::visit::Dfs;
+
+use petgraph::visit::depth_first_search;
+
+pub fn dfs_tree(graph: &mut FlowGraph) -> Vec<NodeIndex> {
+    // get the source node of the graph as the lowest node in the graph
+    let source = NodeIndex::new(0);
+    let mut dfs_order = Vec::new();
+    let mut tree_edges = Vec::new();
+    
+    // run a depth first search and use DfsEvent matching to mark tree edges and back edges
+    // and record when we first encounter a node in the search in dfs_order
+    depth_first_search(&*graph, Some(source), |event| {
+        match event {
+            DfsEvent::Discover(node, _) => {
+                dfs_order.push(node);
+            }
+            DfsEvent::TreeEdge(from, to) => {
+                tree_edges.push((from, to));
+            }
+            //DfsEvent::BackEdge(from, to) => {
+            //    back_edges.push((from, to));
+            //}
+            _ => {}
+        }
+    });
+
+    for (from, to) in tree_edges {
+        // modify the edge in the graph to be marked as a backedge
+        let mut edge = graph.edge_weight_mut(graph.find_edge(from, to).unwrap()).unwrap().borrow_mut();
+        edge.is_tree_edge = true;
+        edge.is_backedge = false;
+    }
+
+    // for all edges that are not tree edges, mark them as backedges
+    for e in graph.edge_weights_mut() {
+        let mut edge = e.borrow_mut();
+        //let mut edge = graph.edge_weight_mut(graph.find_edge(from, to).unwrap()).unwrap().borrow_mut();
+        if !edge.is_tree_edge {
+            edge.is_backedge = true;
+        }
+        //edge.is_backedge = true;
+    }
+
+    for (i, node) in dfs_order.clone().into_iter().enumerate() {
+        let mut n = graph[node].borrow_mut();
+        n.dfsnum = i;
+        //n.hi = i;
+    }
+
+    dfs_order.reverse();
+    dfs_order
+}
+
+pub fn add_graph_node(graph: &mut FlowGraph, id: usize) -> NodeIndex {
+    let node = Rc::new(RefCell::new(Node::new(id)));
+    graph.add_node(node)
+}
+
+pub fn add_graph_edge(graph: &mut FlowGraph, from: NodeIndex, to: NodeIndex) -> Rc<RefCell<Edge>> {
+    let edge = Rc::new(RefCell::new(Edge::new(from.index(), to.index())));
+    graph.add_edge(from, to, edge.clone());
+    edge
+}
+
+pub fn make_example_0() -> FlowGraph {
+
+    let mut graph = FlowGraph::new_undirected();
+
+    let a = add_graph_node(&mut graph, 0);
+    let b = add_graph_node(&mut graph, 1);
+    let c = add_graph_node(&mut graph, 2);
+    let d = add_graph_node(&mut graph, 3);
+    let e = add_graph_node(&mut graph, 4);
+
+    add_graph_edge(&mut graph, a, b);
+    add_graph_edge(&mut graph, a, c);
+    add_graph_edge(&mut graph, b, d);
+    add_graph_edge(&mut graph, c, d);
+    add_graph_edge(&mut graph, d, e);
+    // add end to start
+    add_graph_edge(&mut graph, e, a);
+
+    graph
+}
+
+pub fn flowify(graph: &mut FlowGraph) {
+    // add an edge from the source to node with id=1
+    add_graph_edge(graph, NodeIndex::new(0), NodeIndex::new(1));
+
+    // add an edge from the node with id=node_count-1 to the sink
+    add_graph_edge(graph, NodeIndex::new(graph.node_count()-2), NodeIndex::new(graph.node_count()-1));
+
+    // add sink to source
+    add_graph_edge(graph, NodeIndex::new(graph.node_count()-1), NodeIndex::new(0));
+
+    // add edges from source to all nodes with an edge count of 1
+    for node in graph.node_indices() {
+        if graph.edges(node).count() == 1 {
+            add_graph_edge(graph, NodeIndex::new(0), node);
+        }
+    }
+
+}
+
+
+pub fn make_example_a() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let n0 = add_graph_node(&mut graph, 0);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n6 = add_graph_node(&mut graph, 6);
+    let n7 = add_graph_node(&mut graph, 7);
+    // link them up in a line
+    add_graph_edge(&mut graph, n0, n1);
+    add_graph_edge(&mut graph, n1, n2);
+    add_graph_edge(&mut graph, n2, n3);
+    add_graph_edge(&mut graph, n3, n4);
+    add_graph_edge(&mut graph, n4, n5);
+    add_graph_edge(&mut graph, n5, n6);
+    add_graph_edge(&mut graph, n6, n7);
+    // add back edges
+    // start to end
+    add_graph_edge(&mut graph, n7, n0);
+    // 1 to 4
+    add_graph_edge(&mut graph, n1, n4);
+    // 2 to 3
+    add_graph_edge(&mut graph, n2, n3);
+    // 5 to 6
+    add_graph_edge(&mut graph, n5, n6);
+    graph
+}
+
+pub fn make_example_fig1() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let n0 = add_graph_node(&mut graph, 0);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n6 = add_graph_node(&mut graph, 6);
+    let n7 = add_graph_node(&mut graph, 7);
+    let n8 = add_graph_node(&mut graph, 8);
+    let n9 = add_graph_node(&mut graph, 9);
+    let n10 = add_graph_node(&mut graph, 10);
+    let n11 = add_graph_node(&mut graph, 11);
+    let n12 = add_graph_node(&mut graph, 12);
+    let n13 = add_graph_node(&mut graph, 13);
+    let n14 = add_graph_node(&mut graph, 14);
+    let n15 = add_graph_node(&mut graph, 15);
+    // 0 -> 1 and 2
+    add_graph_edge(&mut graph, n0, n1);
+    add_graph_edge(&mut graph, n0, n2);
+    // 1 -> 3 and 13
+    add_graph_edge(&mut graph, n1, n3);
+    add_graph_edge(&mut graph, n1, n13);
+    // 2 -> 4
+    add_graph_edge(&mut graph, n2, n4);
+    // 4 -> 6 and 8
+    add_graph_edge(&mut graph, n4, n6);
+    add_graph_edge(&mut graph, n4, n8);
+    // 3 -> 5
+    add_graph_edge(&mut graph, n3, n5);
+    // 5 -> 7 and 9
+    add_graph_edge(&mut graph, n5, n7);
+    add_graph_edge(&mut graph, n5, n9);
+    // 7 -> 11
+    add_graph_edge(&mut graph, n7, n11);
+    // 9 -> 11
+    add_graph_edge(&mut graph, n9, n11);
+    // 6 -> 10
+    add_graph_edge(&mut graph, n6, n10);
+    // 8 -> 10 and 12
+    add_graph_edge(&mut graph, n8, n10);
+    add_graph_edge(&mut graph, n8, n12);
+    // 10 -> 12
+    add_graph_edge(&mut graph, n10, n12);
+    // 11 -> 13
+    add_graph_edge(&mut graph, n11, n13);
+    // 12 -> 14
+    add_graph_edge(&mut graph, n12, n14);
+    // 13 -> 15
+    add_graph_edge(&mut graph, n13, n15);
+    // 14 -> 15
+    add_graph_edge(&mut graph, n14, n15);
+    // cycle edge from 15 to 0
+    add_graph_edge(&mut graph, n15, n0);
+    graph
+}
+// rewrite the previous function, incrementing all numbers by 1, both in the ids and in the variable names
+
+pub fn make_example_fig1_a() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let n0 = add_graph_node(&mut graph, 0);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n6 = add_graph_node(&mut graph, 6);
+    let n7 = add_graph_node(&mut graph, 7);
+    let n8 = add_graph_node(&mut graph, 8);
+    let n9 = add_graph_node(&mut graph, 9);
+    let n10 = add_graph_node(&mut graph, 10);
+    let n11 = add_graph_node(&mut graph, 11);
+    let n12 = add_graph_node(&mut graph, 12);
+    let n13 = add_graph_node(&mut graph, 13);
+    let n14 = add_graph_node(&mut graph, 14);
+    let n15 = add_graph_node(&mut graph, 15);
+    let n16 = add_graph_node(&mut graph, 16);
+    let n17 = add_graph_node(&mut graph, 17);
+    //let n18 = add_graph_node(&mut graph, 18);
+    // 1 -> 2 and 3
+    add_graph_edge(&mut graph, n1, n2);
+    add_graph_edge(&mut graph, n1, n3);
+    // 2 -> 4 and 14
+    add_graph_edge(&mut graph, n2, n4);
+    add_graph_edge(&mut graph, n2, n14);
+    // 3 -> 5
+    add_graph_edge(&mut graph, n3, n5);
+    // 5 -> 7 and 9
+    add_graph_edge(&mut graph, n5, n7);
+    add_graph_edge(&mut graph, n5, n9);
+    // 4 -> 6
+    add_graph_edge(&mut graph, n4, n6);
+    // 6 -> 8 and 10
+    add_graph_edge(&mut graph, n6, n8);
+    add_graph_edge(&mut graph, n6, n10);
+    // 8 -> 12
+    add_graph_edge(&mut graph, n8, n12);
+    // 10 -> 12
+    add_graph_edge(&mut graph, n10, n12);
+    // 7 -> 11
+    add_graph_edge(&mut graph, n7, n11);
+    // 9 -> 11 and 13
+    add_graph_edge(&mut graph, n9, n11);
+    add_graph_edge(&mut graph, n9, n13);
+    // 11 -> 13
+    add_graph_edge(&mut graph, n11, n13);
+    // 12 -> 14
+    add_graph_edge(&mut graph, n12, n14);
+    // 13 -> 15
+    add_graph_edge(&mut graph, n13, n15);
+    // 14 -> 16
+    add_graph_edge(&mut graph, n14, n16);
+    // 15 -> 16
+    add_graph_edge(&mut graph, n15, n16);
+    // link linkers
+    add_graph_edge(&mut graph, n16, n17);
+    add_graph_edge(&mut graph, n17, n0);
+    //add_graph_edge(&mut graph, n18, n0);
+    add_graph_edge(&mut graph, n0, n1);
+    
+    //add_graph_edge(&mut graph, n16, n1);
+    // add flow cycle edges
+    //flowify(&mut graph);
+    
+    graph
+}
+
+pub fn make_example_diamond() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let _n0 = add_graph_node(&mut graph, 0); // source
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let _n5 = add_graph_node(&mut graph, 5); // sink
+    // 0 -> 1
+    //add_graph_edge(&mut graph, n0, n1);
+    // 1 -> 2 and 3
+    add_graph_edge(&mut graph, n1, n2);
+    add_graph_edge(&mut graph, n1, n3);
+    // 2 -> 4
+    add_graph_edge(&mut graph, n2, n4);
+    // 3 -> 4
+    add_graph_edge(&mut graph, n3, n4);
+    // 4 -> 5
+    //add_graph_edge(&mut graph, n4, n5);
+    // 5 -> 0
+    //add_graph_edge(&mut graph, n5, n0);
+    flowify(&mut graph);
+    graph
+}
+
+pub fn make_example_c() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let n10 = add_graph_node(&mut graph, 10); // source
+    let n0 = add_graph_node(&mut graph, 0);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n6 = add_graph_node(&mut graph, 6);
+    let n7 = add_graph_node(&mut graph, 7);
+    let n8 = add_graph_node(&mut graph, 8);
+    let n9 = add_graph_node(&mut graph, 9);
+
+    add_graph_edge(&mut graph, n10, n0);
+    
+    // 0 - 4 make a line
+    add_graph_edge(&mut graph, n0, n1);
+    add_graph_edge(&mut graph, n1, n2);
+    add_graph_edge(&mut graph, n2, n3);
+    add_graph_edge(&mut graph, n3, n4);
+    // 4 - 5, 5 - 6
+    add_graph_edge(&mut graph, n4, n5);
+    add_graph_edge(&mut graph, n5, n6);
+    // other fork
+    // 4 - 7, 7 - 8
+    add_graph_edge(&mut graph, n4, n7);
+    add_graph_edge(&mut graph, n7, n8);
+
+    // extra edges
+    add_graph_edge(&mut graph, n0, n8);
+    add_graph_edge(&mut graph, n2, n7);
+    add_graph_edge(&mut graph, n3, n5);
+    add_graph_edge(&mut graph, n1, n6);
+
+    // connect tails
+    add_graph_edge(&mut graph, n6, n9);
+    add_graph_edge(&mut graph, n8, n9);
+
+    // loop
+    add_graph_edge(&mut graph, n9, n10);
+
+    graph
+
+}
+
+pub fn make_nonplanar_1() -> FlowGraph {
+    // make nodes 0 to 7
+    let mut graph = FlowGraph::new_undirected();
+    let n0 = add_graph_node(&mut graph, 0);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n2 = add_graph_node(&mut graph, 2);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n4 = add_graph_node(&mut graph, 4);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n6 = add_graph_node(&mut graph, 6);
+    let n7 = add_graph_node(&mut graph, 7);
+    // make edges
+    add_graph_edge(&mut graph, n0, n1);
+    add_graph_edge(&mut graph, n1, n2);
+    add_graph_edge(&mut graph, n1, n5);
+    add_graph_edge(&mut graph, n2, n3);
+    add_graph_edge(&mut graph, n2, n4);
+    add_graph_edge(&mut graph, n3, n5);
+    add_graph_edge(&mut graph, n5, n4);
+    add_graph_edge(&mut graph, n4, n2);
+    add_graph_edge(&mut graph, n3, n6);
+    add_graph_edge(&mut graph, n4, n6);
+    add_graph_edge(&mut graph, n6, n7);
+    // 7 - 0
+    add_graph_edge(&mut graph, n7, n0);
+    graph
+}
+
+// a five node cycle with a chord and a two edge cycle hanging off the same node
+// the ids differ from the node indexes on purpose, capping edges have to be added by index
+pub fn make_example_nested_cycle() -> FlowGraph {
+    let mut graph = FlowGraph::new_undirected();
+    let n4 = add_graph_node(&mut graph, 4); // source
+    let n0 = add_graph_node(&mut graph, 0);
+    let n5 = add_graph_node(&mut graph, 5);
+    let n1 = add_graph_node(&mut graph, 1);
+    let n3 = add_graph_node(&mut graph, 3);
+    let n2 = add_graph_node(&mut graph, 2);
+    // the cycle
+    add_graph_edge(&mut graph, n4, n0);
+    add_graph_edge(&mut graph, n0, n5);
+    add_graph_edge(&mut graph, n5, n1);
+    add_graph_edge(&mut graph, n1, n3);
+    add_graph_edge(&mut graph, n3, n4);
+    // two parallel edges 0 - 2
+    add_graph_edge(&mut graph, n0, n2);
+    add_graph_edge(&mut graph, n2, n0);
+    // the chord
+    add_graph_edge(&mut graph, n0, n1);
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::seeded_graphs;
+
+    // (from, to) of a boundary edge
+    fn endpoints(graph: &FlowGraph, e: Option<EdgeIndex>) -> Option<(usize, usize)> {
+        e.map(|e| (graph[e].borrow().from, graph[e].borrow().to))
+    }
+
+    // innermost region id of every node id
+    fn region_of_node(tree: &StructureTree) -> HashMap<usize, usize> {
+        let mut result = HashMap::new();
+        for region in tree.regions() {
+            for n in region.borrow().nodes.iter() {
+                assert!(result.insert(n.borrow().id, region.borrow().id).is_none(), "node {} placed twice", n.borrow().id);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn fig1_placement() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        // (entry, exit, entry of the parent, node ids directly inside)
+        let expected = vec![
+            ((0, 1), (13, 15), None, vec![1, 13]),
+            ((1, 3), (3, 5), Some((0, 1)), vec![3]),
+            ((3, 5), (11, 13), Some((0, 1)), vec![5, 11]),
+            ((5, 7), (7, 11), Some((3, 5)), vec![7]),
+            ((5, 9), (9, 11), Some((3, 5)), vec![9]),
+            ((0, 2), (2, 4), None, vec![2]),
+            ((2, 4), (12, 14), None, vec![4, 8, 10, 12]),
+            ((4, 6), (6, 10), Some((2, 4)), vec![6]),
+            ((12, 14), (14, 15), None, vec![14]),
+        ];
+        let regions = tree.regions();
+        assert_eq!(regions.len(), expected.len() + 1);
+        let mut root_nodes = regions[0].borrow().nodes.iter().map(|n| n.borrow().id).collect::<Vec<usize>>();
+        root_nodes.sort();
+        assert_eq!(root_nodes, vec![0, 15]);
+        for (region, (entry, exit, parent, nodes)) in regions[1..].iter().zip(expected) {
+            let region = region.borrow();
+            assert_eq!(endpoints(&graph, region.entry), Some(entry));
+            assert_eq!(endpoints(&graph, region.exit), Some(exit));
+            assert_eq!(endpoints(&graph, region.parent.as_ref().unwrap().borrow().entry), parent);
+            let mut ids = region.nodes.iter().map(|n| n.borrow().id).collect::<Vec<usize>>();
+            ids.sort();
+            assert_eq!(ids, nodes);
+        }
+    }
+
+    #[test]
+    fn nodes_follow_their_edges() {
+        // an edge that bounds no region joins two nodes of the same innermost region, whichever of the
+        // two the traversal reached first and however far it backtracked in between
+        for mut graph in seeded_graphs(31, 12, 8).take(100) {
+            let tree = build_structure_tree(&mut graph);
+            let region_of = region_of_node(&tree);
+            assert_eq!(region_of.len(), graph.node_count());
+            let mut boundaries = HashSet::new();
+            for region in tree.regions() {
+                boundaries.extend(region.borrow().entry);
+                boundaries.extend(region.borrow().exit);
+            }
+            for e in graph.edge_indices() {
+                let edge = graph[e].borrow();
+                if edge.is_capping || boundaries.contains(&e) {
+                    continue;
+                }
+                let (a, b) = (graph[NodeIndex::new(edge.from)].borrow().id, graph[NodeIndex::new(edge.to)].borrow().id);
+                assert_eq!(region_of[&a], region_of[&b], "edge {} -> {}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+use crate::regions::RegionTable;
+
+// one loop of the loop nesting forest
+// nodes holds the whole body, nested loops included, and the header is the node Havlak's algorithm
+// picks as the loop's head: the first node of the body in depth first preorder
+#[derive(Clone,Debug)]
+pub struct Loop {
+    pub header: usize, // node index
+    pub parent: Option<usize>, // index of the enclosing loop
+    pub children: Vec<usize>, // indexes of the loops directly nested inside
+    pub nodes: Vec<usize>, // sorted node indexes of the body
+    pub latches: Vec<usize>, // edge indexes of the edges from the body back to the header
+    pub exits: Vec<usize>, // edge indexes of the edges leaving the body, the return edge excluded
+    pub entries: Vec<usize>, // node indexes other than the header entered from outside the body
+    pub depth: usize, // 1 for an outermost loop
+    pub region: Option<usize>, // innermost SESE region holding the whole loop, once linked
+}
+
+impl Loop {
+    // a loop is reducible when the header is its only way in
+    pub fn is_reducible(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, node: usize) -> bool {
+        self.nodes.binary_search(&node).is_ok()
+    }
+}
+
+// loop nesting forest of the directed view of a flow graph, after Havlak, "Nesting of reducible and
+// irreducible loops", with a union find over the depth first spanning tree
+// nodes that cannot be reached from the source are in no loop
+#[derive(Clone,Debug)]
+pub struct LoopForest {
+    pub loops: Vec<Loop>, // loops in preorder of the forest, outer loops before the loops inside them
+    pub node_loop: Vec<Option<usize>>, // innermost loop of each node index
+}
+
+fn find(parent: &mut [usize], x: usize) -> usize {
+    let mut root = x;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut y = x;
+    while parent[y] != root {
+        let next = parent[y];
+        parent[y] = root;
+        y = next;
+    }
+    root
+}
+
+impl LoopForest {
+    pub fn new(graph: &FlowGraph) -> LoopForest {
+        LoopForest::from_digraph(&DiGraph::from_flow_graph(graph))
+    }
+
+    pub fn from_digraph(digraph: &DiGraph) -> LoopForest {
+        let n = digraph.node_count();
+        let succs = digraph.successor_lists();
+        // depth first preorder numbers and the last number in each subtree
+        let mut number = vec![usize::MAX; n];
+        let mut node_of = Vec::new();
+        let mut last = vec![0; n];
+        let mut stack = vec![(digraph.entry, 0)];
+        number[digraph.entry] = 0;
+        node_of.push(digraph.entry);
+        while let Some((v, i)) = stack.pop() {
+            if i == succs[v].len() {
+                last[number[v]] = node_of.len() - 1;
+                continue;
+            }
+            stack.push((v, i + 1));
+            let w = succs[v][i];
+            if number[w] == usize::MAX {
+                number[w] = node_of.len();
+                node_of.push(w);
+                stack.push((w, 0));
+            }
+        }
+        let reached = node_of.len();
+        let is_ancestor = |w: usize, v: usize| w <= v && v <= last[w];
+        // predecessors split into back predecessors, from descendants, and the rest, by preorder number
+        let mut back_preds = vec![Vec::new(); reached];
+        let mut other_preds = vec![Vec::new(); reached];
+        for w in 0..reached {
+            for p in digraph.predecessors(node_of[w]) {
+                if number[p] == usize::MAX {
+                    continue;
+                }
+                if is_ancestor(w, number[p]) {
+                    back_preds[w].push(number[p]);
+                } else {
+                    other_preds[w].push(number[p]);
+                }
+            }
+        }
+        let mut union = (0..reached).collect::<Vec<usize>>();
+        let mut header = vec![usize::MAX; reached];
+        let mut is_header = vec![false; reached];
+        for w in (0..reached).rev() {
+            let mut body = Vec::new();
+            let mut in_body = vec![false; reached];
+            for v in back_preds[w].iter() {
+                if *v == w {
+                    is_header[w] = true;
+                } else {
+                    let x = find(&mut union, *v);
+                    if !in_body[x] {
+                        in_body[x] = true;
+                        body.push(x);
+                    }
+                }
+            }
+            let mut worklist = body.clone();
+            while let Some(x) = worklist.pop() {
+                for y in other_preds[x].clone() {
+                    let y = find(&mut union, y);
+                    if !is_ancestor(w, y) {
+                        // an entry into the loop that bypasses the header
+                        other_preds[w].push(y);
+                    } else if !in_body[y] && y != w {
+                        in_body[y] = true;
+                        body.push(y);
+                        worklist.push(y);
+                    }
+                }
+            }
+            if !body.is_empty() {
+                is_header[w] = true;
+            }
+            for x in body {
+                header[x] = w;
+                union[x] = w;
+            }
+        }
+        // build the loops outermost first, which is preorder since a header precedes its body
+        let mut loop_of_header = vec![usize::MAX; reached];
+        let mut loops: Vec<Loop> = Vec::new();
+        for w in 0..reached {
+            if !is_header[w] {
+                continue;
+            }
+            // the nearest enclosing header is the first header up the chain
+            let mut h = header[w];
+            while h != usize::MAX && !is_header[h] {
+                h = header[h];
+            }
+            let parent = if h == usize::MAX { None } else { Some(loop_of_header[h]) };
+            let depth = parent.map_or(1, |p| loops[p].depth + 1);
+            let index = loops.len();
+            loop_of_header[w] = index;
+            if let Some(p) = parent {
+                loops[p].children.push(index);
+            }
+            loops.push(Loop {
+                header: node_of[w],
+                parent,
+                children: Vec::new(),
+                nodes: Vec::new(),
+                latches: Vec::new(),
+                exits: Vec::new(),
+                entries: Vec::new(),
+                depth,
+                region: None,
+            });
+        }
+        // innermost loop of each node: its own if it is a header, otherwise that of its header
+        let mut node_loop = vec![None; n];
+        for w in 0..reached {
+            let mut h = if is_header[w] { w } else { header[w] };
+            while h != usize::MAX && !is_header[h] {
+                h = header[h];
+            }
+            if h != usize::MAX {
+                node_loop[node_of[w]] = Some(loop_of_header[h]);
+            }
+        }
+        for (v, l) in node_loop.iter().enumerate() {
+            let mut l = *l;
+            while let Some(x) = l {
+                loops[x].nodes.push(v);
+                l = loops[x].parent;
+            }
+        }
+        for l in loops.iter_mut() {
+            l.nodes.sort_unstable();
+            for (e, endpoints) in digraph.edges.iter().enumerate() {
+                let (a, b) = match endpoints {
+                    Some(ab) if !digraph.is_return_edge(e) => *ab,
+                    _ => continue,
+                };
+                match (l.contains(a), l.contains(b)) {
+                    (true, true) if b == l.header => l.latches.push(e),
+                    (true, false) => l.exits.push(e),
+                    (false, true) if b != l.header && !l.entries.contains(&b) => l.entries.push(b),
+                    _ => {}
+                }
+            }
+            l.entries.sort_unstable();
+        }
+        LoopForest { loops, node_loop }
+    }
+
+    // loop nesting depth of a node, 0 outside every loop
+    pub fn depth(&self, node: usize) -> usize {
+        self.node_loop[node].map_or(0, |l| self.loops[l].depth)
+    }
+
+    pub fn is_reducible(&self) -> bool {
+        self.loops.iter().all(|l| l.is_reducible())
+    }
+
+    // record for each loop the innermost region of the table that holds all of its nodes
+    pub fn link_regions(&mut self, table: &RegionTable) {
+        for l in self.loops.iter_mut() {
+            let mut r = table.node_region[l.header];
+            while !l.nodes.iter().all(|n| table.contains(r, *n)) {
+                r = table.regions[r].parent.unwrap();
+            }
+            l.region = Some(r);
+        }
+    }
+
+    // loops whose innermost holding region is r, after link_regions
+    pub fn loops_in_region(&self, r: usize) -> Vec<usize> {
+        (0..self.loops.len()).filter(|l| self.loops[*l].region == Some(r)).collect()
+    }
+}
+
+// a reducible copy of a directed graph made by node splitting, with the original node of every node
+#[derive(Clone,Debug)]
+pub struct SplitGraph {
+    pub digraph: DiGraph,
+    pub origin: Vec<usize>,
+}
+
+// split nodes until every loop is reducible, or give up with None once the graph would grow past
+// max_nodes; splitting can blow up exponentially on badly irreducible graphs
+// each round takes the first irreducible loop and one of its extra entries, copies the body apart from
+// the header and sends the edges entering at that entry from outside into the copy; the copy returns to
+// the original loop only through the header, so the extra entry is gone
+pub fn split_irreducible(digraph: &DiGraph, max_nodes: usize) -> Option<SplitGraph> {
+    let mut current = digraph.clone();
+    let mut origin = (0..digraph.node_count()).collect::<Vec<usize>>();
+    loop {
+        let forest = LoopForest::from_digraph(&current);
+        let l = match forest.loops.iter().find(|l| !l.is_reducible()) {
+            Some(l) => l,
+            None => return Some(SplitGraph { digraph: current, origin }),
+        };
+        let copied = l.nodes.iter().cloned().filter(|v| *v != l.header).collect::<Vec<usize>>();
+        if current.node_count() + copied.len() > max_nodes {
+            return None;
+        }
+        let entry = l.entries[0];
+        let mut copy_of = vec![usize::MAX; current.node_count()];
+        for (i, v) in copied.iter().enumerate() {
+            copy_of[*v] = current.node_count() + i;
+            origin.push(origin[*v]);
+        }
+        let mut edges = current.edges.clone();
+        for (e, endpoints) in current.edges.iter().enumerate() {
+            let (a, b) = match endpoints {
+                Some(ab) if !current.is_return_edge(e) => *ab,
+                _ => continue,
+            };
+            if copy_of[a] != usize::MAX {
+                // the copy of an edge out of the body keeps its head unless that head was copied too
+                let head = if copy_of[b] != usize::MAX { copy_of[b] } else { b };
+                edges.push(Some((copy_of[a], head)));
+            } else if b == entry && !l.contains(a) {
+                edges[e] = Some((a, copy_of[entry]));
+            }
+        }
+        current = DiGraph::new(current.entry, current.exit, current.return_edge, edges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::seeded_graphs;
+
+    // 0 -> 1, 0 -> 2, 1 <-> 2, both to 3: a loop with two ways in
+    fn irreducible() -> DiGraph {
+        let edges = vec![(0, 1), (0, 2), (1, 2), (2, 1), (1, 3), (2, 3), (3, 0)];
+        DiGraph::new(0, 3, Some(6), edges.into_iter().map(Some).collect())
+    }
+
+    #[test]
+    fn irreducible_loop() {
+        let forest = LoopForest::from_digraph(&irreducible());
+        assert_eq!(forest.loops.len(), 1);
+        let l = &forest.loops[0];
+        assert_eq!(l.header, 1);
+        assert_eq!(l.nodes, vec![1, 2]);
+        assert_eq!(l.entries, vec![2]);
+        assert_eq!(l.latches, vec![3]);
+        assert_eq!(l.exits, vec![4, 5]);
+        assert!(!forest.is_reducible());
+        assert_eq!(forest.depth(2), 1);
+        assert_eq!(forest.depth(3), 0);
+
+        let split = split_irreducible(&irreducible(), 10).unwrap();
+        assert!(LoopForest::from_digraph(&split.digraph).is_reducible());
+        assert_eq!(split.origin, vec![0, 1, 2, 3, 2]);
+        assert!(split_irreducible(&irreducible(), 4).is_none());
+    }
+
+    #[test]
+    fn nested_loops() {
+        // 0 -> 1 -> 2 -> 3 -> 4, with 3 -> 2 inside 4 -> 1, leaving by 4 -> 5
+        let edges = vec![(0, 1), (1, 2), (2, 3), (3, 2), (3, 4), (4, 1), (4, 5), (5, 0)];
+        let digraph = DiGraph::new(0, 5, Some(7), edges.into_iter().map(Some).collect());
+        let forest = LoopForest::from_digraph(&digraph);
+        assert_eq!(forest.loops.len(), 2);
+        assert_eq!(forest.loops[0].header, 1);
+        assert_eq!(forest.loops[0].nodes, vec![1, 2, 3, 4]);
+        assert_eq!(forest.loops[0].children, vec![1]);
+        assert_eq!(forest.loops[1].header, 2);
+        assert_eq!(forest.loops[1].nodes, vec![2, 3]);
+        assert_eq!(forest.loops[1].latches, vec![3]);
+        assert_eq!(forest.loops[1].exits, vec![4]);
+        assert_eq!(forest.depth(3), 2);
+        assert_eq!(forest.depth(4), 1);
+        assert!(forest.is_reducible());
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut irreducible = 0;
+        for mut graph in seeded_graphs(5, 14, 6).take(200) {
+            let tree = build_structure_tree(&mut graph);
+            let table = RegionTable::new(&graph, &tree).unwrap();
+            let mut forest = LoopForest::new(&graph);
+            forest.link_regions(&table);
+            for l in forest.loops.iter() {
+                let r = l.region.unwrap();
+                assert!(l.nodes.iter().all(|n| table.contains(r, *n)));
+                // no child region holds the whole loop
+                for c in table.regions[r].children.iter() {
+                    assert!(!l.nodes.iter().all(|n| table.contains(*c, *n)));
+                }
+            }
+            if !forest.is_reducible() {
+                irreducible += 1;
+                let digraph = DiGraph::from_flow_graph(&graph);
+                if let Some(split) = split_irreducible(&digraph, 200) {
+                    assert!(LoopForest::from_digraph(&split.digraph).is_reducible());
+                }
+            }
+        }
+        assert!(irreducible > 0);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use crate::{FlowGraph, StructureTree};
+use crate::regions::RegionTable;
+use crate::traversals::{Step, TraversalOptions, region_traversals};
+
+// sequence measures of a region, from the walks through it and the nodes inside it
+#[derive(Clone,Debug,PartialEq)]
+pub struct RegionMetrics {
+    pub region: usize, // index in the region table
+    pub min_allele_length: usize, // bases on the shortest walk from entry to exit, between the boundary nodes
+    pub max_allele_length: usize, // bases on the longest
+    pub boundary_length: usize, // bases on the head of the entry edge and the tail of the exit edge, 0 for the root
+    pub total_length: usize, // bases on every node inside the region or its descendants
+    pub gc_content: f64, // fraction of G and C among those bases that are not N, 0 when there are none
+    pub truncated: bool, // the walks were capped, so the allele lengths may be off
+}
+
+// the metrics of one region of the table, over walks through every node of it whatever
+// options.collapse_children says; the graph must be the one the table was built from
+pub fn region_metrics(graph: &FlowGraph, table: &RegionTable, r: usize, options: &TraversalOptions) -> RegionMetrics {
+    let index = graph.node_indices().map(|n| (graph[n].borrow().id, n)).collect::<HashMap<usize, NodeIndex>>();
+    metrics_of(graph, table, r, options, &index)
+}
+
+// the metrics of every region, by table index
+pub fn all_metrics(graph: &FlowGraph, tree: &StructureTree, options: &TraversalOptions) -> Result<Vec<RegionMetrics>, String> {
+    let table = RegionTable::new(graph, tree)?;
+    let index = graph.node_indices().map(|n| (graph[n].borrow().id, n)).collect::<HashMap<usize, NodeIndex>>();
+    Ok((0..table.len()).map(|r| metrics_of(graph, &table, r, options, &index)).collect())
+}
+
+fn metrics_of(graph: &FlowGraph, table: &RegionTable, r: usize, options: &TraversalOptions, index: &HashMap<usize, NodeIndex>) -> RegionMetrics {
+    let options = TraversalOptions { collapse_children: false, ..options.clone() };
+    let traversals = region_traversals(graph, table, r, &options);
+    let length = |step: &Step| match step {
+        Step::Node(id) => graph[index[id]].borrow().sequence.len(),
+        Step::Region(_) => 0,
+    };
+    // the ids of the boundary nodes, which the walks start and end on
+    let region = &table.regions[r];
+    let boundary = region.entry.zip(region.exit).map(|(entry, exit)| {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        (id(graph[EdgeIndex::new(entry)].borrow().to), id(graph[EdgeIndex::new(exit)].borrow().from))
+    });
+    let lengths = traversals.walks.iter()
+        .map(|w| {
+            let mut inner = &w[..];
+            if let Some((head, tail)) = boundary {
+                if inner.first() == Some(&Step::Node(head)) {
+                    inner = &inner[1..];
+                }
+                if inner.last() == Some(&Step::Node(tail)) {
+                    inner = &inner[..inner.len() - 1];
+                }
+            }
+            inner.iter().map(length).sum()
+        })
+        .collect::<Vec<usize>>();
+    let boundary_length = match boundary {
+        Some((head, tail)) if head == tail => length(&Step::Node(head)),
+        Some((head, tail)) => length(&Step::Node(head)) + length(&Step::Node(tail)),
+        None => 0,
+    };
+
+    let (mut total_length, mut gc, mut n) = (0, 0, 0);
+    for node in table.all_nodes(r) {
+        let sequence = &graph[NodeIndex::new(node)].borrow().sequence;
+        total_length += sequence.len();
+        gc += sequence.gc_count();
+        n += sequence.n_count();
+    }
+    RegionMetrics {
+        region: r,
+        min_allele_length: lengths.iter().cloned().min().unwrap_or(0),
+        max_allele_length: lengths.iter().cloned().max().unwrap_or(0),
+        boundary_length,
+        total_length,
+        gc_content: if total_length > n { gc as f64 / (total_length - n) as f64 } else { 0.0 },
+        truncated: traversals.truncated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::bidirected::BiGraph;
+
+    #[test]
+    fn nested_bubbles() {
+        // s -> a -> (b1 -> (x | y) -> b2 | c) -> d -> t
+        let gfa = "S\ts\tACG\nS\ta\tT\nS\tb1\tGA\nS\tx\tC\nS\ty\tN\nS\tb2\tAG\nS\tc\tA\nS\td\tC\nS\tt\tGT\n\
+            L\ts\t+\ta\t+\t0M\nL\ta\t+\tb1\t+\t0M\nL\tb1\t+\tx\t+\t0M\nL\tb1\t+\ty\t+\t0M\nL\tx\t+\tb2\t+\t0M\n\
+            L\ty\t+\tb2\t+\t0M\nL\tb2\t+\td\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tc\t+\td\t+\t0M\nL\td\t+\tt\t+\t0M\n";
+        let bigraph = BiGraph::from_gfa(gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let metrics = all_metrics(&graph, &tree, &TraversalOptions::default()).unwrap();
+        assert_eq!(metrics.len(), table.len());
+
+        let mut nodes = ["a", "b1", "x", "y", "b2", "c", "d"].iter().map(|n| bigraph.flow_node(bigraph.node_index(n).unwrap())).collect::<Vec<usize>>();
+        nodes.sort();
+        let outer = (0..table.len()).find(|r| table.all_nodes(*r) == nodes).unwrap();
+        // GACAG or GANAG against A between T and C, with G and C four of the eight bases other than N
+        let expected = RegionMetrics { region: outer, min_allele_length: 1, max_allele_length: 5, boundary_length: 2, total_length: 9, gc_content: 0.5, truncated: false };
+        assert_eq!(metrics[outer], expected);
+        assert_eq!(region_metrics(&graph, &table, outer, &TraversalOptions::default()), expected);
+        assert_eq!((metrics[0].min_allele_length, metrics[0].boundary_length, metrics[0].total_length), (8, 0, 14));
+        // x alone is both boundary nodes of its region
+        let x = table.node_region[bigraph.flow_node(bigraph.node_index("x").unwrap())];
+        assert_eq!((metrics[x].min_allele_length, metrics[x].max_allele_length, metrics[x].boundary_length), (0, 0, 1));
+
+        let capped = region_metrics(&graph, &table, outer, &TraversalOptions { max_traversals: 1, ..Default::default() });
+        assert!(capped.truncated);
+    }
+}
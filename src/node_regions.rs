@@ -0,0 +1,265 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use std::collections::HashMap;
+
+use crate::{FlowGraph, add_graph_node, add_graph_edge};
+use crate::blocks::cycle_equivalence_by_block;
+
+// a region bounded by two nodes rather than two edges: every path into the region passes through its entry
+// node and every path out of it through its exit node
+#[derive(Clone,Debug)]
+pub struct NodeRegion {
+    pub entry: Option<usize>, // node index of the entry node, None for the root
+    pub exit: Option<usize>, // node index of the exit node, None for the root
+    pub class: usize, // cycle equivalence class of the internal edges of the boundary nodes in the split graph
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub nodes: Vec<usize>, // node indexes directly inside, not in a child; boundary nodes are in the parent
+}
+
+// node bounded regions, found by splitting each node v into v_in and v_out joined by an internal edge and
+// taking the regions of the split graph bounded by internal edges: in each class, each adjacent pair of
+// internal edges in dominance order bounds a region; these are unions of canonical edge regions of the
+// split graph, and nest the same way, so they are found with the same depth first walk
+#[derive(Clone,Debug)]
+pub struct NodeStructureTree {
+    pub regions: Vec<NodeRegion>, // preorder, the root first
+    pub node_region: Vec<usize>, // region of each node by node index
+}
+
+// the split graph: node v becomes 2v (in) and 2v + 1 (out), with the internal edge of v at edge index v and
+// the edge of index e at edge index node_count + e; capping backedges are left out
+pub fn split_nodes(graph: &FlowGraph) -> FlowGraph {
+    let mut split = FlowGraph::new_undirected();
+    let mut halves = Vec::new();
+    for n in graph.node_indices() {
+        // ids must be distinct for cycle_equivalence
+        halves.push((add_graph_node(&mut split, 2 * n.index()), add_graph_node(&mut split, 2 * n.index() + 1)));
+    }
+    for (v_in, v_out) in halves.iter() {
+        add_graph_edge(&mut split, *v_in, *v_out);
+    }
+    for e in graph.edge_indices() {
+        let edge = graph[e].borrow();
+        if !edge.is_capping {
+            add_graph_edge(&mut split, halves[edge.from].1, halves[edge.to].0);
+        }
+    }
+    split
+}
+
+impl NodeStructureTree {
+    pub fn new(graph: &FlowGraph) -> NodeStructureTree {
+        let node_count = graph.node_count();
+        let mut split = split_nodes(graph);
+        cycle_equivalence_by_block(&mut split);
+
+        // directed depth first order of the edges from the source, as in build_structure_tree
+        let mut out_edges = vec![Vec::new(); split.node_count()];
+        for e in split.edge_indices() {
+            out_edges[split[e].borrow().from].push(e.index());
+        }
+        let mut order = Vec::new();
+        let mut visited = vec![false; split.node_count()];
+        for start in 0..split.node_count() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![(start, 0)];
+            while let Some((n, i)) = stack.last_mut() {
+                if *i == out_edges[*n].len() {
+                    stack.pop();
+                    continue;
+                }
+                let e = out_edges[*n][*i];
+                *i += 1;
+                order.push(e);
+                let to = split[EdgeIndex::new(e)].borrow().to;
+                if !visited[to] {
+                    visited[to] = true;
+                    stack.push((to, 0));
+                }
+            }
+        }
+
+        let order = order.into_iter()
+            .map(|e| (e, split[EdgeIndex::new(e)].borrow().from))
+            .collect::<Vec<(usize, usize)>>();
+        NodeStructureTree::nest(&split, node_count, &order).0
+    }
+
+    // the regions of a split graph bounded by its internal edges, which are edges 0..node_count, from all
+    // its edges in the order a depth first search examines them, each with the split node it was examined
+    // from; each region also gets the split nodes inside it at its entry and at its exit
+    pub(crate) fn nest(split: &FlowGraph, node_count: usize, order: &[(usize, usize)]) -> (NodeStructureTree, Vec<(usize, usize)>) {
+        let class = |e: usize| split[EdgeIndex::new(e)].borrow().class;
+        // first and last internal edge of each class
+        let mut first = HashMap::new();
+        let mut last = HashMap::new();
+        for (e, _) in order.iter().filter(|(e, _)| *e < node_count) {
+            first.entry(class(*e)).or_insert(*e);
+            last.insert(class(*e), *e);
+        }
+
+        let mut tree = NodeStructureTree {
+            regions: vec![NodeRegion { entry: None, exit: None, class: 0, parent: None, children: Vec::new(), nodes: Vec::new() }],
+            node_region: vec![0; node_count],
+        };
+        let mut sides = vec![(0, 0)];
+        // the region of each split node, set by the edge that discovers it
+        let mut region_of = vec![None; split.node_count()];
+        for (e, from) in order.iter().cloned() {
+            let to = {
+                let edge = split[EdgeIndex::new(e)].borrow();
+                if edge.from == from { edge.to } else { edge.from }
+            };
+            let mut current = *region_of[from].get_or_insert(0);
+            if e < node_count {
+                let c = class(e);
+                if first[&c] != e && tree.regions[current].parent.is_some() {
+                    tree.regions[current].exit = Some(e);
+                    sides[current].1 = from;
+                    current = tree.regions[current].parent.unwrap();
+                }
+                tree.node_region[e] = current;
+                if last[&c] != e {
+                    let r = tree.regions.len();
+                    tree.regions.push(NodeRegion { entry: Some(e), exit: None, class: c, parent: Some(current), children: Vec::new(), nodes: Vec::new() });
+                    tree.regions[current].children.push(r);
+                    sides.push((to, 0));
+                    current = r;
+                }
+            }
+            region_of[to].get_or_insert(current);
+        }
+        for n in 0..node_count {
+            let r = tree.node_region[n];
+            tree.regions[r].nodes.push(n);
+        }
+        (tree, sides)
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    // the nodes strictly inside a region, its children's included, sorted
+    pub fn inside(&self, r: usize) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![r];
+        while let Some(s) = stack.pop() {
+            nodes.extend(self.regions[s].nodes.iter().cloned());
+            for c in self.regions[s].children.iter() {
+                nodes.extend(self.regions[*c].entry);
+                nodes.extend(self.regions[*c].exit);
+                stack.push(*c);
+            }
+        }
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    // (entry, exit) node pairs of the regions other than the root, in preorder
+    pub fn boundaries(&self) -> Vec<(usize, usize)> {
+        self.regions.iter().filter_map(|r| Some((r.entry?, r.exit?))).collect()
+    }
+
+    // the tree in dot format, each region labelled with its boundary node ids and the ids of its nodes
+    pub fn to_dot(&self, graph: &FlowGraph) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut dot = String::new();
+        dot.push_str("digraph {\n");
+        for (i, r) in self.regions.iter().enumerate() {
+            let bound = match (r.entry, r.exit) {
+                (Some(a), Some(b)) => format!("{}..{}", id(a), id(b)),
+                (Some(a), None) => format!("{}..", id(a)),
+                _ => "root".to_string(),
+            };
+            let nodes = r.nodes.iter().map(|n| id(*n).to_string()).collect::<Vec<String>>();
+            dot.push_str(format!("r{} [label=\"{}|{}\"];\n", i, bound, nodes.join(" ")).as_str());
+            for c in r.children.iter() {
+                dot.push_str(format!("r{} -> r{};\n", i, c).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::seeded_graphs;
+
+    // every region is closed: edges reach its inside only from the inside or the entry, and leave only to
+    // the inside or the exit; every node is in exactly one region
+    fn check(graph: &FlowGraph, tree: &NodeStructureTree) {
+        let mut counted = tree.regions.iter().map(|r| r.nodes.len()).sum::<usize>();
+        assert_eq!(counted, graph.node_count());
+        for (r, region) in tree.regions.iter().enumerate().skip(1) {
+            let (entry, exit) = (region.entry.unwrap(), region.exit.expect("every region is closed"));
+            assert_ne!(entry, exit);
+            let inside = tree.inside(r);
+            assert!(!inside.contains(&entry) && !inside.contains(&exit));
+            for e in graph.edge_weights() {
+                let e = e.borrow();
+                if e.is_capping {
+                    continue;
+                }
+                let (a, b) = (inside.contains(&e.from), inside.contains(&e.to));
+                if !a && b {
+                    assert_eq!(e.from, entry, "region {} entered from {}", r, e.from);
+                }
+                if a && !b {
+                    assert_eq!(e.to, exit, "region {} left to {}", r, e.to);
+                }
+            }
+            counted -= region.nodes.len();
+        }
+        assert_eq!(counted, tree.regions[0].nodes.len());
+    }
+
+    #[test]
+    fn fig1() {
+        let graph = make_example_fig1();
+        let tree = NodeStructureTree::new(&graph);
+        check(&graph, &tree);
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let boundaries = tree.boundaries().into_iter().map(|(a, b)| (id(a), id(b))).collect::<Vec<(usize, usize)>>();
+        // the body between the start and the end, the two sides of it, and the branches of 5 and of 4
+        assert_eq!(boundaries, vec![(0, 15), (1, 13), (3, 5), (5, 11), (2, 4), (4, 12), (12, 14)]);
+        assert_eq!(tree.inside(0).len(), 16);
+        assert_eq!(tree.inside(4), vec![7, 9]);
+        assert_eq!(tree.inside(6), vec![6, 8, 10]);
+    }
+
+    #[test]
+    fn diamond() {
+        let graph = make_example_diamond();
+        let tree = NodeStructureTree::new(&graph);
+        check(&graph, &tree);
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let boundaries = tree.boundaries().into_iter().map(|(a, b)| (id(a), id(b))).collect::<Vec<(usize, usize)>>();
+        // the edge in, the two branches between 1 and 4, and the edge out, in a chain under the root
+        assert_eq!(boundaries, vec![(0, 1), (1, 4), (4, 5)]);
+        assert_eq!(tree.inside(0).len(), 6);
+        assert!(tree.inside(1).is_empty() && tree.inside(3).is_empty());
+        assert_eq!(tree.inside(2), vec![2, 3]);
+        assert!(tree.regions[1..].iter().all(|r| r.parent == Some(0)));
+    }
+
+    #[test]
+    fn random_graphs() {
+        for graph in seeded_graphs(42, 12, 6).take(200) {
+            let tree = NodeStructureTree::new(&graph);
+            check(&graph, &tree);
+        }
+    }
+}
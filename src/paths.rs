@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use crate::bidirected::{BiGraph, Handle, Orientation};
+use crate::sequence::PackedSequence;
+
+// a walk through a bidirected graph, from a GFA P line or W line
+#[derive(Clone,Debug,PartialEq)]
+pub struct Path {
+    pub name: String, // for a W line sample#haplotype#sequence, as in PanSN
+    pub steps: Vec<Handle>,
+    pub offset: usize, // position of the first base on its sequence, from the W line, 0 for a P line
+}
+
+// the named paths of a graph, in the order they were added
+#[derive(Clone,Debug,Default)]
+pub struct PathStore {
+    pub paths: Vec<Path>,
+    index: HashMap<String, usize>,
+}
+
+impl PathStore {
+    pub fn new() -> PathStore {
+        PathStore::default()
+    }
+
+    // add a path, replacing one of the same name
+    pub fn add(&mut self, path: Path) -> usize {
+        if let Some(p) = self.index.get(&path.name) {
+            self.paths[*p] = path;
+            return *p;
+        }
+        let p = self.paths.len();
+        self.index.insert(path.name.clone(), p);
+        self.paths.push(path);
+        p
+    }
+
+    pub fn path_index(&self, name: &str) -> Option<usize> {
+        self.index.get(name).cloned()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.path_index(name).map(|p| &self.paths[p])
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.paths.iter().map(|p| p.name.as_str()).collect()
+    }
+}
+
+// positions along a path: where each step starts on the sequence the path spells, counting from the
+// path's offset
+#[derive(Clone,Debug)]
+pub struct PathIndex {
+    pub offset: usize,
+    starts: Vec<usize>, // cumulative lengths from the start of the path, one more than the steps
+}
+
+impl PathIndex {
+    pub fn new(path: &Path, sequences: &[PackedSequence]) -> PathIndex {
+        let mut starts = Vec::with_capacity(path.steps.len() + 1);
+        starts.push(0);
+        for h in path.steps.iter() {
+            starts.push(starts.last().unwrap() + sequences[h.node].len());
+        }
+        PathIndex { offset: path.offset, starts }
+    }
+
+    // 0-based position of the first base of a step; the step count gives the end of the path
+    pub fn start(&self, step: usize) -> usize {
+        self.offset + self.starts[step]
+    }
+
+    // bases the path spells
+    pub fn length(&self) -> usize {
+        *self.starts.last().unwrap()
+    }
+
+    // the step covering a 0-based position, None outside the path
+    pub fn step_at(&self, position: usize) -> Option<usize> {
+        let position = position.checked_sub(self.offset)?;
+        if position >= self.length() {
+            return None;
+        }
+        // the last step starting at or before the position, past the empty steps starting there too
+        Some(self.starts.partition_point(|s| *s <= position) - 1)
+    }
+}
+
+// the steps of a P line, segment names each followed by their orientation and separated by commas;
+// segments not seen yet are added to the graph
+pub fn parse_p_steps(graph: &mut BiGraph, steps: &str) -> Result<Vec<Handle>, String> {
+    steps.split(',')
+        .map(|step| {
+            let (name, orientation) = match (step.strip_suffix('+'), step.strip_suffix('-')) {
+                (Some(name), _) => (name, Orientation::Forward),
+                (_, Some(name)) => (name, Orientation::Reverse),
+                _ => return Err(format!("bad step {}", step)),
+            };
+            if name.is_empty() {
+                return Err(format!("bad step {}", step));
+            }
+            Ok(Handle::new(graph.add_node(name), orientation))
+        })
+        .collect()
+}
+
+// the steps of a W line, segment names each preceded by > when forward or < when reversed
+pub fn parse_w_steps(graph: &mut BiGraph, walk: &str) -> Result<Vec<Handle>, String> {
+    let mut steps = Vec::new();
+    let mut rest = walk;
+    while !rest.is_empty() {
+        let orientation = match rest.as_bytes()[0] {
+            b'>' => Orientation::Forward,
+            b'<' => Orientation::Reverse,
+            _ => return Err(format!("bad walk {}", walk)),
+        };
+        let end = rest[1..].find(['>', '<']).map_or(rest.len(), |i| i + 1);
+        if end == 1 {
+            return Err(format!("bad walk {}", walk));
+        }
+        steps.push(Handle::new(graph.add_node(&rest[1..end]), orientation));
+        rest = &rest[end..];
+    }
+    Ok(steps)
+}
+
+// a path name split as PanSN, sample#haplotype#contig; a name not in that form is a sample of its own
+// with haplotype 0
+pub fn parse_pansn(name: &str) -> (&str, usize, &str) {
+    let fields = name.splitn(3, '#').collect::<Vec<&str>>();
+    match fields.as_slice() {
+        [sample, haplotype, contig] => match haplotype.parse() {
+            Ok(h) => (sample, h, contig),
+            Err(_) => (name, 0, name),
+        },
+        _ => (name, 0, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let mut graph = BiGraph::new();
+        let p = parse_p_steps(&mut graph, "a+,b-,c+").unwrap();
+        let w = parse_w_steps(&mut graph, ">a<b>c").unwrap();
+        assert_eq!(p, w);
+        assert_eq!(p[1], Handle::new(1, Orientation::Reverse));
+        assert!(parse_p_steps(&mut graph, "a+,b").is_err());
+        // a step ending in a character wider than a byte has no orientation
+        assert_eq!(parse_p_steps(&mut graph, "a+,bé").unwrap_err(), "bad step bé");
+        assert_eq!(parse_p_steps(&mut graph, "é+").unwrap(), vec![Handle::new(3, Orientation::Forward)]);
+        assert!(parse_w_steps(&mut graph, "a>b").is_err());
+        assert_eq!(parse_pansn("HG002#1#chr20"), ("HG002", 1, "chr20"));
+        assert_eq!(parse_pansn("GRCh38"), ("GRCh38", 0, "GRCh38"));
+    }
+
+    #[test]
+    fn positions() {
+        let graph = BiGraph::from_gfa("S\ta\tACG\nS\tb\t*\nS\tc\tTT\nW\tx\t1\tchr1\t100\t105\t>a>b<c>a\n").unwrap();
+        let index = PathIndex::new(&graph.paths.paths[0], &graph.sequences);
+        assert_eq!((0..5).map(|i| index.start(i)).collect::<Vec<usize>>(), vec![100, 103, 103, 105, 108]);
+        assert_eq!(index.length(), 8);
+        assert_eq!(index.step_at(99), None);
+        assert_eq!(index.step_at(102), Some(0));
+        assert_eq!(index.step_at(103), Some(2));
+        assert_eq!(index.step_at(107), Some(3));
+        assert_eq!(index.step_at(108), None);
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{FlowGraph, StructureTree};
+use crate::digraph::DiGraph;
+
+// index based view of a StructureTree, for analyses that walk regions rather than Rc pointers
+// boundary edges are oriented along the directed graph: the entry edge points into the region
+// and the exit edge points out of it
+#[derive(Clone,Debug)]
+pub struct Region {
+    pub id: usize, // id of the region in the structure tree
+    pub class: usize, // cycle equivalence class of the boundary edges
+    pub parent: Option<usize>, // index of the parent region in the table
+    pub children: Vec<usize>, // indexes of the child regions in the table
+    pub nodes: Vec<usize>, // node indexes directly inside the region, not inside a child
+    pub entry: Option<usize>, // edge index of the entry edge, None for the root
+    pub exit: Option<usize>, // edge index of the exit edge, None for the root
+}
+
+// what an edge of a region's local graph stands for
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum LocalEdgeKind {
+    Edge(usize), // an edge of the graph between vertices of the region
+    Entry(Option<usize>), // from start to the first node, with the region's entry edge if it has one
+    Exit(Option<usize>), // from the last node to end, with the region's exit edge if it has one
+    Bypass, // from start straight to end
+}
+
+#[derive(Clone,Copy,Debug)]
+pub struct LocalEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: LocalEdgeKind,
+}
+
+// one region with each child region collapsed to a single vertex, closed by a start vertex standing for
+// its entry edge and an end vertex standing for its exit edge
+// vertex i < nodes.len() is node nodes[i], the next children.len() vertices are the child regions,
+// followed by start and end
+#[derive(Clone,Debug)]
+pub struct LocalGraph {
+    pub region: usize,
+    pub nodes: Vec<usize>,
+    pub children: Vec<usize>,
+    pub start: usize,
+    pub end: usize,
+    pub edges: Vec<LocalEdge>,
+}
+
+impl LocalGraph {
+    pub fn vertex_count(&self) -> usize {
+        self.end + 1
+    }
+
+    // vertex of a child region
+    pub fn child_vertex(&self, i: usize) -> usize {
+        self.nodes.len() + i
+    }
+
+    pub fn is_node(&self, v: usize) -> bool {
+        v < self.nodes.len()
+    }
+
+    pub fn is_child(&self, v: usize) -> bool {
+        v >= self.nodes.len() && v < self.start
+    }
+
+    // successor lists, with or without the start to end bypass
+    pub fn successors(&self, bypass: bool) -> Vec<Vec<usize>> {
+        let mut succs = vec![Vec::new(); self.vertex_count()];
+        for e in self.edges.iter() {
+            if bypass || e.kind != LocalEdgeKind::Bypass {
+                succs[e.from].push(e.to);
+            }
+        }
+        succs
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct RegionTable {
+    pub regions: Vec<Region>, // regions in preorder, the root first
+    pub node_region: Vec<usize>, // innermost region of each node index
+}
+
+impl RegionTable {
+    // build the table from a tree and the graph it was built from
+    // nodes the tree does not place are put in the root region
+    // every region but the root must have an entry edge leading into it and an exit edge leading out of it,
+    // as recorded; the first region that does not is returned as an error
+    pub fn new(graph: &FlowGraph, tree: &StructureTree) -> Result<RegionTable, String> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let mut node_index = HashMap::new();
+        for n in graph.node_indices() {
+            node_index.insert(Rc::as_ptr(&graph[n]), n.index());
+        }
+        // copy the tree, numbering regions in preorder
+        let tree_regions = tree.regions();
+        let mut table_index = HashMap::new();
+        for (i, r) in tree_regions.iter().enumerate() {
+            table_index.insert(Rc::as_ptr(r), i);
+        }
+        let mut regions = Vec::with_capacity(tree_regions.len());
+        for r in tree_regions.iter() {
+            let r = r.borrow();
+            regions.push(Region {
+                id: r.id,
+                class: r.class,
+                parent: r.parent.as_ref().and_then(|p| table_index.get(&Rc::as_ptr(p)).cloned()),
+                children: r.children.iter().filter_map(|c| table_index.get(&Rc::as_ptr(c)).cloned()).collect(),
+                nodes: r.nodes.iter().filter_map(|n| node_index.get(&Rc::as_ptr(n)).cloned()).collect(),
+                entry: r.entry.map(|e| e.index()),
+                exit: r.exit.map(|e| e.index()),
+            });
+        }
+        if regions.is_empty() {
+            regions.push(Region { id: 0, class: 0, parent: None, children: Vec::new(), nodes: Vec::new(), entry: None, exit: None });
+        }
+        let mut table = RegionTable {
+            regions,
+            node_region: vec![0; graph.node_count()],
+        };
+        table.index_nodes(graph.node_count());
+        for r in 1..table.regions.len() {
+            table.check_boundary(&digraph, r)?;
+        }
+        Ok(table)
+    }
+
+    // recompute node_region from the region node lists
+    fn index_nodes(&mut self, node_count: usize) {
+        let mut placed = vec![false; node_count];
+        for (r, region) in self.regions.iter().enumerate() {
+            for n in region.nodes.iter() {
+                self.node_region[*n] = r;
+                placed[*n] = true;
+            }
+        }
+        for (n, p) in placed.iter().enumerate() {
+            if !p {
+                self.node_region[n] = 0;
+                self.regions[0].nodes.push(n);
+            }
+        }
+    }
+
+    // does the entry edge point into the region and the exit edge out of it?
+    fn check_boundary(&self, digraph: &DiGraph, r: usize) -> Result<(), String> {
+        let region = &self.regions[r];
+        let (entry, exit) = match (region.entry, region.exit) {
+            (Some(entry), Some(exit)) => (entry, exit),
+            _ => return Err(format!("region {}: missing entry or exit edge", region.id)),
+        };
+        let crosses = |e: usize, into: bool| match digraph.endpoints(e) {
+            Some((from, to)) => self.contains(r, from) != into && self.contains(r, to) == into,
+            None => false,
+        };
+        if entry == exit || !crosses(entry, true) || !crosses(exit, false) {
+            return Err(format!("region {}: entry {} and exit {} do not lead into and out of it", region.id, entry, exit));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    // is region a equal to or nested inside region b?
+    pub fn is_within(&self, a: usize, b: usize) -> bool {
+        let mut r = Some(a);
+        while let Some(x) = r {
+            if x == b {
+                return true;
+            }
+            r = self.regions[x].parent;
+        }
+        false
+    }
+
+    // is the node inside the region or one of its descendants?
+    pub fn contains(&self, r: usize, node: usize) -> bool {
+        self.is_within(self.node_region[node], r)
+    }
+
+    // the child of region r whose subtree holds the node, None if the node is directly in r or outside it
+    pub fn child_containing(&self, r: usize, node: usize) -> Option<usize> {
+        let mut x = self.node_region[node];
+        while let Some(p) = self.regions[x].parent {
+            if p == r {
+                return Some(x);
+            }
+            x = p;
+        }
+        None
+    }
+
+    // all nodes of the region and its descendants
+    pub fn all_nodes(&self, r: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut stack = vec![r];
+        while let Some(x) = stack.pop() {
+            result.extend(self.regions[x].nodes.iter().cloned());
+            stack.extend(self.regions[x].children.iter().cloned());
+        }
+        result.sort_unstable();
+        result
+    }
+
+    // the local graph of a region
+    // the root region starts at the source node and ends through the return edge, or at the sink without one
+    pub fn local_graph(&self, digraph: &DiGraph, r: usize) -> LocalGraph {
+        let region = &self.regions[r];
+        let is_root = region.parent.is_none();
+        let mut local = vec![usize::MAX; digraph.node_count()];
+        for (i, n) in region.nodes.iter().enumerate() {
+            local[*n] = i;
+        }
+        let child_base = region.nodes.len();
+        let start = child_base + region.children.len();
+        let end = start + 1;
+        let vertex = |n: usize| -> Option<usize> {
+            if local[n] != usize::MAX {
+                return Some(local[n]);
+            }
+            self.child_containing(r, n)
+                .map(|c| child_base + region.children.iter().position(|x| *x == c).unwrap())
+        };
+        let mut edges = Vec::new();
+        edges.push(LocalEdge { from: start, to: end, kind: LocalEdgeKind::Bypass });
+        if is_root {
+            edges.push(LocalEdge { from: start, to: vertex(digraph.entry).unwrap(), kind: LocalEdgeKind::Entry(None) });
+            if digraph.return_edge.is_none() {
+                edges.push(LocalEdge { from: vertex(digraph.exit).unwrap(), to: end, kind: LocalEdgeKind::Exit(None) });
+            }
+        }
+        // the edges at the region's own nodes and the boundaries of its children; a child is left only
+        // through its boundary, so no other edge joins two vertices of the region
+        let mut candidates = Vec::new();
+        for n in region.nodes.iter() {
+            candidates.extend(digraph.out_edges[*n].iter().cloned());
+            candidates.extend(digraph.in_edges[*n].iter().cloned());
+        }
+        for c in region.children.iter() {
+            candidates.extend(self.regions[*c].entry);
+            candidates.extend(self.regions[*c].exit);
+        }
+        candidates.extend(region.entry);
+        candidates.extend(region.exit);
+        candidates.sort_unstable();
+        candidates.dedup();
+        for e in candidates {
+            let (a, b) = match digraph.endpoints(e) {
+                Some(ab) => ab,
+                None => continue,
+            };
+            if region.entry == Some(e) {
+                edges.push(LocalEdge { from: start, to: vertex(b).unwrap(), kind: LocalEdgeKind::Entry(Some(e)) });
+            } else if region.exit == Some(e) || (is_root && digraph.is_return_edge(e)) {
+                edges.push(LocalEdge { from: vertex(a).unwrap(), to: end, kind: LocalEdgeKind::Exit(Some(e)) });
+            } else if self.contains(r, a) && self.contains(r, b) {
+                let (va, vb) = (vertex(a).unwrap(), vertex(b).unwrap());
+                // edges inside a child belong to the child's own local graph
+                if va == vb && va >= child_base {
+                    continue;
+                }
+                edges.push(LocalEdge { from: va, to: vb, kind: LocalEdgeKind::Edge(e) });
+            }
+        }
+        LocalGraph {
+            region: r,
+            nodes: region.nodes.clone(),
+            children: region.children.clone(),
+            start,
+            end,
+            edges,
+        }
+    }
+
+    // nesting depth, the root has depth 0
+    pub fn depth(&self, r: usize) -> usize {
+        let mut depth = 0;
+        let mut x = r;
+        while let Some(p) = self.regions[x].parent {
+            depth += 1;
+            x = p;
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::seeded_graphs;
+
+    // the edges of a local graph found by looking at every edge of the graph
+    fn scanned_edges(table: &RegionTable, digraph: &DiGraph, r: usize) -> Vec<(usize, usize, LocalEdgeKind)> {
+        let local = table.local_graph(digraph, r);
+        let region = &table.regions[r];
+        let vertex = |n: usize| match region.nodes.iter().position(|x| *x == n) {
+            Some(i) => i,
+            None => local.child_vertex(region.children.iter().position(|c| Some(*c) == table.child_containing(r, n)).unwrap()),
+        };
+        let mut edges = Vec::new();
+        for (e, endpoints) in digraph.edges.iter().enumerate() {
+            let (a, b) = match endpoints {
+                Some(ab) => *ab,
+                None => continue,
+            };
+            if region.entry == Some(e) {
+                edges.push((local.start, vertex(b), LocalEdgeKind::Entry(Some(e))));
+            } else if region.exit == Some(e) || (r == 0 && digraph.is_return_edge(e)) {
+                edges.push((vertex(a), local.end, LocalEdgeKind::Exit(Some(e))));
+            } else if table.contains(r, a) && table.contains(r, b) && (vertex(a) != vertex(b) || local.is_node(vertex(a))) {
+                edges.push((vertex(a), vertex(b), LocalEdgeKind::Edge(e)));
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn local_graphs() {
+        let mut graphs = vec![make_example_fig1(), make_example_diamond()];
+        graphs.extend(seeded_graphs(31, 12, 8).take(50));
+        for mut graph in graphs {
+            let tree = build_structure_tree(&mut graph);
+            let table = RegionTable::new(&graph, &tree).unwrap();
+            let digraph = DiGraph::from_flow_graph(&graph);
+            for r in 0..table.len() {
+                let local = table.local_graph(&digraph, r);
+                let edges = local.edges.iter()
+                    .filter(|e| matches!(e.kind, LocalEdgeKind::Edge(_) | LocalEdgeKind::Entry(Some(_)) | LocalEdgeKind::Exit(Some(_))))
+                    .map(|e| (e.from, e.to, e.kind))
+                    .collect::<Vec<_>>();
+                assert_eq!(edges, scanned_edges(&table, &digraph, r));
+            }
+        }
+    }
+
+    #[test]
+    fn unoriented_region_is_an_error() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let region = tree.regions()[1].clone();
+        let (entry, exit) = (region.borrow().entry, region.borrow().exit);
+        region.borrow_mut().entry = exit;
+        region.borrow_mut().exit = entry;
+        let error = RegionTable::new(&graph, &tree).unwrap_err();
+        assert!(error.starts_with(&format!("region {}:", region.borrow().id)), "{}", error);
+        region.borrow_mut().exit = None;
+        assert!(RegionTable::new(&graph, &tree).unwrap_err().contains("missing"));
+        region.borrow_mut().entry = entry;
+        region.borrow_mut().exit = exit;
+        assert!(RegionTable::new(&graph, &tree).is_ok());
+        assert!(traversals::traversals_of(&graph, &tree, &region.borrow(), &Default::default()).is_ok());
+    }
+}
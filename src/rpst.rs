@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use petgraph::graph::NodeIndex;
+
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+use crate::triconnected::{ComponentKind, Triconnected};
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FragmentKind {
+    Trivial, // a single edge
+    Polygon, // a sequence of fragments
+    Bond, // fragments side by side between the same two nodes
+    Rigid, // anything else
+}
+
+// a canonical fragment of the refined process structure tree, bounded by two nodes
+// fragments are node bounded rather than edge bounded like the regions of a StructureTree, so a
+// sequence splits at every node and a rigid fragment is broken down further instead of being left whole
+#[derive(Clone,Debug)]
+pub struct Fragment {
+    pub kind: FragmentKind,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>, // in order along the sequence for a polygon
+    pub edges: Vec<usize>, // sorted edge indexes of every edge in the fragment
+    pub boundary: (usize, usize), // node indexes of the two boundary nodes
+    pub entry: Option<usize>, // the boundary node edges only leave, if the fragment has one
+    pub exit: Option<usize>, // the boundary node edges only enter, if the fragment has one
+}
+
+// refined process structure tree, after Polyvyanyy, Vanhatalo and Völzer, "Simplified computation and
+// generalization of the refined process structure tree"
+// the triconnected components of the graph with its return edge form a tree; rooted at the component
+// holding the return edge, each component gives the fragment made of the edges in its subtree, and each
+// edge other than the return edge is a trivial fragment under the component it is in
+#[derive(Clone,Debug)]
+pub struct Rpst {
+    pub fragments: Vec<Fragment>, // preorder, the root first
+}
+
+impl Rpst {
+    // the RPST of a flow graph, None when the graph with its return edge is not biconnected
+    pub fn new(graph: &FlowGraph) -> Option<Rpst> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let decomposition = Triconnected::from_flow_graph(graph)?;
+        let root_edge = digraph.return_edge.unwrap_or_else(|| digraph.edges.iter().position(|e| e.is_some()).unwrap());
+        let split_root = decomposition.edges.iter().position(|e| e.real == Some(root_edge)).unwrap();
+        let root = decomposition.components_of(split_root)[0];
+        let mut rpst = Rpst { fragments: Vec::new() };
+        let (a, b) = (digraph.entry, digraph.exit);
+        rpst.add_component(&digraph, &decomposition, root, split_root, (a, b), None);
+        Some(rpst)
+    }
+
+    // add the fragment of a component entered from its parent through the split edge `from`
+    fn add_component(&mut self, digraph: &DiGraph, decomposition: &Triconnected, c: usize, from: usize,
+                     boundary: (usize, usize), parent: Option<usize>) -> usize {
+        let kind = match decomposition.components[c].kind {
+            ComponentKind::Bond => FragmentKind::Bond,
+            ComponentKind::Polygon => FragmentKind::Polygon,
+            ComponentKind::Rigid => FragmentKind::Rigid,
+        };
+        let index = self.push(Fragment { kind, parent, children: Vec::new(), edges: Vec::new(), boundary, entry: None, exit: None });
+        let mut edges = decomposition.components[c].edges.iter().cloned().filter(|e| *e != from).collect::<Vec<usize>>();
+        if kind == FragmentKind::Polygon {
+            edges = walk_cycle(decomposition, &edges, boundary.0);
+        }
+        let mut all = Vec::new();
+        for e in edges {
+            let split = decomposition.edges[e];
+            let child = match split.real {
+                Some(real) => {
+                    let (x, y) = digraph.endpoints(real).unwrap();
+                    self.push(Fragment {
+                        kind: FragmentKind::Trivial,
+                        parent: Some(index),
+                        children: Vec::new(),
+                        edges: vec![real],
+                        boundary: (x, y),
+                        entry: Some(x),
+                        exit: Some(y),
+                    })
+                }
+                None => {
+                    let other = decomposition.components_of(e).into_iter().find(|d| *d != c).unwrap();
+                    self.add_component(digraph, decomposition, other, e, split.ends, Some(index))
+                }
+            };
+            all.extend(self.fragments[child].edges.iter().cloned());
+            self.fragments[index].children.push(child);
+        }
+        all.sort_unstable();
+        let (entry, exit) = orient(digraph, &all, boundary);
+        let fragment = &mut self.fragments[index];
+        fragment.edges = all;
+        fragment.entry = entry;
+        fragment.exit = exit;
+        index
+    }
+
+    fn push(&mut self, fragment: Fragment) -> usize {
+        self.fragments.push(fragment);
+        self.fragments.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+
+    // fragments of one kind
+    pub fn of_kind(&self, kind: FragmentKind) -> Vec<usize> {
+        (0..self.fragments.len()).filter(|f| self.fragments[*f].kind == kind).collect()
+    }
+
+    // the nodes of a fragment other than its boundary nodes
+    pub fn interior(&self, digraph: &DiGraph, f: usize) -> Vec<usize> {
+        let fragment = &self.fragments[f];
+        let nodes = fragment.edges.iter()
+            .flat_map(|e| {
+                let (x, y) = digraph.endpoints(*e).unwrap();
+                vec![x, y]
+            })
+            .filter(|n| *n != fragment.boundary.0 && *n != fragment.boundary.1)
+            .collect::<BTreeSet<usize>>();
+        nodes.into_iter().collect()
+    }
+
+    // the tree in dot format, labelled with kind and boundary node ids
+    pub fn to_dot(&self, graph: &FlowGraph) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut dot = String::new();
+        dot.push_str("digraph {\n");
+        for (i, f) in self.fragments.iter().enumerate() {
+            let (a, b) = match (f.entry, f.exit) {
+                (Some(a), Some(b)) => (a, b),
+                _ => f.boundary,
+            };
+            let arrow = if f.entry.is_some() { "->" } else { "--" };
+            dot.push_str(format!("f{} [label=\"{:?} {}{}{}\"];\n", i, f.kind, id(a), arrow, id(b)).as_str());
+            for c in f.children.iter() {
+                dot.push_str(format!("f{} -> f{};\n", i, c).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// the edges of a polygon in order along the path from one end of the missing edge to the other
+fn walk_cycle(decomposition: &Triconnected, edges: &[usize], start: usize) -> Vec<usize> {
+    let mut at: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for e in edges.iter() {
+        let (a, b) = decomposition.edges[*e].ends;
+        at.entry(a).or_default().push(*e);
+        at.entry(b).or_default().push(*e);
+    }
+    let mut order = Vec::new();
+    let mut node = start;
+    let mut used = BTreeSet::new();
+    while let Some(e) = at.get(&node).and_then(|es| es.iter().find(|e| !used.contains(*e))) {
+        used.insert(*e);
+        order.push(*e);
+        let (a, b) = decomposition.edges[*e].ends;
+        node = if a == node { b } else { a };
+    }
+    order
+}
+
+// entry and exit of a fragment: edges only leave the entry and only enter the exit
+fn orient(digraph: &DiGraph, edges: &[usize], boundary: (usize, usize)) -> (Option<usize>, Option<usize>) {
+    let only = |n: usize, leaving: bool| {
+        edges.iter().all(|e| {
+            let (x, y) = digraph.endpoints(*e).unwrap();
+            if leaving { y != n } else { x != n }
+        })
+    };
+    let (a, b) = boundary;
+    if only(a, true) && only(b, false) {
+        (Some(a), Some(b))
+    } else if only(b, true) && only(a, false) {
+        (Some(b), Some(a))
+    } else {
+        (None, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::regions::RegionTable;
+    use crate::triconnected::is_biconnected;
+    use crate::test_utils::seeded_graphs;
+
+    // every fragment is made of its children, and only its boundary nodes touch the rest of the graph
+    fn check(graph: &FlowGraph, rpst: &Rpst) {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let mut trivial = Vec::new();
+        for (f, fragment) in rpst.fragments.iter().enumerate() {
+            if fragment.kind == FragmentKind::Trivial {
+                assert!(fragment.children.is_empty());
+                trivial.extend(fragment.edges.iter().cloned());
+                continue;
+            }
+            let mut edges = fragment.children.iter()
+                .flat_map(|c| rpst.fragments[*c].edges.iter().cloned())
+                .collect::<Vec<usize>>();
+            edges.sort_unstable();
+            assert_eq!(edges, fragment.edges);
+            let interior = rpst.interior(&digraph, f);
+            for (e, ends) in digraph.edges.iter().enumerate() {
+                if let Some((x, y)) = ends {
+                    if interior.contains(x) || interior.contains(y) {
+                        assert!(fragment.edges.contains(&e), "edge {} leaves fragment {}", e, f);
+                    }
+                }
+            }
+        }
+        trivial.sort_unstable();
+        let mut real = (0..digraph.edges.len())
+            .filter(|e| digraph.edges[*e].is_some() && !digraph.is_return_edge(*e))
+            .collect::<Vec<usize>>();
+        real.sort_unstable();
+        assert_eq!(trivial, real);
+    }
+
+    #[test]
+    fn nonplanar_1_differs_from_pst() {
+        let mut graph = make_nonplanar_1();
+        let rpst = Rpst::new(&graph).unwrap();
+        check(&graph, &rpst);
+        let digraph = DiGraph::from_flow_graph(&graph);
+        // node ids are the node indexes in this example
+        // 0 -> 1, the block from 1 to 6, then 6 -> 7 back to 0
+        assert_eq!(rpst.fragments[0].kind, FragmentKind::Polygon);
+        assert_eq!((rpst.fragments[0].entry, rpst.fragments[0].exit), (Some(0), Some(7)));
+        let rigid = rpst.of_kind(FragmentKind::Rigid);
+        assert_eq!(rigid.len(), 1);
+        let r = &rpst.fragments[rigid[0]];
+        assert_eq!((r.entry, r.exit), (Some(1), Some(6)));
+        assert_eq!(rpst.interior(&digraph, rigid[0]), vec![2, 3, 4, 5]);
+        // the two edges between 2 and 4 run in opposite directions, so their bond has no entry
+        let bonds = rpst.of_kind(FragmentKind::Bond);
+        assert_eq!(bonds.len(), 1);
+        let bond = &rpst.fragments[bonds[0]];
+        assert_eq!(bond.parent, Some(rigid[0]));
+        assert_eq!(bond.entry, None);
+        // the structure tree has the same block from 1 to 6, but as one flat region
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let region = (0..table.len()).find(|r| table.all_nodes(*r) == vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert!(table.regions[region].children.is_empty());
+    }
+
+    #[test]
+    fn fig1() {
+        let graph = make_example_fig1();
+        let rpst = Rpst::new(&graph).unwrap();
+        check(&graph, &rpst);
+        let digraph = DiGraph::from_flow_graph(&graph);
+        // 8 -> 10 crosses the branches of 4, leaving 4, 8, 10 and 12 fully connected
+        let rigid = rpst.of_kind(FragmentKind::Rigid);
+        assert_eq!(rigid.len(), 1);
+        assert_eq!((rpst.fragments[rigid[0]].entry, rpst.fragments[rigid[0]].exit), (Some(4), Some(12)));
+        assert_eq!(rpst.interior(&digraph, rigid[0]), vec![6, 8, 10]);
+        // the branches of 5 are a bond of two sequences
+        let bond = rpst.fragments.iter().find(|f| f.kind == FragmentKind::Bond && f.boundary == (5, 11)).unwrap();
+        assert_eq!(bond.children.len(), 2);
+        assert!(rpst.fragments.iter().all(|f| f.entry.is_some()));
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut checked = 0;
+        for graph in seeded_graphs(9, 12, 6) {
+            let digraph = DiGraph::from_flow_graph(&graph);
+            if !is_biconnected(&digraph.edges) {
+                assert!(Rpst::new(&graph).is_none());
+                continue;
+            }
+            let rpst = Rpst::new(&graph).unwrap();
+            check(&graph, &rpst);
+            checked += 1;
+            if checked == 100 {
+                break;
+            }
+        }
+    }
+}
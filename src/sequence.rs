@@ -0,0 +1,124 @@
+use std::fmt;
+
+// DNA packed two bits to a base, A C G T being 0 to 3; any other IUPAC code, N among them, is kept apart
+// in runs of the same base with an A in each packed slot; case is not kept
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct PackedSequence {
+    len: usize,
+    packed: Vec<u8>, // four bases a byte, the first in the low bits
+    escapes: Vec<(usize, usize, u8)>, // start, length and base of each run of bases other than A C G T, by start
+}
+
+impl PackedSequence {
+    // fails on a character that is not an IUPAC code
+    pub fn new(sequence: &str) -> Result<PackedSequence, String> {
+        let mut packed = vec![0; sequence.len().div_ceil(4)];
+        let mut escapes: Vec<(usize, usize, u8)> = Vec::new();
+        for (i, c) in sequence.chars().map(|c| c.to_ascii_uppercase()).enumerate() {
+            let code = match c {
+                'A' => 0,
+                'C' => 1,
+                'G' => 2,
+                'T' => 3,
+                'R' | 'Y' | 'S' | 'W' | 'K' | 'M' | 'B' | 'D' | 'H' | 'V' | 'N' => {
+                    match escapes.last_mut() {
+                        Some((start, len, base)) if *start + *len == i && *base == c as u8 => *len += 1,
+                        _ => escapes.push((i, 1, c as u8)),
+                    }
+                    0
+                }
+                _ => return Err(format!("bad base {}", c)),
+            };
+            packed[i / 4] |= code << (2 * (i % 4));
+        }
+        Ok(PackedSequence { len: sequence.len(), packed, escapes })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the base at a position
+    pub fn get(&self, i: usize) -> u8 {
+        assert!(i < self.len, "position {} past the end of a sequence of {}", i, self.len);
+        // the last run starting at or before the position, if it reaches that far
+        match self.escapes.partition_point(|(start, _, _)| *start <= i).checked_sub(1).map(|e| self.escapes[e]) {
+            Some((start, len, base)) if i < start + len => base,
+            _ => b"ACGT"[((self.packed[i / 4] >> (2 * (i % 4))) & 3) as usize],
+        }
+    }
+
+    pub fn bases(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    // G, C and S (either of them)
+    pub fn gc_count(&self) -> usize {
+        self.bases().filter(|b| matches!(b, b'G' | b'C' | b'S')).count()
+    }
+
+    pub fn n_count(&self) -> usize {
+        self.escapes.iter().filter(|(_, _, b)| *b == b'N').map(|(_, len, _)| len).sum()
+    }
+
+    pub fn reverse_complement(&self) -> String {
+        reverse_complement(&self.to_string())
+    }
+}
+
+impl fmt::Display for PackedSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.bases().collect::<Vec<u8>>()))
+    }
+}
+
+// the reverse complement, IUPAC codes included; other characters become N
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence.bytes().rev().map(|b| complement(b.to_ascii_uppercase()) as char).collect()
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'S' => b'S',
+        b'W' => b'W',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        _ => b'N',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packing() {
+        let sequence = PackedSequence::new("acGTNnnRtgcaS").unwrap();
+        assert_eq!(sequence.to_string(), "ACGTNNNRTGCAS");
+        assert_eq!(sequence.len(), 13);
+        assert_eq!(sequence.packed.len(), 4);
+        assert_eq!(sequence.escapes, vec![(4, 3, b'N'), (7, 1, b'R'), (12, 1, b'S')]);
+        assert_eq!((sequence.get(6), sequence.get(8)), (b'N', b'T'));
+        assert_eq!((sequence.gc_count(), sequence.n_count()), (5, 3));
+        assert_eq!(sequence.reverse_complement(), "STGCAYNNNACGT");
+        assert_eq!(reverse_complement(&sequence.reverse_complement()), sequence.to_string());
+        assert!(PackedSequence::new("").unwrap().is_empty());
+        // characters other than IUPAC codes, a multibyte one among them
+        assert_eq!(PackedSequence::new("ACGé").unwrap_err(), "bad base é");
+        assert!(PackedSequence::new("AC-GT").is_err());
+    }
+}
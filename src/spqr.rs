@@ -0,0 +1,272 @@
+use std::collections::BTreeSet;
+
+use petgraph::graph::NodeIndex;
+
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+use crate::triconnected::{ComponentKind, Triconnected};
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SpqrKind {
+    S, // series: the skeleton is a cycle
+    P, // parallel: two poles joined by three or more edges
+    Q, // a single real edge
+    R, // rigid: the skeleton is triconnected
+}
+
+// a node of an SPQR tree; each virtual edge of its skeleton stands for the subgraph on the far side of
+// it, and the reference edge is the skeleton edge shared with the parent
+#[derive(Clone,Debug)]
+pub struct SpqrNode {
+    pub kind: SpqrKind,
+    pub component: Option<usize>, // triconnected component of the skeleton, None for a Q node
+    pub skeleton: Vec<usize>, // split edges, only the real edge for a Q node
+    pub reference: Option<usize>, // split edge shared with the parent, None at the root
+    pub poles: (usize, usize), // node indexes of the ends of the reference edge
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+// SPQR tree of a biconnected multigraph, after Di Battista and Tamassia, "On-line maintenance of
+// triconnected components with SPQR-trees", over the linear time triconnected components
+// the tree is rooted at the Q node of a reference edge; every other real edge is a Q leaf under the node
+// whose skeleton holds it, and every virtual edge joins the two nodes whose skeletons hold it
+#[derive(Clone,Debug)]
+pub struct SpqrTree {
+    pub decomposition: Triconnected,
+    pub nodes: Vec<SpqrNode>, // preorder, the root first
+    pub edge_node: Vec<Option<usize>>, // Q node of each graph edge by edge index
+}
+
+impl SpqrTree {
+    // the SPQR tree of a multigraph given as endpoints by edge index, None edges being ignored, rooted
+    // at the Q node of root_edge; None when the graph is not biconnected
+    pub fn new(edges: &[Option<(usize, usize)>], root_edge: usize) -> Option<SpqrTree> {
+        edges.get(root_edge).cloned().flatten()?;
+        let decomposition = Triconnected::new(edges)?;
+        let mut owners = vec![Vec::new(); decomposition.edges.len()];
+        for (c, component) in decomposition.components.iter().enumerate() {
+            for e in component.edges.iter() {
+                owners[*e].push(c);
+            }
+        }
+        let split_root = decomposition.edges.iter().position(|e| e.real == Some(root_edge)).unwrap();
+        let mut tree = SpqrTree { decomposition, nodes: Vec::new(), edge_node: vec![None; edges.len()] };
+        let root = tree.add_q(split_root, None);
+        tree.add_component(&owners, owners[split_root][0], split_root, root);
+        Some(tree)
+    }
+
+    // the SPQR tree of a flow graph without its capping backedges, rooted at the return edge when there
+    // is one; None when the graph is not biconnected
+    pub fn from_flow_graph(graph: &FlowGraph) -> Option<SpqrTree> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let root_edge = digraph.return_edge.or_else(|| digraph.edges.iter().position(|e| e.is_some()))?;
+        SpqrTree::new(&digraph.edges, root_edge)
+    }
+
+    fn push(&mut self, node: SpqrNode) -> usize {
+        let n = self.nodes.len();
+        if let Some(p) = node.parent {
+            self.nodes[p].children.push(n);
+        }
+        self.nodes.push(node);
+        n
+    }
+
+    fn add_q(&mut self, e: usize, parent: Option<usize>) -> usize {
+        let split = self.decomposition.edges[e];
+        let n = self.push(SpqrNode {
+            kind: SpqrKind::Q,
+            component: None,
+            skeleton: vec![e],
+            reference: parent.map(|_| e),
+            poles: split.ends,
+            parent,
+            children: Vec::new(),
+        });
+        self.edge_node[split.real.unwrap()] = Some(n);
+        n
+    }
+
+    // add the node of component c entered from its parent through the split edge `from`
+    fn add_component(&mut self, owners: &[Vec<usize>], c: usize, from: usize, parent: usize) -> usize {
+        let component = &self.decomposition.components[c];
+        let kind = match component.kind {
+            ComponentKind::Bond => SpqrKind::P,
+            ComponentKind::Polygon => SpqrKind::S,
+            ComponentKind::Rigid => SpqrKind::R,
+        };
+        let skeleton = component.edges.clone();
+        let n = self.push(SpqrNode {
+            kind,
+            component: Some(c),
+            skeleton: skeleton.clone(),
+            reference: Some(from),
+            poles: self.decomposition.edges[from].ends,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        for e in skeleton {
+            if e == from {
+                continue;
+            }
+            if self.decomposition.edges[e].real.is_some() {
+                self.add_q(e, Some(n));
+            } else {
+                let other = owners[e].iter().cloned().find(|d| *d != c).unwrap();
+                self.add_component(owners, other, e, n);
+            }
+        }
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // nodes of one kind
+    pub fn of_kind(&self, kind: SpqrKind) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|n| self.nodes[*n].kind == kind).collect()
+    }
+
+    // the graph edges in the subtree of a node, sorted, which for the root is every edge
+    pub fn pertinent_edges(&self, n: usize) -> Vec<usize> {
+        let mut edges = Vec::new();
+        let mut stack = vec![n];
+        while let Some(m) = stack.pop() {
+            if self.nodes[m].kind == SpqrKind::Q {
+                edges.push(self.decomposition.edges[self.nodes[m].skeleton[0]].real.unwrap());
+            }
+            stack.extend(self.nodes[m].children.iter().cloned());
+        }
+        edges.sort();
+        edges
+    }
+
+    // the graph edges a skeleton edge stands for, seen from the root: itself for a real edge and the
+    // pertinent edges of the child below it for a virtual edge
+    pub fn expansion(&self, e: usize) -> Vec<usize> {
+        if let Some(real) = self.decomposition.edges[e].real {
+            return vec![real];
+        }
+        match self.nodes.iter().position(|n| n.reference == Some(e)) {
+            Some(n) => self.pertinent_edges(n),
+            None => Vec::new(),
+        }
+    }
+
+    // the graph edges as cycle equivalence classes: two edges are equivalent exactly when they are real
+    // edges of the same S skeleton, every other edge is alone in its class; classes are sorted
+    pub fn cycle_classes(&self) -> Vec<Vec<usize>> {
+        let mut classes = Vec::new();
+        let mut in_series = BTreeSet::new();
+        for node in self.nodes.iter().filter(|n| n.kind == SpqrKind::S) {
+            let class = node.skeleton.iter().filter_map(|e| self.decomposition.edges[*e].real).collect::<Vec<usize>>();
+            in_series.extend(class.iter().cloned());
+            if !class.is_empty() {
+                classes.push(class);
+            }
+        }
+        for (e, q) in self.edge_node.iter().enumerate() {
+            if q.is_some() && !in_series.contains(&e) {
+                classes.push(vec![e]);
+            }
+        }
+        for class in classes.iter_mut() {
+            class.sort();
+        }
+        classes.sort();
+        classes
+    }
+
+    // the tree in dot format, labelled with kind and pole ids
+    pub fn to_dot(&self, graph: &FlowGraph) -> String {
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut dot = String::new();
+        dot.push_str("graph {\n");
+        for (i, n) in self.nodes.iter().enumerate() {
+            dot.push_str(format!("n{} [label=\"{:?} {}--{}\"];\n", i, n.kind, id(n.poles.0), id(n.poles.1)).as_str());
+            for c in n.children.iter() {
+                dot.push_str(format!("n{} -- n{};\n", i, c).as_str());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::equivalence::CycleEquivalence;
+    use crate::triconnected::is_biconnected;
+    use crate::test_utils::seeded_graphs;
+
+    // the tree is well formed, each node's pertinent edges are the expansions of its skeleton, and the
+    // S nodes give the same classes as cycle equivalence
+    fn check(graph: &mut FlowGraph, tree: &SpqrTree) {
+        let digraph = DiGraph::from_flow_graph(graph);
+        assert_eq!(tree.nodes[0].kind, SpqrKind::Q);
+        assert_eq!(tree.pertinent_edges(0), (0..digraph.edges.len()).filter(|e| digraph.edges[*e].is_some()).collect::<Vec<usize>>());
+        for (i, n) in tree.nodes.iter().enumerate() {
+            for c in n.children.iter() {
+                assert_eq!(tree.nodes[*c].parent, Some(i));
+            }
+            if n.kind == SpqrKind::Q {
+                assert!(n.children.is_empty() || i == 0);
+                continue;
+            }
+            let mut expanded = n.skeleton.iter()
+                .filter(|e| Some(**e) != n.reference)
+                .flat_map(|e| tree.expansion(*e))
+                .collect::<Vec<usize>>();
+            expanded.sort();
+            assert_eq!(expanded, tree.pertinent_edges(i));
+            // neighbouring S nodes and neighbouring P nodes would have been merged
+            let parent = &tree.nodes[n.parent.unwrap()];
+            assert!(parent.kind != n.kind || n.kind == SpqrKind::R);
+        }
+
+        let rev_order = dfs_tree(graph);
+        cycle_equivalence(graph, &rev_order);
+        let query = CycleEquivalence::new(graph);
+        let mut classes = query.class_ids().iter()
+            .map(|c| query.members(*c).iter().map(|e| e.index()).collect::<Vec<usize>>())
+            .collect::<Vec<Vec<usize>>>();
+        classes.sort();
+        assert_eq!(tree.cycle_classes(), classes);
+    }
+
+    #[test]
+    fn fig1() {
+        let mut graph = make_example_fig1();
+        let tree = SpqrTree::from_flow_graph(&graph).unwrap();
+        check(&mut graph, &tree);
+        assert_eq!(tree.of_kind(SpqrKind::R).len(), 1);
+        assert_eq!(tree.of_kind(SpqrKind::Q).len(), tree.edge_node.iter().flatten().count());
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut checked = 0;
+        for mut graph in seeded_graphs(37, 14, 7) {
+            let digraph = DiGraph::from_flow_graph(&graph);
+            let tree = SpqrTree::from_flow_graph(&graph);
+            if !is_biconnected(&digraph.edges) {
+                assert!(tree.is_none());
+                continue;
+            }
+            check(&mut graph, &tree.unwrap());
+            checked += 1;
+            if checked == 200 {
+                break;
+            }
+        }
+    }
+}
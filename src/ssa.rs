@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{FlowGraph, StructureTree};
+use crate::digraph::DiGraph;
+use crate::dominators::{CfgDominance, DominatorTree};
+use crate::regions::RegionTable;
+
+// phi placement for SSA construction
+// defs holds the variables defined at each node index, and the result maps every variable defined
+// anywhere to the sorted node indexes that need a phi for it
+// the source node counts as defining every variable, standing for the values the graph is entered with
+pub type PhiPlacement<V> = BTreeMap<V, Vec<usize>>;
+
+// iterated dominance frontier of a set of vertices
+fn iterated_frontier(frontiers: &[Vec<usize>], sites: &[usize]) -> BTreeSet<usize> {
+    let mut result = BTreeSet::new();
+    let mut queued = vec![false; frontiers.len()];
+    let mut worklist = Vec::new();
+    for s in sites.iter() {
+        if !queued[*s] {
+            queued[*s] = true;
+            worklist.push(*s);
+        }
+    }
+    while let Some(v) = worklist.pop() {
+        for w in frontiers[v].iter() {
+            result.insert(*w);
+            if !queued[*w] {
+                queued[*w] = true;
+                worklist.push(*w);
+            }
+        }
+    }
+    result
+}
+
+// variables defined anywhere, each with the nodes defining it
+fn def_sites<V: Ord + Clone>(defs: &[Vec<V>]) -> BTreeMap<V, Vec<usize>> {
+    let mut sites: BTreeMap<V, Vec<usize>> = BTreeMap::new();
+    for (n, vars) in defs.iter().enumerate() {
+        for v in vars.iter() {
+            let s = sites.entry(v.clone()).or_default();
+            if s.last() != Some(&n) {
+                s.push(n);
+            }
+        }
+    }
+    sites
+}
+
+// the standard placement: phis go on the iterated dominance frontier of the defining nodes
+pub fn place_phis_by_frontiers<V: Ord + Clone>(graph: &FlowGraph, defs: &[Vec<V>]) -> PhiPlacement<V> {
+    let analysis = CfgDominance::new(graph);
+    let entry = analysis.digraph.entry;
+    let mut phis = BTreeMap::new();
+    for (v, mut sites) in def_sites(defs) {
+        sites.push(entry);
+        phis.insert(v, iterated_frontier(&analysis.frontiers, &sites).into_iter().collect());
+    }
+    phis
+}
+
+// placement region by region over the program structure tree
+// a dominance frontier never leaves a region except through its exit edge, so each region is solved on
+// its local graph: child regions are single vertices that define whatever is defined inside them, and
+// start defines everything since values arrive through the entry edge
+// a phi the region needs after its exit shows up in the parent as the frontier of the region's vertex,
+// and a child vertex has a single predecessor so it is never on a frontier itself
+pub fn place_phis<V: Ord + Clone>(graph: &FlowGraph, tree: &StructureTree, defs: &[Vec<V>]) -> Result<PhiPlacement<V>, String> {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let table = RegionTable::new(graph, tree)?;
+    let sites = def_sites(defs);
+    let mut phis: PhiPlacement<V> = sites.keys().map(|v| (v.clone(), Vec::new())).collect();
+    // the source defines everything, which matters when the source is also the head of a loop
+    let mut defs = defs.to_vec();
+    defs.resize(digraph.node_count(), Vec::new());
+    defs[digraph.entry] = sites.keys().cloned().collect();
+    // variables defined in each region, children first
+    let mut region_defs = vec![BTreeSet::new(); table.len()];
+    for r in (0..table.len()).rev() {
+        let mut vars = BTreeSet::new();
+        for n in table.regions[r].nodes.iter() {
+            vars.extend(defs[*n].iter().cloned());
+        }
+        for c in table.regions[r].children.iter() {
+            vars.extend(region_defs[*c].iter().cloned());
+        }
+        region_defs[r] = vars;
+    }
+    for (r, vars) in region_defs.iter().enumerate() {
+        if vars.is_empty() {
+            continue;
+        }
+        let local = table.local_graph(&digraph, r);
+        let succs = local.successors(true);
+        let frontiers = DominatorTree::new(&succs, local.start).frontiers(&succs);
+        for v in vars.iter() {
+            let mut local_sites = vec![local.start];
+            for (i, n) in local.nodes.iter().enumerate() {
+                if defs[*n].contains(v) {
+                    local_sites.push(i);
+                }
+            }
+            for (i, c) in local.children.iter().enumerate() {
+                if region_defs[*c].contains(v) {
+                    local_sites.push(local.child_vertex(i));
+                }
+            }
+            let placed = phis.get_mut(v).unwrap();
+            for w in iterated_frontier(&frontiers, &local_sites) {
+                if local.is_node(w) {
+                    placed.push(local.nodes[w]);
+                }
+            }
+        }
+    }
+    for p in phis.values_mut() {
+        p.sort_unstable();
+    }
+    Ok(phis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::{examples, seeded_graphs, Rng};
+
+    #[test]
+    fn examples_match_iterated_frontiers() {
+        for mut graph in examples() {
+            let tree = build_structure_tree(&mut graph);
+            // one variable per node defined only there, plus one defined at every third node
+            let defs = (0..graph.node_count())
+                .map(|n| if n % 3 == 1 { vec![n, usize::MAX] } else { vec![n] })
+                .collect::<Vec<Vec<usize>>>();
+            assert_eq!(place_phis(&graph, &tree, &defs).unwrap(), place_phis_by_frontiers(&graph, &defs));
+        }
+    }
+
+    #[test]
+    fn fig1_phis() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let mut defs = vec![Vec::new(); graph.node_count()];
+        defs[6].push("x");
+        defs[8].push("x");
+        defs[3].push("y");
+        let phis = place_phis(&graph, &tree, &defs).unwrap();
+        // x meets the other x at 10, that value meets the entry's at 12 and so on out to 15
+        assert_eq!(phis["x"], vec![10, 12, 15]);
+        assert_eq!(phis["y"], vec![13, 15]);
+    }
+
+    #[test]
+    fn random_graphs_match_iterated_frontiers() {
+        // a second generator picks the definitions
+        let mut rng = Rng::new(7);
+        for mut graph in seeded_graphs(7, 14, 6).take(200) {
+            let tree = build_structure_tree(&mut graph);
+            let defs = (0..graph.node_count())
+                .map(|_| (0..3).filter(|_| rng.below(3) == 0).collect())
+                .collect::<Vec<Vec<u64>>>();
+            assert_eq!(place_phis(&graph, &tree, &defs).unwrap(), place_phis_by_frontiers(&graph, &defs));
+        }
+    }
+}
@@ -0,0 +1,731 @@
+use std::collections::BTreeSet;
+
+use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use crate::{FlowGraph, StructureTree};
+use crate::digraph::DiGraph;
+use crate::dominators::{is_reducible, DominatorTree};
+use crate::regions::{LocalEdgeKind, RegionTable};
+
+// a branch condition: run the node, then test whether it left along edge `taken` rather than `not_taken`
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Cond {
+    pub node: usize, // node index
+    pub taken: usize, // edge index
+    pub not_taken: usize, // edge index
+}
+
+impl Cond {
+    pub fn negate(&self) -> Cond {
+        Cond { node: self.node, taken: self.not_taken, not_taken: self.taken }
+    }
+}
+
+// structured statements over node indexes
+// a node with one successor is a plain Node, one with two is the condition of an If or a loop,
+// and one with more is a Switch over its outgoing edge indexes
+// labels are shared by Block, Loop, While and DoWhile and are the targets of Break and Continue;
+// Label and Goto only appear where a region could not be structured
+#[derive(Clone,Debug,PartialEq)]
+pub enum Stmt {
+    Node(usize),
+    If { cond: Cond, then_branch: Vec<Stmt>, else_branch: Vec<Stmt> },
+    Switch { node: usize, arms: Vec<(usize, Vec<Stmt>)> },
+    Block { label: usize, body: Vec<Stmt> }, // break leaves the block
+    Loop { label: usize, body: Vec<Stmt> }, // continue and falling off the end both go round again
+    While { label: usize, cond: Cond, body: Vec<Stmt> },
+    DoWhile { label: usize, body: Vec<Stmt>, cond: Cond }, // continue goes to the condition
+    Break(usize),
+    Continue(usize),
+    Label(usize),
+    Goto(usize),
+}
+
+// the structured program of a flow graph
+#[derive(Clone,Debug)]
+pub struct Structured {
+    pub body: Vec<Stmt>,
+    pub unstructured: Vec<usize>, // table indexes of the regions left as labels and gotos
+}
+
+// what a vertex of a region's local graph branches to, with the graph edge of each branch
+struct RegionCode {
+    out: Vec<Vec<(usize, Option<usize>)>>,
+    rpo: Vec<usize>, // reverse postorder number, usize::MAX when unreachable from start
+    merge: Vec<bool>, // reached by more than one forward edge
+    header: Vec<bool>, // target of a back edge
+    dominators: DominatorTree,
+    loop_label: Vec<usize>,
+    block_label: Vec<usize>,
+    end: usize,
+    end_label: usize,
+}
+
+struct Structurizer<'a> {
+    digraph: &'a DiGraph,
+    table: &'a RegionTable,
+    next_label: usize,
+    unstructured: Vec<usize>,
+}
+
+impl<'a> Structurizer<'a> {
+    fn label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label - 1
+    }
+
+    // code for one region, wrapped in a block whose label is where the region's exit edge leads
+    fn region(&mut self, r: usize) -> Vec<Stmt> {
+        let local = self.table.local_graph(self.digraph, r);
+        let mut out = vec![Vec::new(); local.vertex_count()];
+        for e in local.edges.iter() {
+            let edge = match e.kind {
+                LocalEdgeKind::Bypass => continue,
+                LocalEdgeKind::Edge(x) => Some(x),
+                LocalEdgeKind::Entry(x) | LocalEdgeKind::Exit(x) => x,
+            };
+            out[e.from].push((e.to, edge));
+        }
+        let succs = out.iter().map(|o| o.iter().map(|(t, _)| *t).collect()).collect::<Vec<Vec<usize>>>();
+        // reverse postorder from start
+        let mut postorder = Vec::new();
+        let mut visited = vec![false; succs.len()];
+        let mut stack = vec![(local.start, 0)];
+        visited[local.start] = true;
+        while let Some((v, i)) = stack.pop() {
+            if i == succs[v].len() {
+                postorder.push(v);
+                continue;
+            }
+            stack.push((v, i + 1));
+            let w = succs[v][i];
+            if !visited[w] {
+                visited[w] = true;
+                stack.push((w, 0));
+            }
+        }
+        let mut rpo = vec![usize::MAX; succs.len()];
+        for (i, v) in postorder.iter().rev().enumerate() {
+            rpo[*v] = i;
+        }
+        let mut forward_preds = vec![0; succs.len()];
+        let mut header = vec![false; succs.len()];
+        for (v, ws) in succs.iter().enumerate() {
+            if rpo[v] == usize::MAX {
+                continue;
+            }
+            for w in ws.iter() {
+                if rpo[*w] > rpo[v] {
+                    forward_preds[*w] += 1;
+                } else {
+                    header[*w] = true;
+                }
+            }
+        }
+        let mut code = RegionCode {
+            out,
+            merge: forward_preds.iter().map(|c| *c > 1).collect(),
+            header,
+            dominators: DominatorTree::new(&succs, local.start),
+            loop_label: vec![usize::MAX; succs.len()],
+            block_label: vec![usize::MAX; succs.len()],
+            end: local.end,
+            end_label: self.label(),
+            rpo,
+        };
+        for v in 0..succs.len() {
+            if code.header[v] {
+                code.loop_label[v] = self.label();
+            }
+            if code.merge[v] {
+                code.block_label[v] = self.label();
+            }
+        }
+        let body = if is_reducible(&succs, local.start) {
+            self.tree(&code, &local.nodes, &local.children, local.start)
+        } else {
+            self.unstructured.push(r);
+            self.gotos(&code, &local.nodes, &local.children, &postorder)
+        };
+        vec![Stmt::Block { label: code.end_label, body }]
+    }
+
+    // the statements of one vertex, given the code of each of its branches
+    fn vertex(&mut self, code: &RegionCode, nodes: &[usize], children: &[usize], v: usize, mut branches: Vec<Vec<Stmt>>) -> Vec<Stmt> {
+        if v >= nodes.len() {
+            // start, or a child region followed by its exit edge
+            let mut stmts = if v < nodes.len() + children.len() { self.region(children[v - nodes.len()]) } else { Vec::new() };
+            stmts.extend(branches.pop().unwrap_or_default());
+            return stmts;
+        }
+        let n = nodes[v];
+        let edge = |i: usize| code.out[v][i].1.unwrap_or(usize::MAX);
+        match branches.len() {
+            0 | 1 => {
+                let mut stmts = vec![Stmt::Node(n)];
+                stmts.extend(branches.pop().unwrap_or_default());
+                stmts
+            }
+            2 => {
+                let else_branch = branches.pop().unwrap();
+                let then_branch = branches.pop().unwrap();
+                vec![Stmt::If { cond: Cond { node: n, taken: edge(0), not_taken: edge(1) }, then_branch, else_branch }]
+            }
+            _ => vec![Stmt::Switch { node: n, arms: branches.into_iter().enumerate().map(|(i, b)| (edge(i), b)).collect() }],
+        }
+    }
+
+    // structured code for the dominator subtree of x, after Ramsey, "Beyond Relooper"
+    // merge nodes dominated by x follow x in nested blocks, innermost first, so that forward edges
+    // into them are breaks out of their block; a loop header wraps its code in a loop
+    fn tree(&mut self, code: &RegionCode, nodes: &[usize], children: &[usize], x: usize) -> Vec<Stmt> {
+        if x == code.end {
+            return vec![Stmt::Break(code.end_label)];
+        }
+        let mut merges = code.dominators.children(x).iter().cloned().filter(|y| code.merge[*y]).collect::<Vec<usize>>();
+        merges.sort_unstable_by_key(|y| std::cmp::Reverse(code.rpo[*y]));
+        let body = self.within(code, nodes, children, x, &merges);
+        if code.header[x] {
+            vec![Stmt::Loop { label: code.loop_label[x], body }]
+        } else {
+            body
+        }
+    }
+
+    fn within(&mut self, code: &RegionCode, nodes: &[usize], children: &[usize], x: usize, merges: &[usize]) -> Vec<Stmt> {
+        match merges.split_first() {
+            None => {
+                let branches = code.out[x].iter()
+                    .map(|(t, _)| self.branch(code, nodes, children, x, *t))
+                    .collect();
+                self.vertex(code, nodes, children, x, branches)
+            }
+            Some((y, rest)) => {
+                let body = self.within(code, nodes, children, x, rest);
+                let mut stmts = vec![Stmt::Block { label: code.block_label[*y], body }];
+                stmts.extend(self.tree(code, nodes, children, *y));
+                stmts
+            }
+        }
+    }
+
+    fn branch(&mut self, code: &RegionCode, nodes: &[usize], children: &[usize], x: usize, target: usize) -> Vec<Stmt> {
+        if code.rpo[target] <= code.rpo[x] {
+            vec![Stmt::Continue(code.loop_label[target])]
+        } else if code.merge[target] {
+            vec![Stmt::Break(code.block_label[target])]
+        } else {
+            self.tree(code, nodes, children, target)
+        }
+    }
+
+    // an irreducible region as a labelled statement per vertex in reverse postorder, joined by gotos
+    fn gotos(&mut self, code: &RegionCode, nodes: &[usize], children: &[usize], postorder: &[usize]) -> Vec<Stmt> {
+        let labels = (0..code.out.len()).map(|_| self.label()).collect::<Vec<usize>>();
+        let mut stmts = Vec::new();
+        for v in postorder.iter().rev() {
+            if *v == code.end {
+                continue;
+            }
+            stmts.push(Stmt::Label(labels[*v]));
+            let branches = code.out[*v].iter()
+                .map(|(t, _)| if *t == code.end { vec![Stmt::Break(code.end_label)] } else { vec![Stmt::Goto(labels[*t])] })
+                .collect();
+            stmts.extend(self.vertex(code, nodes, children, *v, branches));
+        }
+        stmts
+    }
+}
+
+// does any jump in the statements go to the label?
+fn references(stmts: &[Stmt], label: usize) -> bool {
+    stmts.iter().any(|s| match s {
+        Stmt::Break(l) | Stmt::Continue(l) | Stmt::Goto(l) => *l == label,
+        Stmt::If { then_branch, else_branch, .. } => references(then_branch, label) || references(else_branch, label),
+        Stmt::Switch { arms, .. } => arms.iter().any(|(_, b)| references(b, label)),
+        Stmt::Block { body, .. } | Stmt::Loop { body, .. } | Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => references(body, label),
+        Stmt::Node(_) | Stmt::Label(_) => false,
+    })
+}
+
+// does every path through the statements end in a jump, so that they never fall off the end?
+fn always_jumps(stmts: &[Stmt]) -> bool {
+    match stmts.last() {
+        Some(Stmt::Break(_)) | Some(Stmt::Continue(_)) | Some(Stmt::Goto(_)) => true,
+        Some(Stmt::If { then_branch, else_branch, .. }) => always_jumps(then_branch) && always_jumps(else_branch),
+        Some(Stmt::Switch { arms, .. }) => arms.iter().all(|(_, b)| always_jumps(b)),
+        Some(Stmt::Loop { .. }) => true,
+        _ => false,
+    }
+}
+
+// one pass of cleanup over a statement list
+// fall holds the jumps that are the same as falling off the end of the list
+fn simplify_list(stmts: Vec<Stmt>, fall: &[Stmt]) -> Vec<Stmt> {
+    let count = stmts.len();
+    let mut result = Vec::new();
+    for (i, s) in stmts.into_iter().enumerate() {
+        let last_fall = if i + 1 == count { fall.to_vec() } else { Vec::new() };
+        match s {
+            Stmt::If { cond, then_branch, else_branch } => {
+                let then_branch = simplify_list(then_branch, &last_fall);
+                let else_branch = simplify_list(else_branch, &last_fall);
+                // test for the branch that does something
+                if then_branch.is_empty() && !else_branch.is_empty() {
+                    result.push(Stmt::If { cond: cond.negate(), then_branch: else_branch, else_branch: then_branch });
+                } else {
+                    result.push(Stmt::If { cond, then_branch, else_branch });
+                }
+            }
+            Stmt::Switch { node, arms } => result.push(Stmt::Switch {
+                node,
+                arms: arms.into_iter().map(|(e, b)| (e, simplify_list(b, &last_fall))).collect(),
+            }),
+            Stmt::Block { label, body } => {
+                let mut body_fall = last_fall;
+                body_fall.push(Stmt::Break(label));
+                let body = simplify_list(body, &body_fall);
+                if references(&body, label) {
+                    result.push(Stmt::Block { label, body });
+                } else {
+                    result.extend(body);
+                }
+            }
+            Stmt::Loop { label, body } => {
+                let body = simplify_list(body, &[Stmt::Continue(label)]);
+                result.extend(recover_loop(label, body));
+            }
+            Stmt::While { label, cond, body } => {
+                let body = simplify_list(body, &[Stmt::Continue(label)]);
+                result.push(Stmt::While { label, cond, body });
+            }
+            Stmt::DoWhile { label, body, cond } => {
+                let body = simplify_list(body, &[Stmt::Continue(label)]);
+                result.push(Stmt::DoWhile { label, body, cond });
+            }
+            s => result.push(s),
+        }
+    }
+    // a goto straight to the next statement does nothing
+    let mut i = 0;
+    while i + 1 < result.len() {
+        if let (Stmt::Goto(a), Stmt::Label(b)) = (&result[i], &result[i + 1]) {
+            if a == b {
+                result.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if result.last().is_some_and(|s| fall.contains(s)) {
+        result.pop();
+    }
+    result
+}
+
+// turn an endless loop into a while or do-while loop when one branch of a test leaves it for good
+fn recover_loop(label: usize, mut body: Vec<Stmt>) -> Vec<Stmt> {
+    // the exit branch must not come back round and must not fall off the end
+    let leaves = |b: &[Stmt]| always_jumps(b) && !references(b, label);
+    if let [Stmt::If { cond, then_branch, else_branch }] = body.as_slice() {
+        if leaves(else_branch) {
+            let mut stmts = vec![Stmt::While { label, cond: *cond, body: then_branch.clone() }];
+            stmts.extend(else_branch.iter().cloned());
+            return stmts;
+        }
+        if leaves(then_branch) {
+            let mut stmts = vec![Stmt::While { label, cond: cond.negate(), body: else_branch.clone() }];
+            stmts.extend(then_branch.iter().cloned());
+            return stmts;
+        }
+    }
+    if let Some(Stmt::If { cond, then_branch, else_branch }) = body.last() {
+        let (cond, exit) = if then_branch.is_empty() && leaves(else_branch) {
+            (*cond, else_branch.clone())
+        } else if else_branch.is_empty() && leaves(then_branch) {
+            (cond.negate(), then_branch.clone())
+        } else {
+            return vec![Stmt::Loop { label, body }];
+        };
+        body.pop();
+        if !references(&body, label) {
+            let mut stmts = vec![Stmt::DoWhile { label, body, cond }];
+            stmts.extend(exit);
+            return stmts;
+        }
+        body.push(Stmt::If { cond, then_branch: Vec::new(), else_branch: exit });
+    }
+    vec![Stmt::Loop { label, body }]
+}
+
+// drop labels that no goto uses
+fn drop_labels(stmts: Vec<Stmt>, used: &BTreeSet<usize>) -> Vec<Stmt> {
+    stmts.into_iter()
+        .filter(|s| !matches!(s, Stmt::Label(l) if !used.contains(l)))
+        .map(|s| match s {
+            Stmt::If { cond, then_branch, else_branch } => Stmt::If {
+                cond,
+                then_branch: drop_labels(then_branch, used),
+                else_branch: drop_labels(else_branch, used),
+            },
+            Stmt::Switch { node, arms } => Stmt::Switch { node, arms: arms.into_iter().map(|(e, b)| (e, drop_labels(b, used))).collect() },
+            Stmt::Block { label, body } => Stmt::Block { label, body: drop_labels(body, used) },
+            Stmt::Loop { label, body } => Stmt::Loop { label, body: drop_labels(body, used) },
+            Stmt::While { label, cond, body } => Stmt::While { label, cond, body: drop_labels(body, used) },
+            Stmt::DoWhile { label, body, cond } => Stmt::DoWhile { label, body: drop_labels(body, used), cond },
+            s => s,
+        })
+        .collect()
+}
+
+// every label some jump goes to
+fn jump_targets(stmts: &[Stmt], used: &mut BTreeSet<usize>) {
+    for s in stmts.iter() {
+        match s {
+            Stmt::Break(l) | Stmt::Continue(l) | Stmt::Goto(l) => {
+                used.insert(*l);
+            }
+            Stmt::If { then_branch, else_branch, .. } => {
+                jump_targets(then_branch, used);
+                jump_targets(else_branch, used);
+            }
+            Stmt::Switch { arms, .. } => {
+                for (_, b) in arms.iter() {
+                    jump_targets(b, used);
+                }
+            }
+            Stmt::Block { body, .. } | Stmt::Loop { body, .. } | Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => jump_targets(body, used),
+            Stmt::Node(_) | Stmt::Label(_) => {}
+        }
+    }
+}
+
+// structure a flow graph region by region over its structure tree
+// each region is structured on its local graph, with child regions spliced in as single statements,
+// so only regions whose local graph is irreducible fall back to gotos
+pub fn structurize(graph: &FlowGraph, tree: &StructureTree) -> Result<Structured, String> {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let table = RegionTable::new(graph, tree)?;
+    let mut structurizer = Structurizer { digraph: &digraph, table: &table, next_label: 0, unstructured: Vec::new() };
+    let mut body = structurizer.region(0);
+    loop {
+        let simplified = simplify_list(body.clone(), &[]);
+        if simplified == body {
+            break;
+        }
+        body = simplified;
+    }
+    let mut used = BTreeSet::new();
+    jump_targets(&body, &mut used);
+    let body = drop_labels(body, &used);
+    let mut unstructured = structurizer.unstructured;
+    unstructured.sort_unstable();
+    Ok(Structured { body, unstructured })
+}
+
+impl Structured {
+    // the program as indented pseudo code, with nodes by id and conditions as the edge taken
+    pub fn pretty(&self, graph: &FlowGraph) -> String {
+        let mut used = BTreeSet::new();
+        jump_targets(&self.body, &mut used);
+        let mut text = String::new();
+        pretty_list(&self.body, graph, &used, 0, &mut text);
+        text
+    }
+}
+
+fn pretty_list(stmts: &[Stmt], graph: &FlowGraph, used: &BTreeSet<usize>, indent: usize, text: &mut String) {
+    let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+    let cond = |c: &Cond| {
+        let edge = graph[EdgeIndex::new(c.taken)].borrow();
+        format!("{} -> {}", id(c.node), id(edge.to))
+    };
+    let label = |l: &usize| if used.contains(l) { format!("L{}: ", l) } else { String::new() };
+    let pad = "    ".repeat(indent);
+    for s in stmts.iter() {
+        match s {
+            Stmt::Node(n) => text.push_str(&format!("{}{}\n", pad, id(*n))),
+            Stmt::If { cond: c, then_branch, else_branch } => {
+                text.push_str(&format!("{}if {} {{\n", pad, cond(c)));
+                pretty_list(then_branch, graph, used, indent + 1, text);
+                let mut rest = else_branch;
+                // chains of tests in the else branch read as else if
+                while let [Stmt::If { cond: c, then_branch, else_branch }] = rest.as_slice() {
+                    text.push_str(&format!("{}}} else if {} {{\n", pad, cond(c)));
+                    pretty_list(then_branch, graph, used, indent + 1, text);
+                    rest = else_branch;
+                }
+                if !rest.is_empty() {
+                    text.push_str(&format!("{}}} else {{\n", pad));
+                    pretty_list(rest, graph, used, indent + 1, text);
+                }
+                text.push_str(&format!("{}}}\n", pad));
+            }
+            Stmt::Switch { node, arms } => {
+                text.push_str(&format!("{}switch {} {{\n", pad, id(*node)));
+                for (e, b) in arms.iter() {
+                    let edge = graph[EdgeIndex::new(*e)].borrow();
+                    text.push_str(&format!("{}    -> {} {{\n", pad, id(edge.to)));
+                    pretty_list(b, graph, used, indent + 2, text);
+                    text.push_str(&format!("{}    }}\n", pad));
+                }
+                text.push_str(&format!("{}}}\n", pad));
+            }
+            Stmt::Block { label: l, body } => {
+                text.push_str(&format!("{}{}{{\n", pad, label(l)));
+                pretty_list(body, graph, used, indent + 1, text);
+                text.push_str(&format!("{}}}\n", pad));
+            }
+            Stmt::Loop { label: l, body } => {
+                text.push_str(&format!("{}{}loop {{\n", pad, label(l)));
+                pretty_list(body, graph, used, indent + 1, text);
+                text.push_str(&format!("{}}}\n", pad));
+            }
+            Stmt::While { label: l, cond: c, body } => {
+                text.push_str(&format!("{}{}while {} {{\n", pad, label(l), cond(c)));
+                pretty_list(body, graph, used, indent + 1, text);
+                text.push_str(&format!("{}}}\n", pad));
+            }
+            Stmt::DoWhile { label: l, body, cond: c } => {
+                text.push_str(&format!("{}{}do {{\n", pad, label(l)));
+                pretty_list(body, graph, used, indent + 1, text);
+                text.push_str(&format!("{}}} while {}\n", pad, cond(c)));
+            }
+            Stmt::Break(l) => text.push_str(&format!("{}break L{}\n", pad, l)),
+            Stmt::Continue(l) => text.push_str(&format!("{}continue L{}\n", pad, l)),
+            Stmt::Label(l) => text.push_str(&format!("{}L{}:\n", pad, l)),
+            Stmt::Goto(l) => text.push_str(&format!("{}goto L{}\n", pad, l)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::seeded_graphs;
+
+    enum Flow {
+        Normal,
+        Break(usize),
+        Continue(usize),
+        Goto(usize),
+        Stop,
+    }
+
+    // runs the graph or the program, taking branches by a fixed function of node and visit count
+    struct Run<'a> {
+        digraph: &'a DiGraph,
+        visits: Vec<usize>,
+        trace: Vec<usize>,
+        limit: usize,
+    }
+
+    impl<'a> Run<'a> {
+        fn new(digraph: &'a DiGraph) -> Run<'a> {
+            Run { digraph, visits: vec![0; digraph.node_count()], trace: Vec::new(), limit: 200 }
+        }
+
+        // run a node, returning the edge it leaves by, None when it leaves the graph
+        fn visit(&mut self, n: usize) -> Option<Option<usize>> {
+            if self.trace.len() == self.limit {
+                return None;
+            }
+            self.trace.push(n);
+            self.visits[n] += 1;
+            let outs = &self.digraph.out_edges[n];
+            if outs.is_empty() {
+                return Some(None);
+            }
+            let k = (n * 7919 + self.visits[n] * 104729) % 11;
+            Some(Some(outs[k % outs.len()]))
+        }
+
+        fn graph(&mut self) {
+            let mut n = self.digraph.entry;
+            while let Some(Some(e)) = self.visit(n) {
+                if self.digraph.is_return_edge(e) {
+                    break;
+                }
+                n = self.digraph.endpoints(e).unwrap().1;
+            }
+        }
+
+        fn test(&mut self, c: &Cond) -> Option<bool> {
+            let e = self.visit(c.node)?.unwrap();
+            assert!(e == c.taken || e == c.not_taken);
+            Some(e == c.taken)
+        }
+
+        fn list(&mut self, stmts: &[Stmt]) -> Flow {
+            let mut i = 0;
+            while i < stmts.len() {
+                match self.stmt(&stmts[i]) {
+                    Flow::Normal => i += 1,
+                    Flow::Goto(l) => match stmts.iter().position(|s| *s == Stmt::Label(l)) {
+                        Some(j) => i = j,
+                        None => return Flow::Goto(l),
+                    },
+                    f => return f,
+                }
+            }
+            Flow::Normal
+        }
+
+        fn stmt(&mut self, s: &Stmt) -> Flow {
+            match s {
+                Stmt::Node(n) => match self.visit(*n) {
+                    None => Flow::Stop,
+                    Some(_) => Flow::Normal,
+                },
+                Stmt::If { cond, then_branch, else_branch } => match self.test(cond) {
+                    None => Flow::Stop,
+                    Some(true) => self.list(then_branch),
+                    Some(false) => self.list(else_branch),
+                },
+                Stmt::Switch { node, arms } => match self.visit(*node) {
+                    None => Flow::Stop,
+                    Some(e) => {
+                        let e = e.unwrap();
+                        self.list(&arms.iter().find(|(x, _)| *x == e).unwrap().1)
+                    }
+                },
+                Stmt::Block { label, body } => match self.list(body) {
+                    Flow::Break(l) if l == *label => Flow::Normal,
+                    f => f,
+                },
+                Stmt::Loop { label, body } => loop {
+                    match self.list(body) {
+                        Flow::Normal => {}
+                        Flow::Continue(l) if l == *label => {}
+                        f => return f,
+                    }
+                },
+                Stmt::While { label, cond, body } => loop {
+                    match self.test(cond) {
+                        None => return Flow::Stop,
+                        Some(false) => return Flow::Normal,
+                        Some(true) => {}
+                    }
+                    match self.list(body) {
+                        Flow::Normal => {}
+                        Flow::Continue(l) if l == *label => {}
+                        f => return f,
+                    }
+                },
+                Stmt::DoWhile { label, body, cond } => loop {
+                    match self.list(body) {
+                        Flow::Normal => {}
+                        Flow::Continue(l) if l == *label => {}
+                        f => return f,
+                    }
+                    match self.test(cond) {
+                        None => return Flow::Stop,
+                        Some(false) => return Flow::Normal,
+                        Some(true) => {}
+                    }
+                },
+                Stmt::Break(l) => Flow::Break(*l),
+                Stmt::Continue(l) => Flow::Continue(*l),
+                Stmt::Goto(l) => Flow::Goto(*l),
+                Stmt::Label(_) => Flow::Normal,
+            }
+        }
+    }
+
+    // the program visits the same nodes in the same order as the graph
+    fn check_runs(graph: &FlowGraph, structured: &Structured) {
+        let digraph = DiGraph::from_flow_graph(graph);
+        let mut expected = Run::new(&digraph);
+        expected.graph();
+        let mut run = Run::new(&digraph);
+        let flow = run.list(&structured.body);
+        assert!(matches!(flow, Flow::Normal | Flow::Stop), "{}", structured.pretty(graph));
+        assert_eq!(run.trace, expected.trace, "{}", structured.pretty(graph));
+    }
+
+    #[test]
+    fn fig1() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let structured = structurize(&graph, &tree).unwrap();
+        check_runs(&graph, &structured);
+        assert!(structured.unstructured.is_empty());
+        assert_eq!(structured.pretty(&graph), "\
+if 0 -> 1 {
+    if 1 -> 3 {
+        3
+        if 5 -> 7 {
+            7
+        } else {
+            9
+        }
+        11
+    }
+    13
+} else {
+    2
+    L12: {
+        if 4 -> 6 {
+            6
+        } else if 8 -> 12 {
+            break L12
+        }
+        10
+    }
+    12
+    14
+}
+15
+");
+    }
+
+    #[test]
+    fn example_c() {
+        let mut graph = make_example_c();
+        let tree = build_structure_tree(&mut graph);
+        let structured = structurize(&graph, &tree).unwrap();
+        check_runs(&graph, &structured);
+        // the crossing edges leave no nesting of ifs, so merges are reached by breaking out of blocks
+        assert!(structured.unstructured.is_empty());
+        assert_eq!(structured.pretty(&graph), "\
+10
+L4: {
+    L6: {
+        if 0 -> 1 {
+            L3: {
+                if 1 -> 2 {
+                    L5: {
+                        if 2 -> 3 {
+                            if 3 -> 4 {
+                                if 4 -> 7 {
+                                    break L5
+                                }
+                            }
+                            5
+                            break L3
+                        }
+                    }
+                    7
+                    break L6
+                }
+            }
+            6
+            break L4
+        }
+    }
+    8
+}
+9
+");
+    }
+
+    #[test]
+    fn random_graphs() {
+        for mut graph in seeded_graphs(3, 14, 6).take(300) {
+            let tree = build_structure_tree(&mut graph);
+            let structured = structurize(&graph, &tree).unwrap();
+            check_runs(&graph, &structured);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use petgraph::graph::EdgeIndex;
+
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+use crate::regions::RegionTable;
+
+// a superbubble in the sense of Onodera, Sadakane and Shibuya, "Detecting superbubbles in assembly
+// graphs": a pair (s, t) such that t is reachable from s, the nodes reachable from s without passing t
+// are the nodes reaching t without passing s, the subgraph they induce is acyclic, and no node between
+// them forms such a pair with s
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Superbubble {
+    pub entrance: usize, // node index of s
+    pub exit: usize, // node index of t
+    span: (usize, usize), // positions of s and t in the order
+}
+
+// how a superbubble lines up with the regions of a structure tree
+#[derive(Clone,Debug,PartialEq)]
+pub enum RegionRelation {
+    Canonical(usize), // the nodes of a canonical SESE region, by table index
+    Composite(Vec<usize>), // the nodes of a run of sibling canonical regions of one class, a SESE region that is not canonical
+    Enclosed(usize), // not a SESE region at all; the smallest region holding its nodes
+}
+
+impl RegionRelation {
+    pub fn is_canonical(&self) -> bool {
+        matches!(self, RegionRelation::Canonical(_))
+    }
+}
+
+// all superbubbles of a directed graph, following Gärtner, Müller and Stadler, "Superbubbles revisited":
+// in a suitable depth first order the nodes of a superbubble are an interval closed to edges from outside,
+// so one sweep with a stack of candidate entrances finds every one of them in linear time
+// cycles are handled as in Sung et al. by cutting the back edges of the search, which never lie inside a
+// superbubble, and treating them as leaving and entering the graph; searches start only in strongly
+// connected components without edges from outside, so a superbubble is missed only when it holds the start
+// of such a component other than as its entrance, which in a flow graph needs the return edge
+#[derive(Clone,Debug)]
+pub struct Superbubbles {
+    pub bubbles: Vec<Superbubble>, // by position of the exit in the order
+    order: Vec<usize>, // node indexes in reverse postorder of the search
+}
+
+// postorder of a depth first search over successor lists, starting from each root in turn
+fn postorder(succs: &[Vec<usize>], roots: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut visited = vec![false; succs.len()];
+    let mut post = Vec::with_capacity(succs.len());
+    for root in roots {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((n, i)) = stack.last_mut() {
+            if *i == succs[*n].len() {
+                post.push(*n);
+                stack.pop();
+                continue;
+            }
+            let next = succs[*n][*i];
+            *i += 1;
+            if !visited[next] {
+                visited[next] = true;
+                stack.push((next, 0));
+            }
+        }
+    }
+    post
+}
+
+impl Superbubbles {
+    // the superbubbles of a directed graph, the return edge being treated as leaving the graph
+    pub fn new(digraph: &DiGraph) -> Superbubbles {
+        let n = digraph.node_count();
+        let succs = digraph.successor_lists();
+        let preds = digraph.predecessor_lists();
+        // a first search finishes last in a component without edges from outside, and taking roots in
+        // reverse finishing order keeps doing so for the nodes still unvisited
+        let first = postorder(&succs, std::iter::once(digraph.entry).chain(0..n));
+        let order = postorder(&succs, first.into_iter().rev()).into_iter().rev().collect::<Vec<usize>>();
+        let mut position = vec![0; n];
+        for (i, v) in order.iter().enumerate() {
+            position[*v] = i;
+        }
+
+        // furthest child and nearest parent of each node by position, past the ends when a node has
+        // none or has a back edge, as a back edge stands for an edge to or from outside
+        let out_child = order.iter()
+            .map(|v| {
+                let p = position[*v];
+                if succs[*v].is_empty() || succs[*v].iter().any(|c| position[*c] <= p) {
+                    n
+                } else {
+                    succs[*v].iter().map(|c| position[*c]).max().unwrap()
+                }
+            })
+            .collect::<Vec<usize>>();
+        let out_parent = order.iter()
+            .map(|v| {
+                let p = position[*v];
+                if preds[*v].is_empty() || preds[*v].iter().any(|q| position[*q] >= p) {
+                    None
+                } else {
+                    preds[*v].iter().map(|q| position[*q]).min()
+                }
+            })
+            .collect::<Vec<Option<usize>>>();
+
+        // [s, t] is a superbubble when no node after s up to t has a parent before s and no node from s
+        // up to before t has a child after t; for each t only the latest such s gives a minimal one
+        // the stack holds the entrances still closed to parents from before them, latest on top, each with
+        // the furthest child of the nodes from it up to the next one on the stack
+        let mut bubbles = Vec::new();
+        let mut stack: Vec<(usize, usize)> = Vec::new();
+        for t in 0..n {
+            while let Some((s, far)) = stack.last().cloned() {
+                if out_parent[t].is_some_and(|q| q >= s) {
+                    break;
+                }
+                stack.pop();
+                if let Some(below) = stack.last_mut() {
+                    below.1 = below.1.max(far);
+                }
+            }
+            if let Some((s, far)) = stack.last().cloned() {
+                // an edge from t back to s would close a cycle inside
+                if far <= t && !succs[order[t]].contains(&order[s]) {
+                    bubbles.push(Superbubble { entrance: order[s], exit: order[t], span: (s, t) });
+                }
+            }
+            stack.push((t, out_child[t]));
+        }
+        Superbubbles { bubbles, order }
+    }
+
+    pub fn from_flow_graph(graph: &FlowGraph) -> Superbubbles {
+        Superbubbles::new(&DiGraph::from_flow_graph(graph))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bubbles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bubbles.is_empty()
+    }
+
+    // the nodes of a superbubble, its entrance and exit included, sorted
+    pub fn nodes(&self, b: usize) -> Vec<usize> {
+        let (s, t) = self.bubbles[b].span;
+        let mut nodes = self.order[s..=t].to_vec();
+        nodes.sort();
+        nodes
+    }
+
+    // where a superbubble sits in the structure tree; the graph must be the one the table was built from
+    // it is a SESE region when its entrance has a single edge in and its exit a single edge out, these two
+    // being cycle equivalent, and canonical when they bound a region of the tree
+    pub fn relation(&self, b: usize, graph: &FlowGraph, digraph: &DiGraph, table: &RegionTable) -> RegionRelation {
+        let bubble = self.bubbles[b];
+        let mut in_edges = digraph.in_edges[bubble.entrance].clone();
+        if bubble.entrance == digraph.entry {
+            in_edges.extend(digraph.return_edge);
+        }
+        let out_edges = &digraph.out_edges[bubble.exit];
+        if let ([entry], [exit]) = (in_edges.as_slice(), out_edges.as_slice()) {
+            let class = |e: usize| graph[EdgeIndex::new(e)].borrow().class;
+            if let Some(r) = table.regions.iter().position(|r| r.entry == Some(*entry)) {
+                if table.regions[r].exit == Some(*exit) {
+                    return RegionRelation::Canonical(r);
+                }
+                if class(*entry) == class(*exit) {
+                    // follow the siblings of the chain from the entry edge to the exit edge
+                    let mut run = vec![r];
+                    while table.regions[*run.last().unwrap()].exit != Some(*exit) {
+                        let last = *run.last().unwrap();
+                        let next = table.regions.iter().position(|s| s.parent == table.regions[last].parent && s.entry == table.regions[last].exit);
+                        match next {
+                            Some(next) if !run.contains(&next) => run.push(next),
+                            _ => break,
+                        }
+                    }
+                    if table.regions[*run.last().unwrap()].exit == Some(*exit) {
+                        return RegionRelation::Composite(run);
+                    }
+                }
+            }
+        }
+        // the deepest region holding every node
+        let nodes = self.nodes(b);
+        let mut region = table.node_region[nodes[0]];
+        for v in nodes[1..].iter() {
+            while !table.contains(region, *v) {
+                region = table.regions[region].parent.unwrap();
+            }
+        }
+        RegionRelation::Enclosed(region)
+    }
+
+    // the superbubbles that are not canonical SESE regions of the tree, with their relation
+    pub fn not_canonical(&self, graph: &FlowGraph, table: &RegionTable) -> Vec<(usize, RegionRelation)> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        (0..self.bubbles.len())
+            .map(|b| (b, self.relation(b, graph, &digraph, table)))
+            .filter(|(_, r)| !r.is_canonical())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::{seeded_graphs, Rng};
+
+    // nodes reachable from a without expanding b, a and b included when reached
+    fn reach(succs: &[Vec<usize>], a: usize, b: usize) -> Vec<bool> {
+        let mut seen = vec![false; succs.len()];
+        seen[a] = true;
+        let mut stack = vec![a];
+        while let Some(v) = stack.pop() {
+            if v == b && v != a {
+                continue;
+            }
+            for w in succs[v].iter() {
+                if !seen[*w] {
+                    seen[*w] = true;
+                    stack.push(*w);
+                }
+            }
+        }
+        seen
+    }
+
+    fn acyclic(succs: &[Vec<usize>], nodes: &[bool]) -> bool {
+        let mut indegree = vec![0; succs.len()];
+        for v in 0..succs.len() {
+            for w in succs[v].iter() {
+                if nodes[v] && nodes[*w] {
+                    indegree[*w] += 1;
+                }
+            }
+        }
+        let mut ready = (0..succs.len()).filter(|v| nodes[*v] && indegree[*v] == 0).collect::<Vec<usize>>();
+        let mut done = 0;
+        while let Some(v) = ready.pop() {
+            done += 1;
+            for w in succs[v].iter() {
+                if nodes[*w] {
+                    indegree[*w] -= 1;
+                    if indegree[*w] == 0 {
+                        ready.push(*w);
+                    }
+                }
+            }
+        }
+        done == nodes.iter().filter(|n| **n).count()
+    }
+
+    // the definition checked directly, quadratic in the number of pairs
+    fn reference(digraph: &DiGraph) -> Vec<(usize, usize)> {
+        let succs = digraph.successor_lists();
+        let preds = digraph.predecessor_lists();
+        let n = digraph.node_count();
+        let candidate = |s: usize, t: usize| {
+            let forward = reach(&succs, s, t);
+            forward[t] && forward == reach(&preds, t, s) && acyclic(&succs, &forward)
+        };
+        let mut bubbles = Vec::new();
+        for s in 0..n {
+            for t in 0..n {
+                if s == t || !candidate(s, t) {
+                    continue;
+                }
+                let inside = reach(&succs, s, t);
+                if !(0..n).any(|u| u != s && u != t && inside[u] && candidate(s, u)) {
+                    bubbles.push((s, t));
+                }
+            }
+        }
+        bubbles.sort();
+        bubbles
+    }
+
+    fn pairs(bubbles: &Superbubbles) -> Vec<(usize, usize)> {
+        let mut pairs = bubbles.bubbles.iter().map(|b| (b.entrance, b.exit)).collect::<Vec<(usize, usize)>>();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn cycles() {
+        // 0 -> 1 -> {2, 3} -> 4 -> 5 with a loop from 4 back to 1 through 10, and a bubble 5 -> {6, 7} -> 8
+        // inside the cycle 8 -> 11 -> 5
+        let edges = [(0, 1), (1, 2), (1, 3), (2, 4), (3, 4), (4, 10), (10, 1), (4, 5), (5, 6), (5, 7), (6, 8), (7, 8), (8, 11), (11, 5), (8, 9)];
+        let digraph = DiGraph::new(0, 9, None, edges.iter().map(|e| Some(*e)).collect());
+        let bubbles = Superbubbles::new(&digraph);
+        assert_eq!(pairs(&bubbles), reference(&digraph));
+        assert!(pairs(&bubbles).contains(&(1, 4)));
+        assert!(pairs(&bubbles).contains(&(5, 8)));
+        // with the edge back from its exit straight to its entrance the bubble holds a cycle
+        let edges = [(0, 1), (1, 2), (1, 3), (2, 4), (3, 4), (4, 1), (4, 5)];
+        let digraph = DiGraph::new(0, 5, None, edges.iter().map(|e| Some(*e)).collect());
+        assert!(!pairs(&Superbubbles::new(&digraph)).contains(&(1, 4)));
+    }
+
+    #[test]
+    fn random_digraphs() {
+        let mut rng = Rng::new(43);
+        let mut found = 0;
+        for _ in 0..300 {
+            // every node reachable from node 0, which has no edge into it
+            let n = 2 + rng.below(9);
+            let mut edges = Vec::new();
+            for v in 1..n {
+                edges.push(Some((rng.below(v), v)));
+            }
+            for _ in 0..rng.below(2 * n) {
+                let (a, b) = (rng.below(n), 1 + rng.below(n - 1));
+                edges.push(Some((a, b)));
+            }
+            let digraph = DiGraph::new(0, n - 1, None, edges);
+            let bubbles = Superbubbles::new(&digraph);
+            assert_eq!(pairs(&bubbles), reference(&digraph), "{:?}", digraph.edges);
+            for b in 0..bubbles.len() {
+                let nodes = bubbles.nodes(b);
+                let inside = reach(&digraph.successor_lists(), bubbles.bubbles[b].entrance, bubbles.bubbles[b].exit);
+                assert_eq!(nodes, (0..n).filter(|v| inside[*v]).collect::<Vec<usize>>());
+            }
+            found += bubbles.len();
+        }
+        assert!(found > 0);
+    }
+
+    fn table_of(graph: &mut FlowGraph) -> RegionTable {
+        let tree = build_structure_tree(graph);
+        RegionTable::new(graph, &tree).unwrap()
+    }
+
+    #[test]
+    fn fig1() {
+        let mut graph = make_example_fig1();
+        let table = table_of(&mut graph);
+        let digraph = DiGraph::from_flow_graph(&graph);
+        let bubbles = Superbubbles::from_flow_graph(&graph);
+        assert_eq!(pairs(&bubbles), reference(&digraph));
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let ids = bubbles.bubbles.iter().map(|b| (id(b.entrance), id(b.exit))).collect::<Vec<(usize, usize)>>();
+        assert_eq!(ids, vec![(2, 4), (4, 12), (12, 14), (3, 5), (5, 11), (1, 13), (0, 15)]);
+        // single edges between nodes that branch and the whole graph are superbubbles but not regions
+        let flagged = bubbles.not_canonical(&graph, &table).into_iter().map(|(b, _)| ids[b]).collect::<Vec<(usize, usize)>>();
+        assert_eq!(flagged, vec![(2, 4), (12, 14), (3, 5), (0, 15)]);
+        for b in 0..bubbles.len() {
+            if let RegionRelation::Canonical(r) = bubbles.relation(b, &graph, &digraph, &table) {
+                assert_eq!(table.all_nodes(r), bubbles.nodes(b));
+            }
+        }
+    }
+
+    #[test]
+    fn random_graphs() {
+        for mut graph in seeded_graphs(44, 12, 6).take(100) {
+            let table = table_of(&mut graph);
+            let digraph = DiGraph::from_flow_graph(&graph);
+            let bubbles = Superbubbles::from_flow_graph(&graph);
+            for b in 0..bubbles.len() {
+                let nodes = bubbles.nodes(b);
+                match bubbles.relation(b, &graph, &digraph, &table) {
+                    RegionRelation::Canonical(r) => assert_eq!(table.all_nodes(r), nodes),
+                    RegionRelation::Composite(run) => {
+                        let mut all = run.iter().flat_map(|r| table.all_nodes(*r)).collect::<Vec<usize>>();
+                        all.sort();
+                        all.dedup();
+                        assert_eq!(all, nodes);
+                    }
+                    RegionRelation::Enclosed(r) => assert!(nodes.iter().all(|v| table.contains(r, *v))),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// fixtures shared by the unit tests of every module
+
+use crate::*;
+
+pub use crate::verify::{random_two_edge_connected, reachable_without, Rng};
+
+// the named example graphs
+pub fn examples() -> Vec<FlowGraph> {
+    vec![
+        make_example_0(),
+        make_example_a(),
+        make_example_fig1(),
+        make_example_fig1_a(),
+        make_example_diamond(),
+        make_example_c(),
+        make_nonplanar_1(),
+        make_example_nested_cycle(),
+    ]
+}
+
+// an endless, reproducible stream of random 2-edge-connected graphs
+pub fn seeded_graphs(seed: u64, max_nodes: usize, ears: usize) -> impl Iterator<Item = FlowGraph> {
+    let mut rng = Rng::new(seed);
+    std::iter::repeat_with(move || random_two_edge_connected(&mut rng, max_nodes, ears))
+}
+
+// the structure tree at the given granularity
+pub fn tree_with(graph: &mut FlowGraph, granularity: Granularity) -> StructureTree {
+    build_structure_tree_with(graph, &StructureTreeOptions { granularity, ..Default::default() })
+}
@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use petgraph::graph::NodeIndex;
+
+use crate::{FlowGraph, SeSeRegion, StructureTree};
+use crate::digraph::DiGraph;
+use crate::regions::RegionTable;
+
+// a step of a walk through a region: a node, or a whole child region taken as one step
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum Step {
+    Node(usize), // node id
+    Region(usize), // region id in the structure tree
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Node(id) => write!(f, "{}", id),
+            Step::Region(id) => write!(f, "r{}", id),
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct TraversalOptions {
+    pub max_traversals: usize, // stop once this many walks are found
+    pub collapse_children: bool, // take each child region as a single step
+    pub max_visits: usize, // times a walk may pass the same step, 1 for walks without cycles
+}
+
+impl Default for TraversalOptions {
+    fn default() -> TraversalOptions {
+        TraversalOptions { max_traversals: 1000, collapse_children: true, max_visits: 1 }
+    }
+}
+
+// the alleles of a region: the distinct walks from its entry edge to its exit edge that stay inside it
+#[derive(Clone,Debug)]
+pub struct RegionTraversals {
+    pub region: usize, // index in the region table
+    pub walks: Vec<Vec<Step>>, // in depth first order, following edges by edge index
+    pub truncated: bool, // the cap was reached, so there may be more walks
+    pub cyclic: bool, // some walk was cut short because it would pass a step more than max_visits times
+}
+
+impl RegionTraversals {
+    // each walk written as its steps joined by commas
+    pub fn to_strings(&self) -> Vec<String> {
+        self.walks.iter().map(|w| w.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(",")).collect()
+    }
+}
+
+// the vertices a walk can take, with start and end standing for the entry and exit edges
+struct WalkGraph {
+    steps: Vec<Step>, // step of each vertex other than start and end
+    succs: Vec<Vec<usize>>, // without repeats, so that distinct vertex walks are distinct step walks
+    start: usize,
+    end: usize,
+}
+
+impl WalkGraph {
+    // the local graph of the region, child regions being single vertices
+    fn collapsed(graph: &FlowGraph, digraph: &DiGraph, table: &RegionTable, r: usize) -> WalkGraph {
+        let local = table.local_graph(digraph, r);
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        let mut steps = local.nodes.iter().map(|n| Step::Node(id(*n))).collect::<Vec<Step>>();
+        steps.extend(local.children.iter().map(|c| Step::Region(table.regions[*c].id)));
+        WalkGraph { steps, succs: local.successors(false), start: local.start, end: local.end }
+    }
+
+    // every node of the region and its descendants
+    fn expanded(graph: &FlowGraph, digraph: &DiGraph, table: &RegionTable, r: usize) -> WalkGraph {
+        let region = &table.regions[r];
+        let nodes = table.all_nodes(r);
+        let mut vertex = vec![usize::MAX; digraph.node_count()];
+        for (i, n) in nodes.iter().enumerate() {
+            vertex[*n] = i;
+        }
+        let (start, end) = (nodes.len(), nodes.len() + 1);
+        let mut succs = vec![Vec::new(); nodes.len() + 2];
+        if region.parent.is_none() {
+            succs[start].push(vertex[digraph.entry]);
+            if digraph.return_edge.is_none() {
+                succs[vertex[digraph.exit]].push(end);
+            }
+        }
+        for (e, endpoints) in digraph.edges.iter().enumerate() {
+            let (a, b) = match endpoints {
+                Some(ab) => *ab,
+                None => continue,
+            };
+            if region.entry == Some(e) {
+                succs[start].push(vertex[b]);
+            } else if region.exit == Some(e) || (region.parent.is_none() && digraph.is_return_edge(e)) {
+                succs[vertex[a]].push(end);
+            } else if vertex[a] != usize::MAX && vertex[b] != usize::MAX {
+                succs[vertex[a]].push(vertex[b]);
+            }
+        }
+        let id = |n: usize| graph[NodeIndex::new(n)].borrow().id;
+        WalkGraph { steps: nodes.iter().map(|n| Step::Node(id(*n))).collect(), succs, start, end }
+    }
+
+    fn walks(mut self, options: &TraversalOptions, region: usize) -> RegionTraversals {
+        for s in self.succs.iter_mut() {
+            let mut seen = HashSet::new();
+            s.retain(|w| seen.insert(*w));
+        }
+        let mut result = RegionTraversals { region, walks: Vec::new(), truncated: false, cyclic: false };
+        let mut visits = vec![0; self.succs.len()];
+        visits[self.start] = 1;
+        // the walk so far as vertices, each with the position of its next successor to try
+        let mut stack = vec![(self.start, 0)];
+        while let Some((v, i)) = stack.last_mut() {
+            let v = *v;
+            if v == self.end {
+                if result.walks.len() == options.max_traversals {
+                    result.truncated = true;
+                    break;
+                }
+                result.walks.push(stack[1..stack.len() - 1].iter().map(|(w, _)| self.steps[*w]).collect());
+                stack.pop();
+                continue;
+            }
+            if *i == self.succs[v].len() {
+                visits[v] -= 1;
+                stack.pop();
+                continue;
+            }
+            let w = self.succs[v][*i];
+            *i += 1;
+            if w != self.end && visits[w] == options.max_visits {
+                result.cyclic = true;
+                continue;
+            }
+            if w != self.end {
+                visits[w] += 1;
+            }
+            stack.push((w, 0));
+        }
+        result
+    }
+}
+
+// the walks through one region of the table; the graph must be the one the table was built from
+pub fn region_traversals(graph: &FlowGraph, table: &RegionTable, r: usize, options: &TraversalOptions) -> RegionTraversals {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let walk_graph = if options.collapse_children {
+        WalkGraph::collapsed(graph, &digraph, table, r)
+    } else {
+        WalkGraph::expanded(graph, &digraph, table, r)
+    };
+    walk_graph.walks(options, r)
+}
+
+// the walks through every region, by table index
+pub fn all_traversals(graph: &FlowGraph, tree: &StructureTree, options: &TraversalOptions) -> Result<Vec<RegionTraversals>, String> {
+    let table = RegionTable::new(graph, tree)?;
+    Ok((0..table.len()).map(|r| region_traversals(graph, &table, r, options)).collect())
+}
+
+// the walks through a region of the tree
+pub fn traversals_of(graph: &FlowGraph, tree: &StructureTree, region: &SeSeRegion, options: &TraversalOptions) -> Result<RegionTraversals, String> {
+    let table = RegionTable::new(graph, tree)?;
+    let r = table.regions.iter().position(|r| r.id == region.id).ok_or(format!("region {} is not in the tree", region.id))?;
+    Ok(region_traversals(graph, &table, r, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    // source to sink paths of an acyclic graph, counted over the directed view
+    fn path_count(digraph: &DiGraph, from: usize, to: usize) -> usize {
+        if from == to {
+            return 1;
+        }
+        digraph.successors(from).iter().map(|s| path_count(digraph, *s, to)).sum()
+    }
+
+    #[test]
+    fn fig1() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let digraph = DiGraph::from_flow_graph(&graph);
+        let expanded = TraversalOptions { collapse_children: false, ..Default::default() };
+        let root = region_traversals(&graph, &table, 0, &expanded);
+        assert_eq!(root.walks.len(), path_count(&digraph, digraph.entry, digraph.exit));
+        assert!(!root.truncated && !root.cyclic);
+        for walk in root.walks.iter() {
+            assert_eq!((walk[0], *walk.last().unwrap()), (Step::Node(0), Step::Node(15)));
+        }
+        let mut distinct = root.to_strings();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), root.walks.len());
+
+        // collapsing children never gives more walks, and every child step is a region of the tree
+        for r in 0..table.len() {
+            let collapsed = region_traversals(&graph, &table, r, &TraversalOptions::default());
+            let all = region_traversals(&graph, &table, r, &expanded);
+            assert!(!collapsed.walks.is_empty() && collapsed.walks.len() <= all.walks.len());
+            for step in collapsed.walks.iter().flatten() {
+                if let Step::Region(id) = step {
+                    assert!(table.regions[r].children.iter().any(|c| table.regions[*c].id == *id));
+                }
+            }
+        }
+        // the two branches out of node 5
+        let branches = table.regions.iter().position(|r| r.nodes.iter().any(|n| graph[NodeIndex::new(*n)].borrow().id == 7)).unwrap();
+        let parent = table.regions[branches].parent.unwrap();
+        let walks = region_traversals(&graph, &table, parent, &expanded).to_strings();
+        assert!(walks.contains(&"5,7,11".to_string()) && walks.contains(&"5,9,11".to_string()), "{:?}", walks);
+
+        let capped = region_traversals(&graph, &table, 0, &TraversalOptions { max_traversals: 2, ..expanded });
+        assert!(capped.truncated);
+        assert_eq!(capped.walks, root.walks[..2].to_vec());
+        let region = tree.regions().into_iter().find(|s| s.borrow().id == table.regions[parent].id).unwrap();
+        assert_eq!(traversals_of(&graph, &tree, &region.borrow(), &expanded).unwrap().walks.len(), 2);
+    }
+
+    #[test]
+    fn cycles() {
+        // 0 -> 1 -> 2 -> 3 with a loop 2 -> 1 and the return edge 3 -> 0
+        let mut graph = FlowGraph::new_undirected();
+        let nodes = (0..4).map(|i| add_graph_node(&mut graph, i)).collect::<Vec<NodeIndex>>();
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (2, 1), (3, 0)] {
+            add_graph_edge(&mut graph, nodes[a], nodes[b]);
+        }
+        let tree = build_structure_tree(&mut graph);
+        let table = RegionTable::new(&graph, &tree).unwrap();
+        let once = TraversalOptions { collapse_children: false, ..Default::default() };
+        let simple = region_traversals(&graph, &table, 0, &once);
+        assert_eq!(simple.to_strings(), vec!["0,1,2,3"]);
+        assert!(simple.cyclic);
+        let twice = region_traversals(&graph, &table, 0, &TraversalOptions { max_visits: 2, ..once });
+        assert_eq!(twice.to_strings(), vec!["0,1,2,3", "0,1,2,1,2,3"]);
+    }
+}
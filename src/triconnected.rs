@@ -0,0 +1,960 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::FlowGraph;
+use crate::digraph::DiGraph;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ComponentKind {
+    Bond, // two nodes joined by three or more edges
+    Polygon, // a simple cycle
+    Rigid, // a triconnected simple graph
+}
+
+// an edge of the decomposition: a real edge of the graph or a virtual edge standing for the rest of the
+// graph on the far side of a separation pair; each virtual edge is in exactly two components
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct SplitEdge {
+    pub ends: (usize, usize), // node indexes
+    pub real: Option<usize>, // edge index in the graph, None for a virtual edge
+}
+
+#[derive(Clone,Debug)]
+pub struct Component {
+    pub kind: ComponentKind,
+    pub edges: Vec<usize>, // split edge indexes
+}
+
+// triconnected components of a biconnected multigraph, after Hopcroft and Tarjan, "Dividing a graph into
+// triconnected components": split at separation pairs until every split component is a triangle, a triple
+// bond or triconnected, then merge bonds sharing a virtual edge and polygons sharing a virtual edge
+// new finds the separation pairs in linear time with the path search, by_separation_pairs tries every
+// pair of nodes and is kept as a slow reference
+#[derive(Clone,Debug)]
+pub struct Triconnected {
+    pub edges: Vec<SplitEdge>,
+    pub components: Vec<Component>,
+}
+
+fn key(a: usize, b: usize) -> (usize, usize) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+// is the multigraph connected and still connected after removing any one node?
+// edges are by edge index and None edges are ignored
+// by brute force, removing each node in turn, so it can check Blocks and the decompositions
+pub fn is_biconnected(edges: &[Option<(usize, usize)>]) -> bool {
+    let nodes = edges.iter().flatten().flat_map(|(a, b)| vec![*a, *b]).collect::<BTreeSet<usize>>();
+    let connected_without = |removed: Option<usize>| {
+        let mut adjacency: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (a, b) in edges.iter().flatten() {
+            if Some(*a) != removed && Some(*b) != removed {
+                adjacency.entry(*a).or_default().push(*b);
+                adjacency.entry(*b).or_default().push(*a);
+            }
+        }
+        let remaining = nodes.iter().filter(|n| Some(**n) != removed).cloned().collect::<Vec<usize>>();
+        let start = match remaining.first() {
+            Some(s) => *s,
+            None => return true,
+        };
+        let mut seen = BTreeSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(v) = stack.pop() {
+            for w in adjacency.get(&v).into_iter().flatten() {
+                if seen.insert(*w) {
+                    stack.push(*w);
+                }
+            }
+        }
+        seen.len() == remaining.len()
+    };
+    nodes.len() >= 2 && connected_without(None) && (nodes.len() == 2 || nodes.iter().all(|n| connected_without(Some(*n))))
+}
+
+impl Triconnected {
+    // decompose a multigraph given as endpoints by edge index, None edges being ignored, in linear time
+    // None when the graph is not biconnected
+    pub fn new(edges: &[Option<(usize, usize)>]) -> Option<Triconnected> {
+        PathSearch::decompose(edges)
+    }
+
+    // decompose a biconnected multigraph by trying every pair of nodes as a separation pair, cubic in
+    // the number of nodes
+    pub fn by_separation_pairs(edges: &[Option<(usize, usize)>]) -> Triconnected {
+        let mut decomposition = Triconnected { edges: Vec::new(), components: Vec::new() };
+        let mut all = Vec::new();
+        for (e, ends) in edges.iter().enumerate() {
+            if let Some(ends) = ends {
+                all.push(decomposition.edges.len());
+                decomposition.edges.push(SplitEdge { ends: *ends, real: Some(e) });
+            }
+        }
+        let mut pending = vec![all];
+        while let Some(component) = pending.pop() {
+            decomposition.split(component, &mut pending);
+        }
+        decomposition.merge(ComponentKind::Bond);
+        decomposition.merge(ComponentKind::Polygon);
+        decomposition
+    }
+
+    // the triconnected components of a flow graph without its capping backedges, None when the graph
+    // is not biconnected
+    pub fn from_flow_graph(graph: &FlowGraph) -> Option<Triconnected> {
+        let digraph = DiGraph::from_flow_graph(graph);
+        Triconnected::new(&digraph.edges)
+    }
+
+    fn virtual_edge(&mut self, a: usize, b: usize) -> usize {
+        self.edges.push(SplitEdge { ends: (a, b), real: None });
+        self.edges.len() - 1
+    }
+
+    fn finish(&mut self, edges: Vec<usize>) {
+        let kind = self.kind_of(&edges);
+        self.components.push(Component { kind, edges });
+    }
+
+    fn kind_of(&self, edges: &[usize]) -> ComponentKind {
+        let nodes = edges.iter().flat_map(|e| vec![self.edges[*e].ends.0, self.edges[*e].ends.1]).collect::<BTreeSet<usize>>();
+        if nodes.len() == 2 {
+            ComponentKind::Bond
+        } else if nodes.len() == edges.len() && self.is_cycle(edges) {
+            ComponentKind::Polygon
+        } else {
+            ComponentKind::Rigid
+        }
+    }
+
+    fn is_cycle(&self, edges: &[usize]) -> bool {
+        let mut degree: BTreeMap<usize, usize> = BTreeMap::new();
+        for e in edges.iter() {
+            let (a, b) = self.edges[*e].ends;
+            *degree.entry(a).or_default() += 1;
+            *degree.entry(b).or_default() += 1;
+        }
+        degree.values().all(|d| *d == 2)
+    }
+
+    // split one component once, pushing the pieces back, or record it when it cannot be split
+    fn split(&mut self, mut edges: Vec<usize>, pending: &mut Vec<Vec<usize>>) {
+        // multiple edges go into a bond of their own, unless that is all there is
+        let mut groups: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        for e in edges.iter() {
+            let (a, b) = self.edges[*e].ends;
+            groups.entry(key(a, b)).or_default().push(*e);
+        }
+        if groups.len() == 1 {
+            self.finish(edges);
+            return;
+        }
+        if groups.values().any(|g| g.len() > 1) {
+            edges.clear();
+            for ((a, b), group) in groups {
+                if group.len() > 1 {
+                    let v = self.virtual_edge(a, b);
+                    let mut bond = group;
+                    bond.push(v);
+                    self.finish(bond);
+                    edges.push(v);
+                } else {
+                    edges.extend(group);
+                }
+            }
+            pending.push(edges);
+            return;
+        }
+        let nodes = edges.iter().flat_map(|e| vec![self.edges[*e].ends.0, self.edges[*e].ends.1]).collect::<Vec<usize>>();
+        let nodes = nodes.into_iter().collect::<BTreeSet<usize>>().into_iter().collect::<Vec<usize>>();
+        for (i, a) in nodes.iter().enumerate() {
+            for b in nodes[i + 1..].iter() {
+                let classes = self.separation_classes(&edges, *a, *b);
+                if classes.len() < 2 || (classes.len() == 2 && classes.iter().any(|c| c.len() == 1)) {
+                    continue;
+                }
+                // split off one class with at least two edges, leaving at least two behind
+                let class = classes.iter().find(|c| c.len() > 1 && edges.len() - c.len() > 1);
+                let class = match class {
+                    Some(c) => c.clone(),
+                    None => continue,
+                };
+                let v = self.virtual_edge(*a, *b);
+                let mut first = class.clone();
+                first.push(v);
+                let mut second = edges.iter().cloned().filter(|e| !class.contains(e)).collect::<Vec<usize>>();
+                second.push(v);
+                pending.push(first);
+                pending.push(second);
+                return;
+            }
+        }
+        self.finish(edges);
+    }
+
+    // classes of edges joined by paths that do not pass through a or b
+    fn separation_classes(&self, edges: &[usize], a: usize, b: usize) -> Vec<Vec<usize>> {
+        let mut by_node: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, e) in edges.iter().enumerate() {
+            let (x, y) = self.edges[*e].ends;
+            for n in [x, y] {
+                if n != a && n != b {
+                    by_node.entry(n).or_default().push(i);
+                }
+            }
+        }
+        let mut class = vec![usize::MAX; edges.len()];
+        let mut classes = Vec::new();
+        for start in 0..edges.len() {
+            if class[start] != usize::MAX {
+                continue;
+            }
+            let c = classes.len();
+            let mut members = vec![edges[start]];
+            class[start] = c;
+            let mut stack = vec![start];
+            while let Some(i) = stack.pop() {
+                let (x, y) = self.edges[edges[i]].ends;
+                for n in [x, y] {
+                    for j in by_node.get(&n).into_iter().flatten() {
+                        if class[*j] == usize::MAX {
+                            class[*j] = c;
+                            members.push(edges[*j]);
+                            stack.push(*j);
+                        }
+                    }
+                }
+            }
+            classes.push(members);
+        }
+        classes
+    }
+
+    // merge components of one kind that share a virtual edge, dropping that edge
+    fn merge(&mut self, kind: ComponentKind) {
+        loop {
+            let mut owner: BTreeMap<usize, usize> = BTreeMap::new();
+            let mut pair = None;
+            'search: for (c, component) in self.components.iter().enumerate() {
+                if component.kind != kind {
+                    continue;
+                }
+                for e in component.edges.iter() {
+                    if self.edges[*e].real.is_some() {
+                        continue;
+                    }
+                    if let Some(d) = owner.insert(*e, c) {
+                        pair = Some((d, c, *e));
+                        break 'search;
+                    }
+                }
+            }
+            let (d, c, v) = match pair {
+                Some(p) => p,
+                None => return,
+            };
+            let absorbed = self.components.remove(c);
+            let target = &mut self.components[d];
+            target.edges.retain(|e| *e != v);
+            target.edges.extend(absorbed.edges.into_iter().filter(|e| *e != v));
+        }
+    }
+
+    // the components holding a split edge, one for a real edge and two for a virtual edge
+    pub fn components_of(&self, e: usize) -> Vec<usize> {
+        (0..self.components.len()).filter(|c| self.components[*c].edges.contains(&e)).collect()
+    }
+
+    // the nodes of a component, sorted
+    pub fn nodes(&self, c: usize) -> Vec<usize> {
+        let nodes = self.components[c].edges.iter()
+            .flat_map(|e| vec![self.edges[*e].ends.0, self.edges[*e].ends.1])
+            .collect::<BTreeSet<usize>>();
+        nodes.into_iter().collect()
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Arc {
+    Unseen,
+    Tree, // from father to child
+    Frond, // from a node to one of its ancestors
+}
+
+const NONE: usize = usize::MAX;
+
+// the linear time search of Hopcroft and Tarjan with the corrections of Gutwenger and Mutzel, "A linear
+// time implementation of SPQR-trees"; after the first search nodes are renumbered from 1 so that the
+// children of a node, in adjacency order, have decreasing numbers, and all numbers below are those
+struct PathSearch {
+    split: Triconnected,
+    pieces: Vec<Vec<usize>>, // split components before merging, by split edge index
+    arc: Vec<(usize, usize)>, // split edge oriented by the search
+    arc_type: Vec<Arc>,
+    adjacency: Vec<Vec<Option<usize>>>, // outgoing arcs in path order, None once deleted
+    in_adjacency: Vec<(usize, usize)>, // position of an arc in adjacency
+    number: Vec<usize>,
+    node_at: Vec<usize>,
+    lowpt1: Vec<usize>,
+    lowpt2: Vec<usize>,
+    descendants: Vec<usize>,
+    degree: Vec<usize>,
+    father: Vec<usize>,
+    tree_arc: Vec<usize>, // the tree arc into a node
+    starts_path: Vec<bool>,
+    // sources of the fronds into each node, first visited first; a list entered at the front holds
+    // the entries added while splitting, and dead entries are skipped
+    high_entries: Vec<(usize, bool)>,
+    high_front: Vec<Vec<usize>>,
+    high_back: Vec<(usize, Vec<usize>)>,
+    in_high: Vec<Option<usize>>,
+    estack: Vec<usize>,
+    tstack: Vec<Option<(usize, usize, usize)>>, // (h, a, b) triples, None marking the end of a path
+    root: usize,
+}
+
+impl PathSearch {
+    fn decompose(edges: &[Option<(usize, usize)>]) -> Option<Triconnected> {
+        let mut split = Triconnected { edges: Vec::new(), components: Vec::new() };
+        let mut node_count = 0;
+        for (e, ends) in edges.iter().enumerate() {
+            if let Some((a, b)) = ends {
+                if a == b {
+                    return None;
+                }
+                node_count = node_count.max(a + 1).max(b + 1);
+                split.edges.push(SplitEdge { ends: (*a, *b), real: Some(e) });
+            }
+        }
+        if split.edges.is_empty() {
+            return None;
+        }
+        // multiple edges go into a bond of their own, the graph keeping one virtual edge for them
+        let mut by_low = vec![Vec::new(); node_count];
+        for (e, edge) in split.edges.iter().enumerate() {
+            by_low[edge.ends.0.max(edge.ends.1)].push(e);
+        }
+        let mut by_high = vec![Vec::new(); node_count];
+        for e in by_low.into_iter().flatten() {
+            let (a, b) = split.edges[e].ends;
+            by_high[a.min(b)].push(e);
+        }
+        let sorted = by_high.into_iter().flatten().collect::<Vec<usize>>();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for e in sorted {
+            let (a, b) = split.edges[e].ends;
+            match groups.last_mut() {
+                Some(g) if { let (c, d) = split.edges[g[0]].ends; key(a, b) == key(c, d) } => g.push(e),
+                _ => groups.push(vec![e]),
+            }
+        }
+        if groups.len() == 1 {
+            let all = groups.pop().unwrap();
+            split.finish(all);
+            return Some(split);
+        }
+        let mut pieces = Vec::new();
+        let mut graph_edges = Vec::new();
+        for group in groups {
+            if group.len() == 1 {
+                graph_edges.push(group[0]);
+                continue;
+            }
+            let (a, b) = split.edges[group[0]].ends;
+            let v = split.virtual_edge(a, b);
+            let mut bond = group;
+            bond.push(v);
+            pieces.push(bond);
+            graph_edges.push(v);
+        }
+
+        let edge_count = split.edges.len();
+        let root = split.edges[graph_edges[0]].ends.0;
+        let mut search = PathSearch {
+            split,
+            pieces,
+            arc: vec![(NONE, NONE); edge_count],
+            arc_type: vec![Arc::Unseen; edge_count],
+            adjacency: vec![Vec::new(); node_count],
+            in_adjacency: vec![(NONE, NONE); edge_count],
+            number: vec![0; node_count],
+            node_at: vec![NONE; node_count + 1],
+            lowpt1: vec![0; node_count],
+            lowpt2: vec![0; node_count],
+            descendants: vec![0; node_count],
+            degree: vec![0; node_count],
+            father: vec![NONE; node_count],
+            tree_arc: vec![NONE; node_count],
+            starts_path: vec![false; edge_count],
+            high_entries: Vec::new(),
+            high_front: vec![Vec::new(); node_count],
+            high_back: vec![(0, Vec::new()); node_count],
+            in_high: vec![None; edge_count],
+            estack: Vec::new(),
+            tstack: vec![None],
+            root,
+        };
+        let mut incident = vec![Vec::new(); node_count];
+        for e in graph_edges.iter() {
+            let (a, b) = search.split.edges[*e].ends;
+            incident[a].push(*e);
+            incident[b].push(*e);
+        }
+        for (v, edges) in incident.iter().enumerate() {
+            search.degree[v] = edges.len();
+        }
+        let vertices = search.first_search(&incident)?;
+        search.order_adjacency(&graph_edges, vertices);
+        search.find_paths(vertices);
+        search.search_paths();
+        let last = std::mem::take(&mut search.estack);
+        search.pieces.push(last);
+        Some(search.merge())
+    }
+
+    // number the nodes depth first, orient the edges and compute lowpoints, returning the number of
+    // nodes, or None when the graph is not biconnected
+    fn first_search(&mut self, incident: &[Vec<usize>]) -> Option<usize> {
+        let mut count = 1;
+        let mut root_children = 0;
+        let mut stack = vec![(self.root, 0)];
+        self.enter_first(self.root, count);
+        while let Some((v, i)) = stack.pop() {
+            if i == incident[v].len() {
+                if let Some((u, _)) = stack.last() {
+                    let u = *u;
+                    let w = v;
+                    if self.lowpt1[w] < self.lowpt1[u] {
+                        self.lowpt2[u] = self.lowpt1[u].min(self.lowpt2[w]);
+                        self.lowpt1[u] = self.lowpt1[w];
+                    } else if self.lowpt1[w] == self.lowpt1[u] {
+                        self.lowpt2[u] = self.lowpt2[u].min(self.lowpt2[w]);
+                    } else {
+                        self.lowpt2[u] = self.lowpt2[u].min(self.lowpt1[w]);
+                    }
+                    self.descendants[u] += self.descendants[w];
+                    // a cut node below the root
+                    if u != self.root && self.lowpt1[w] >= self.number[u] {
+                        return None;
+                    }
+                }
+                continue;
+            }
+            stack.push((v, i + 1));
+            let e = incident[v][i];
+            if self.arc_type[e] != Arc::Unseen {
+                continue;
+            }
+            let (a, b) = self.split.edges[e].ends;
+            let w = if a == v { b } else { a };
+            self.arc[e] = (v, w);
+            if self.number[w] == 0 {
+                if v == self.root {
+                    root_children += 1;
+                    if root_children > 1 {
+                        return None;
+                    }
+                }
+                self.arc_type[e] = Arc::Tree;
+                self.tree_arc[w] = e;
+                self.father[w] = v;
+                count += 1;
+                self.enter_first(w, count);
+                stack.push((w, 0));
+            } else {
+                self.arc_type[e] = Arc::Frond;
+                let n = self.number[w];
+                if n < self.lowpt1[v] {
+                    self.lowpt2[v] = self.lowpt1[v];
+                    self.lowpt1[v] = n;
+                } else if n > self.lowpt1[v] {
+                    self.lowpt2[v] = self.lowpt2[v].min(n);
+                }
+            }
+        }
+        let vertices = incident.iter().filter(|edges| !edges.is_empty()).count();
+        if count < vertices {
+            return None;
+        }
+        Some(count)
+    }
+
+    fn enter_first(&mut self, v: usize, number: usize) {
+        self.number[v] = number;
+        self.lowpt1[v] = number;
+        self.lowpt2[v] = number;
+        self.descendants[v] = 1;
+    }
+
+    // sort the arcs out of each node by phi, so that paths go to the lowest ancestor first
+    fn order_adjacency(&mut self, graph_edges: &[usize], vertices: usize) {
+        let mut buckets = vec![Vec::new(); 3 * vertices + 3];
+        for e in graph_edges.iter() {
+            let (v, w) = self.arc[*e];
+            let phi = if self.arc_type[*e] == Arc::Frond {
+                3 * self.number[w] + 1
+            } else if self.lowpt2[w] < self.number[v] {
+                3 * self.lowpt1[w]
+            } else {
+                3 * self.lowpt1[w] + 2
+            };
+            buckets[phi].push(*e);
+        }
+        for e in buckets.into_iter().flatten() {
+            let v = self.arc[e].0;
+            self.in_adjacency[e] = (v, self.adjacency[v].len());
+            self.adjacency[v].push(Some(e));
+        }
+    }
+
+    // renumber the nodes, mark the first arc of each path and list the fronds into each node
+    fn find_paths(&mut self, vertices: usize) {
+        let old_node_at = {
+            let mut at = vec![NONE; vertices + 1];
+            for (v, n) in self.number.iter().enumerate() {
+                if *n != 0 {
+                    at[*n] = v;
+                }
+            }
+            at
+        };
+        let mut newnum = vec![0; self.number.len()];
+        let mut count = vertices;
+        let mut new_path = true;
+        newnum[self.root] = count - self.descendants[self.root] + 1;
+        let mut stack = vec![(self.root, 0)];
+        while let Some((v, i)) = stack.pop() {
+            if i == self.adjacency[v].len() {
+                if !stack.is_empty() {
+                    count -= 1;
+                }
+                continue;
+            }
+            stack.push((v, i + 1));
+            let e = self.adjacency[v][i].unwrap();
+            if new_path {
+                new_path = false;
+                self.starts_path[e] = true;
+            }
+            let w = self.arc[e].1;
+            if self.arc_type[e] == Arc::Tree {
+                newnum[w] = count - self.descendants[w] + 1;
+                stack.push((w, 0));
+            } else {
+                let entry = self.high_entries.len();
+                self.high_entries.push((newnum[v], true));
+                self.high_back[w].1.push(entry);
+                self.in_high[e] = Some(entry);
+                new_path = true;
+            }
+        }
+        for v in 0..self.number.len() {
+            if self.number[v] == 0 {
+                continue;
+            }
+            self.lowpt1[v] = newnum[old_node_at[self.lowpt1[v]]];
+            self.lowpt2[v] = newnum[old_node_at[self.lowpt2[v]]];
+            self.node_at[newnum[v]] = v;
+        }
+        self.number = newnum;
+    }
+
+    // the highest source of a frond into v still in the graph, 0 when there is none
+    fn high(&mut self, v: usize) -> usize {
+        while let Some(entry) = self.high_front[v].last() {
+            if self.high_entries[*entry].1 {
+                return self.high_entries[*entry].0;
+            }
+            self.high_front[v].pop();
+        }
+        let (head, back) = &mut self.high_back[v];
+        while *head < back.len() {
+            let (value, alive) = self.high_entries[back[*head]];
+            if alive {
+                return value;
+            }
+            *head += 1;
+        }
+        0
+    }
+
+    fn delete_high(&mut self, e: usize) {
+        if let Some(entry) = self.in_high[e].take() {
+            self.high_entries[entry].1 = false;
+        }
+    }
+
+    fn delete_arc(&mut self, e: usize) {
+        let (v, i) = self.in_adjacency[e];
+        self.adjacency[v][i] = None;
+    }
+
+    fn first_child_number(&self, w: usize) -> usize {
+        match self.adjacency[w].iter().flatten().next() {
+            Some(e) => self.number[self.arc[*e].1],
+            None => 0,
+        }
+    }
+
+    // is w of degree two with a tree arc out, so that v, w and its child make a sequence?
+    fn passes_through(&self, w: usize) -> bool {
+        self.degree[w] == 2 && self.first_child_number(w) > self.number[w]
+    }
+
+    fn new_edge(&mut self, a: usize, b: usize) -> usize {
+        let e = self.split.virtual_edge(a, b);
+        self.arc.push((a, b));
+        self.arc_type.push(Arc::Unseen);
+        self.in_adjacency.push((NONE, NONE));
+        self.starts_path.push(false);
+        self.in_high.push(None);
+        e
+    }
+
+    fn top(&self) -> Option<(usize, usize, usize)> {
+        *self.tstack.last().unwrap()
+    }
+
+    // the a of the top triple, 0 at the end of a path as no node is numbered 0
+    fn top_a(&self) -> usize {
+        self.top().map_or(0, |t| t.1)
+    }
+
+    // push the triple for a path starting with an arc to a node with lowpoint (or number) low
+    fn update_tstack(&mut self, low: usize, h_new: usize, b_new: usize) {
+        if self.top_a() > low {
+            let mut y = 0;
+            let mut b = 0;
+            while self.top_a() > low {
+                let (h, _, bb) = self.tstack.pop().unwrap().unwrap();
+                y = y.max(h);
+                b = bb;
+            }
+            self.tstack.push(Some((y, low, b)));
+        } else {
+            self.tstack.push(Some((h_new, low, b_new)));
+        }
+    }
+
+    fn search_paths(&mut self) {
+        // frames of (node, position in adjacency, arcs left to visit, tree arc whose child is searched)
+        let outv = self.adjacency[self.root].len();
+        let mut stack: Vec<(usize, usize, usize, Option<usize>)> = vec![(self.root, 0, outv, None)];
+        while let Some((v, i, outv, returning)) = stack.pop() {
+            if let Some(e) = returning {
+                let w = self.arc[e].1;
+                self.after_tree_arc(v, i, e, w, outv);
+                stack.push((v, i + 1, outv - 1, None));
+                continue;
+            }
+            if i == self.adjacency[v].len() {
+                continue;
+            }
+            let e = match self.adjacency[v][i] {
+                Some(e) => e,
+                None => {
+                    stack.push((v, i + 1, outv, None));
+                    continue;
+                }
+            };
+            let w = self.arc[e].1;
+            let vnum = self.number[v];
+            let wnum = self.number[w];
+            if self.arc_type[e] == Arc::Tree {
+                if self.starts_path[e] {
+                    self.update_tstack(self.lowpt1[w], wnum + self.descendants[w] - 1, vnum);
+                    self.tstack.push(None);
+                }
+                stack.push((v, i, outv, Some(e)));
+                let child_arcs = self.adjacency[w].len();
+                stack.push((w, 0, child_arcs, None));
+            } else {
+                if self.starts_path[e] {
+                    self.update_tstack(wnum, vnum, vnum);
+                }
+                self.estack.push(e);
+                stack.push((v, i + 1, outv, None));
+            }
+        }
+    }
+
+    // split off the components found below the tree arc at position i of v, once the search of its
+    // child w is done
+    fn after_tree_arc(&mut self, v: usize, i: usize, e: usize, mut w: usize, outv: usize) {
+        let vnum = self.number[v];
+        let mut wnum = self.number[w];
+        self.estack.push(self.tree_arc[w]);
+
+        // type 2 pairs
+        while vnum != 1 && (self.top_a() == vnum || self.passes_through(w)) {
+            let through_w = self.passes_through(w);
+            if let Some((_, a, b)) = self.top() {
+                if a == vnum && self.father[self.node_at[b]] == self.node_at[a] {
+                    self.tstack.pop();
+                    continue;
+                }
+            }
+            let mut bond_edge = None;
+            let x;
+            let mut virtual_edge;
+            if through_w {
+                let e1 = self.estack.pop().unwrap();
+                let e2 = self.estack.pop().unwrap();
+                self.delete_arc(e2);
+                x = self.arc[e2].1;
+                virtual_edge = self.new_edge(v, x);
+                self.degree[x] -= 1;
+                self.degree[v] -= 1;
+                self.pieces.push(vec![e1, e2, virtual_edge]);
+                if let Some(top) = self.estack.last() {
+                    if self.arc[*top] == (x, v) {
+                        let eab = self.estack.pop().unwrap();
+                        self.delete_arc(eab);
+                        self.delete_high(eab);
+                        bond_edge = Some(eab);
+                    }
+                }
+            } else {
+                let (h, _, b) = self.tstack.pop().unwrap().unwrap();
+                let mut piece = Vec::new();
+                while let Some(xy) = self.estack.last() {
+                    let (x0, y0) = self.arc[*xy];
+                    let (xn, yn) = (self.number[x0], self.number[y0]);
+                    if !(vnum <= xn && xn <= h && vnum <= yn && yn <= h) {
+                        break;
+                    }
+                    let eh = self.estack.pop().unwrap();
+                    if (xn == vnum && yn == b) || (yn == vnum && xn == b) {
+                        self.delete_arc(eh);
+                        self.delete_high(eh);
+                        bond_edge = Some(eh);
+                    } else {
+                        if self.in_adjacency[eh] != (v, i) {
+                            self.delete_arc(eh);
+                            self.delete_high(eh);
+                        }
+                        piece.push(eh);
+                        self.degree[x0] -= 1;
+                        self.degree[y0] -= 1;
+                    }
+                }
+                x = self.node_at[b];
+                virtual_edge = self.new_edge(v, x);
+                piece.push(virtual_edge);
+                self.pieces.push(piece);
+            }
+            if let Some(eab) = bond_edge {
+                let outer = self.new_edge(v, x);
+                self.pieces.push(vec![eab, virtual_edge, outer]);
+                virtual_edge = outer;
+                self.degree[x] -= 1;
+                self.degree[v] -= 1;
+            }
+            self.estack.push(virtual_edge);
+            self.adjacency[v][i] = Some(virtual_edge);
+            self.in_adjacency[virtual_edge] = (v, i);
+            self.degree[x] += 1;
+            self.degree[v] += 1;
+            self.father[x] = v;
+            self.tree_arc[x] = virtual_edge;
+            self.arc_type[virtual_edge] = Arc::Tree;
+            w = x;
+            wnum = self.number[w];
+        }
+
+        // type 1 pair (lowpt1(w), v)
+        if self.lowpt2[w] >= vnum && self.lowpt1[w] < vnum && (self.father[v] != self.root || outv >= 2) {
+            let mut piece = Vec::new();
+            let (mut xn, mut yn) = (0, 0);
+            while let Some(xy) = self.estack.last() {
+                let xy = *xy;
+                let (x0, y0) = self.arc[xy];
+                xn = self.number[x0];
+                yn = self.number[y0];
+                let below = |n: usize| wnum <= n && n < wnum + self.descendants[w];
+                if !(below(xn) || below(yn)) {
+                    break;
+                }
+                piece.push(self.estack.pop().unwrap());
+                self.delete_high(xy);
+                self.degree[x0] -= 1;
+                self.degree[y0] -= 1;
+            }
+            let low = self.node_at[self.lowpt1[w]];
+            let mut virtual_edge = self.new_edge(v, low);
+            piece.push(virtual_edge);
+            self.pieces.push(piece);
+            if (xn == vnum && yn == self.lowpt1[w]) || (yn == vnum && xn == self.lowpt1[w]) {
+                let eh = self.estack.pop().unwrap();
+                if self.in_adjacency[eh] != (v, i) {
+                    self.delete_arc(eh);
+                }
+                let outer = self.new_edge(v, low);
+                self.pieces.push(vec![eh, virtual_edge, outer]);
+                self.in_high[outer] = self.in_high[eh].take();
+                virtual_edge = outer;
+                self.degree[v] -= 1;
+                self.degree[low] -= 1;
+            }
+            if low != self.father[v] {
+                self.estack.push(virtual_edge);
+                self.adjacency[v][i] = Some(virtual_edge);
+                self.in_adjacency[virtual_edge] = (v, i);
+                self.arc_type[virtual_edge] = Arc::Frond;
+                if self.in_high[virtual_edge].is_none() && self.high(low) < vnum {
+                    let entry = self.high_entries.len();
+                    self.high_entries.push((vnum, true));
+                    self.high_front[low].push(entry);
+                    self.in_high[virtual_edge] = Some(entry);
+                }
+                self.degree[v] += 1;
+                self.degree[low] += 1;
+            } else {
+                // the new edge runs parallel to the tree arc into v
+                self.adjacency[v][i] = None;
+                let tree_edge = self.new_edge(low, v);
+                let old = self.tree_arc[v];
+                self.pieces.push(vec![virtual_edge, tree_edge, old]);
+                self.tree_arc[v] = tree_edge;
+                self.arc_type[tree_edge] = Arc::Tree;
+                let (u, j) = self.in_adjacency[old];
+                self.in_adjacency[tree_edge] = (u, j);
+                self.adjacency[u][j] = Some(tree_edge);
+            }
+        }
+
+        if self.starts_path[e] {
+            while self.top().is_some() {
+                self.tstack.pop();
+            }
+            self.tstack.pop();
+        }
+        while let Some((h, _, b)) = self.top() {
+            if b == vnum || self.high(v) <= h {
+                break;
+            }
+            self.tstack.pop();
+        }
+    }
+
+    // classify the split components and merge bonds and polygons that share a virtual edge
+    fn merge(mut self) -> Triconnected {
+        let pieces = std::mem::take(&mut self.pieces);
+        let kinds = pieces.iter().map(|p| self.split.kind_of(p)).collect::<Vec<ComponentKind>>();
+        let mut owners = vec![Vec::new(); self.split.edges.len()];
+        for (p, piece) in pieces.iter().enumerate() {
+            for e in piece.iter() {
+                owners[*e].push(p);
+            }
+        }
+        let mut parent = (0..pieces.len()).collect::<Vec<usize>>();
+        fn find(parent: &mut [usize], mut p: usize) -> usize {
+            while parent[p] != p {
+                parent[p] = parent[parent[p]];
+                p = parent[p];
+            }
+            p
+        }
+        for (e, owner) in owners.iter().enumerate() {
+            if self.split.edges[e].real.is_some() || owner.len() != 2 {
+                continue;
+            }
+            let (p, q) = (owner[0], owner[1]);
+            if kinds[p] == kinds[q] && kinds[p] != ComponentKind::Rigid {
+                let (p, q) = (find(&mut parent, p), find(&mut parent, q));
+                parent[q.max(p)] = p.min(q);
+            }
+        }
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (p, piece) in pieces.iter().enumerate() {
+            let g = find(&mut parent, p);
+            let edges = groups.entry(g).or_default();
+            for e in piece.iter() {
+                let owner = &owners[*e];
+                let inner = owner.len() == 2 && find(&mut parent, owner[0]) == find(&mut parent, owner[1]);
+                if !inner {
+                    edges.push(*e);
+                }
+            }
+        }
+        for (g, edges) in groups {
+            self.split.components.push(Component { kind: kinds[g], edges });
+        }
+        self.split
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::Rng;
+
+    // components as (kind, nodes, real edges, number of virtual edges), which does not depend on how
+    // virtual edges are numbered
+    fn canonical(decomposition: &Triconnected) -> Vec<(String, Vec<usize>, Vec<usize>, usize)> {
+        let mut components = (0..decomposition.components.len()).map(|c| {
+            let edges = &decomposition.components[c].edges;
+            let mut real = edges.iter().filter_map(|e| decomposition.edges[*e].real).collect::<Vec<usize>>();
+            real.sort();
+            let virtual_edges = edges.len() - real.len();
+            (format!("{:?}", decomposition.components[c].kind), decomposition.nodes(c), real, virtual_edges)
+        }).collect::<Vec<_>>();
+        components.sort();
+        components
+    }
+
+    fn check(edges: &[Option<(usize, usize)>]) {
+        let decomposition = match Triconnected::new(edges) {
+            Some(d) => d,
+            None => {
+                assert!(!is_biconnected(edges), "{:?}", edges);
+                return;
+            }
+        };
+        assert!(is_biconnected(edges), "{:?}", edges);
+        for e in 0..decomposition.edges.len() {
+            let holders = decomposition.components_of(e);
+            let expected = if decomposition.edges[e].real.is_some() { 1 } else { 2 };
+            assert!(holders.len() == expected || holders.is_empty() && decomposition.edges[e].real.is_none(),
+                "{:?} split edge {} in {:?}", edges, e, holders);
+        }
+        let reference = Triconnected::by_separation_pairs(edges);
+        assert_eq!(canonical(&decomposition), canonical(&reference), "{:?}", edges);
+    }
+
+    #[test]
+    fn small_graphs() {
+        // a triangle, a triple bond, K4 and a prism
+        check(&[Some((0, 1)), Some((1, 2)), Some((2, 0))]);
+        check(&[Some((0, 1)), Some((1, 0)), Some((0, 1))]);
+        check(&[Some((0, 1)), Some((0, 2)), Some((0, 3)), Some((1, 2)), Some((1, 3)), Some((2, 3))]);
+        check(&[Some((0, 1)), Some((1, 2)), Some((2, 0)), Some((3, 4)), Some((4, 5)), Some((5, 3)),
+            Some((0, 3)), Some((1, 4)), None, Some((2, 5))]);
+        // two K4s sharing an edge and a path
+        check(&[Some((0, 1)), Some((0, 2)), Some((0, 3)), Some((1, 2)), Some((1, 3)), Some((2, 3)),
+            Some((0, 4)), Some((1, 4)), Some((4, 5)), Some((5, 1)), Some((0, 6)), Some((6, 1))]);
+        // a path is not biconnected
+        check(&[Some((0, 1)), Some((1, 2))]);
+    }
+
+    #[test]
+    fn random_multigraphs() {
+        let mut rng = Rng::new(37);
+        let mut decomposed = 0;
+        for _ in 0..4000 {
+            let nodes = 2 + rng.below(8);
+            let count = nodes + rng.below(2 * nodes);
+            let mut edges = Vec::new();
+            for _ in 0..count {
+                let a = rng.below(nodes);
+                let b = rng.below(nodes);
+                edges.push(if a == b { None } else { Some((a, b)) });
+            }
+            if is_biconnected(&edges) {
+                decomposed += 1;
+            }
+            check(&edges);
+        }
+        assert!(decomposed > 500);
+    }
+}
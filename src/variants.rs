@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::bidirected::{BiGraph, Handle, Orientation};
+use crate::flubbles::FlubbleTree;
+use crate::sequence::PackedSequence;
+use crate::traversals::TraversalOptions;
+
+// the kind of variation a bubble holds
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum VariantType {
+    Snp, // alleles differ in a single base
+    Mnp, // alleles differ in a run of bases of the same length
+    Indel, // some allele lacks bases the others have
+    Inversion, // alleles take a node in opposite orientations
+    Cyclic, // some allele passes a node more than once
+    Complex, // alleles differ in length and content, or hold nested variation
+}
+
+impl VariantType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VariantType::Snp => "SNP",
+            VariantType::Mnp => "MNP",
+            VariantType::Indel => "INDEL",
+            VariantType::Inversion => "INV",
+            VariantType::Cyclic => "CYCLIC",
+            VariantType::Complex => "COMPLEX",
+        }
+    }
+}
+
+impl fmt::Display for VariantType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+// the type of a bubble from the walks of its alleles, None when it has no cycle and their sequences are
+// all the same; nested says a bubble inside varies and cyclic that the walks were cut short at a cycle;
+// cycles are checked first, then inversions, then nesting, then the sequences left once the bases all
+// alleles share at the start and end are trimmed
+pub fn classify(alleles: &[Vec<Handle>], sequences: &[PackedSequence], nested: bool, cyclic: bool) -> Option<VariantType> {
+    let strings = alleles.iter()
+        .map(|a| a.iter().map(|h| match h.orientation {
+            Orientation::Forward => sequences[h.node].to_string(),
+            Orientation::Reverse => sequences[h.node].reverse_complement(),
+        }).collect::<String>())
+        .collect::<Vec<String>>();
+    if cyclic || alleles.iter().any(|a| a.iter().map(|h| h.node).collect::<HashSet<usize>>().len() < a.len()) {
+        return Some(VariantType::Cyclic);
+    }
+    if strings.iter().all(|s| *s == strings[0]) {
+        return None;
+    }
+    let taken = alleles.iter().flatten().cloned().collect::<HashSet<Handle>>();
+    if taken.iter().any(|h| taken.contains(&h.flip())) {
+        return Some(VariantType::Inversion);
+    }
+    if nested {
+        return Some(VariantType::Complex);
+    }
+
+    let shortest = strings.iter().map(|s| s.len()).min().unwrap();
+    let shared = |a: &[u8], b: &[u8]| a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let prefix = strings.iter().map(|s| shared(strings[0].as_bytes(), s.as_bytes())).min().unwrap();
+    let reversed = strings.iter().map(|s| s.bytes().rev().collect::<Vec<u8>>()).collect::<Vec<Vec<u8>>>();
+    let suffix = reversed.iter().map(|s| shared(&reversed[0], s)).min().unwrap().min(shortest - prefix);
+    let lengths = strings.iter().map(|s| s.len() - prefix - suffix).collect::<Vec<usize>>();
+    Some(if lengths.iter().all(|l| *l == 1) {
+        VariantType::Snp
+    } else if lengths.iter().all(|l| *l == lengths[0]) {
+        VariantType::Mnp
+    } else if lengths.contains(&0) {
+        VariantType::Indel
+    } else {
+        VariantType::Complex
+    })
+}
+
+// the walks between the boundary nodes of a flubble, through the nodes inside it
+#[derive(Clone,Debug)]
+pub struct FlubbleAlleles {
+    pub flubble: usize,
+    pub walks: Vec<Vec<Handle>>, // steps between entry and exit, in depth first order following links by index
+    pub truncated: bool, // the cap was reached, so there may be more walks
+    pub cyclic: bool, // some walk was cut short because it would pass a step more than max_visits times
+}
+
+// the alleles of a flubble; collapse_children is ignored, walks always going through child flubbles
+pub fn flubble_alleles(graph: &BiGraph, tree: &FlubbleTree, f: usize, options: &TraversalOptions) -> FlubbleAlleles {
+    let flubble = &tree.flubbles[f];
+    let inside = tree.inside(f).into_iter().collect::<HashSet<usize>>();
+    // the handles a walk can go on to, by 2 * node + 1 when reversed
+    let slot = |h: Handle| 2 * h.node + (h.entry_side() & 1);
+    let mut next = vec![Vec::new(); 2 * graph.node_count()];
+    for (a, b) in graph.links.iter() {
+        next[slot(*a)].push(*b);
+        next[slot(b.flip())].push(a.flip());
+    }
+    let mut result = FlubbleAlleles { flubble: f, walks: Vec::new(), truncated: false, cyclic: false };
+    let mut visits = vec![0; graph.node_count()];
+    let mut stack = vec![(flubble.entry, 0)];
+    while let Some((h, i)) = stack.last_mut() {
+        let h = *h;
+        if *i == next[slot(h)].len() {
+            if h != flubble.entry {
+                visits[h.node] -= 1;
+            }
+            stack.pop();
+            continue;
+        }
+        let g = next[slot(h)][*i];
+        *i += 1;
+        if g == flubble.exit {
+            if result.walks.len() == options.max_traversals {
+                result.truncated = true;
+                break;
+            }
+            result.walks.push(stack[1..].iter().map(|(s, _)| *s).collect());
+            continue;
+        }
+        if !inside.contains(&g.node) {
+            continue;
+        }
+        if visits[g.node] == options.max_visits {
+            result.cyclic = true;
+            continue;
+        }
+        visits[g.node] += 1;
+        stack.push((g, 0));
+    }
+    let mut seen = HashSet::new();
+    result.walks.retain(|w| seen.insert(w.clone()));
+    result
+}
+
+// the type of every flubble, by flubble index
+pub fn classify_flubbles(graph: &BiGraph, tree: &FlubbleTree, options: &TraversalOptions) -> Vec<Option<VariantType>> {
+    let mut types = vec![None; tree.len()];
+    // preorder reversed, so children come before their parents
+    for f in (0..tree.len()).rev() {
+        let alleles = flubble_alleles(graph, tree, f, options);
+        let nested = tree.flubbles[f].children.iter().any(|c| types[*c].is_some());
+        types[f] = classify(&alleles.walks, &graph.sequences, nested, alleles.cyclic);
+    }
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types_of(gfa: &str) -> Vec<(String, Option<VariantType>)> {
+        let graph = BiGraph::from_gfa(gfa).unwrap();
+        let tree = FlubbleTree::new(&graph);
+        let types = classify_flubbles(&graph, &tree, &TraversalOptions::default());
+        tree.flubbles.iter().zip(types).map(|(f, t)| (graph.handle_name(f.entry), t)).collect()
+    }
+
+    #[test]
+    fn bubbles() {
+        let links = "L\ta\t+\tb\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tb\t+\td\t+\t0M\nL\tc\t+\td\t+\t0M\n";
+        let with = |b: &str, c: &str| format!("S\ta\tAC\nS\tb\t{}\nS\tc\t{}\nS\td\tGT\n{}", b, c, links);
+        assert_eq!(types_of(&with("A", "G")), vec![("a+".to_string(), Some(VariantType::Snp))]);
+        assert_eq!(types_of(&with("AT", "GC")), vec![("a+".to_string(), Some(VariantType::Mnp))]);
+        assert_eq!(types_of(&with("ATT", "AT")), vec![("a+".to_string(), Some(VariantType::Indel))]);
+        assert_eq!(types_of(&with("ATT", "GC")), vec![("a+".to_string(), Some(VariantType::Complex))]);
+        assert_eq!(types_of(&with("A", "A")), vec![("a+".to_string(), None)]);
+        let graph = BiGraph::from_gfa(&with("A", "G")).unwrap();
+        let tree = FlubbleTree::new(&graph);
+        assert!(tree.to_dot(&graph).contains("f0 [label=\"a+..d+|0|SNP\"];"));
+        assert!(tree.to_json(&graph).ends_with(",\"type\":\"SNP\"}]}\n"));
+
+        // a deletion: a -> d directly or through b
+        let deletion = "S\ta\tAC\nS\tb\tT\nS\td\tGT\nL\ta\t+\tb\t+\t0M\nL\tb\t+\td\t+\t0M\nL\ta\t+\td\t+\t0M\n";
+        assert_eq!(types_of(deletion), vec![("a+".to_string(), Some(VariantType::Indel))]);
+
+        // b in either orientation between a and d
+        let inversion = "S\ta\tAC\nS\tb\tTTG\nS\td\tGT\nL\ta\t+\tb\t+\t0M\nL\tb\t+\td\t+\t0M\nL\ta\t+\tb\t-\t0M\nL\tb\t-\td\t+\t0M\n";
+        assert_eq!(types_of(inversion), vec![("a+".to_string(), Some(VariantType::Inversion))]);
+
+        // a loop back from b to itself
+        let cyclic = "S\ta\tAC\nS\tb\tT\nS\td\tGT\nL\ta\t+\tb\t+\t0M\nL\tb\t+\td\t+\t0M\nL\tb\t+\tb\t+\t0M\n";
+        let graph = BiGraph::from_gfa(cyclic).unwrap();
+        let tree = FlubbleTree::new(&graph);
+        let twice = TraversalOptions { max_visits: 2, ..Default::default() };
+        let f = tree.flubbles.iter().position(|f| graph.handle_name(f.entry) == "a+").unwrap();
+        assert_eq!(flubble_alleles(&graph, &tree, f, &twice).walks.len(), 2);
+        assert_eq!(classify_flubbles(&graph, &tree, &twice)[f], Some(VariantType::Cyclic));
+
+        // a SNP inside one branch of an indel
+        let nested = "S\ta\tAC\nS\tb1\tA\nS\tx\tC\nS\ty\tG\nS\tb2\tA\nS\tc\tT\nS\td\tGT\nL\ta\t+\tb1\t+\t0M\nL\tb1\t+\tx\t+\t0M\n\
+            L\tb1\t+\ty\t+\t0M\nL\tx\t+\tb2\t+\t0M\nL\ty\t+\tb2\t+\t0M\nL\tb2\t+\td\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tc\t+\td\t+\t0M\n";
+        let types = types_of(nested);
+        assert!(types.contains(&("a+".to_string(), Some(VariantType::Complex))), "{:?}", types);
+        assert!(types.contains(&("b1+".to_string(), Some(VariantType::Snp))), "{:?}", types);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+
+use crate::{FlowGraph, StructureTree};
+use crate::bidirected::{BiGraph, Handle, Orientation};
+use crate::haplotypes::HaplotypeMatrix;
+use crate::paths::{PathIndex, parse_pansn};
+use crate::regions::RegionTable;
+use crate::sequence::PackedSequence;
+use crate::variants::{VariantType, classify};
+
+// a variant site: a region the reference path passes through, with more than one allele sequence
+#[derive(Clone,Debug)]
+pub struct Site {
+    pub region: usize, // index in the region table
+    pub id: usize, // region id in the structure tree
+    pub pos: usize, // 1-based position of the first base of the reference allele
+    pub alleles: Vec<String>, // the reference allele first
+    pub walks: Vec<Vec<Handle>>, // the first walk of each allele, read along the reference
+    pub level: usize, // sites this one is nested in
+    pub parent: Option<usize>, // id of the innermost site this one is nested in
+    pub genotypes: Vec<Vec<Option<usize>>>, // allele by sample then haplotype, None when not taken exactly once
+    pub variant_type: VariantType, // from every walk the paths take, complex when a site is nested in it
+}
+
+// the variants of a graph relative to one of its paths
+#[derive(Clone,Debug)]
+pub struct Vcf {
+    pub chrom: String, // contig of the reference path, from its PanSN name
+    pub length: usize, // offset of the reference path plus its length
+    pub samples: Vec<String>, // samples of the other paths, in order of first appearance
+    pub haplotypes: Vec<Vec<usize>>, // haplotype numbers of each sample, in increasing order
+    pub sites: Vec<Site>, // by position, outer sites before the sites they hold
+}
+
+impl Vcf {
+    // the sites of every region with alleles; the graph must be bigraph.to_flow_graph() and the tree built
+    // from it; paths of the reference's own sample are left out of the genotypes
+    pub fn new(graph: &FlowGraph, tree: &StructureTree, bigraph: &BiGraph, reference: &str) -> Result<Vcf, String> {
+        let ref_path = bigraph.paths.path_index(reference).ok_or(format!("no path named {}", reference))?;
+        let sequences = &bigraph.sequences;
+        let table = RegionTable::new(graph, tree)?;
+        let matrix = HaplotypeMatrix::new(bigraph, graph, &table);
+        let sequence_of = |walk: &[Handle]| walk.iter().map(|h| handle_sequence(sequences, *h)).collect::<String>();
+
+        let path = &bigraph.paths.paths[ref_path];
+        let (ref_sample, _, chrom) = parse_pansn(&path.name);
+        let index = PathIndex::new(path, sequences);
+        let ref_sequence = sequence_of(&path.steps);
+
+        // the sample and haplotype slot of each path
+        let mut samples: Vec<String> = Vec::new();
+        let mut haplotypes: Vec<Vec<usize>> = Vec::new();
+        for p in bigraph.paths.paths.iter() {
+            let (sample, haplotype, _) = parse_pansn(&p.name);
+            if sample == ref_sample {
+                continue;
+            }
+            let s = match samples.iter().position(|s| s == sample) {
+                Some(s) => s,
+                None => {
+                    samples.push(sample.to_string());
+                    haplotypes.push(Vec::new());
+                    samples.len() - 1
+                }
+            };
+            if !haplotypes[s].contains(&haplotype) {
+                haplotypes[s].push(haplotype);
+            }
+        }
+        for h in haplotypes.iter_mut() {
+            h.sort();
+        }
+        let slot = bigraph.paths.paths.iter()
+            .map(|p| {
+                let (sample, haplotype, _) = parse_pansn(&p.name);
+                let s = samples.iter().position(|s| s == sample)?;
+                Some((s, haplotypes[s].iter().position(|h| *h == haplotype).unwrap()))
+            })
+            .collect::<Vec<Option<(usize, usize)>>>();
+
+        let mut sites: Vec<Site> = Vec::new();
+        let mut site_of = vec![None; table.len()];
+        for r in 0..table.len() {
+            let region = &matrix.regions[r];
+            let ref_traversal = match region.traversals_of(ref_path).first() {
+                Some(t) => (*t).clone(),
+                None => continue,
+            };
+            // every allele read in the direction the reference reads the region
+            let along = |walk: &Vec<Handle>| if ref_traversal.reversed {
+                walk.iter().rev().map(|h| h.flip()).collect::<Vec<Handle>>()
+            } else {
+                walk.clone()
+            };
+            let mut alleles = vec![sequence_of(&along(&region.alleles[ref_traversal.allele]))];
+            let mut walks = vec![along(&region.alleles[ref_traversal.allele])];
+            let mut vcf_allele = vec![0; region.alleles.len()];
+            for (a, walk) in region.alleles.iter().enumerate() {
+                let walk = along(walk);
+                let sequence = sequence_of(&walk);
+                vcf_allele[a] = match alleles.iter().position(|s| *s == sequence) {
+                    Some(i) => i,
+                    None => {
+                        alleles.push(sequence);
+                        walks.push(walk);
+                        alleles.len() - 1
+                    }
+                };
+            }
+            if alleles.len() < 2 {
+                continue;
+            }
+
+            // the walks hold the boundary nodes of the region, so the alleles share bases at both ends; trim
+            // them as VCF normalization does, the suffix first, leaving each allele at least one base, then an
+            // empty allele takes the base before the site, or after it at the start of the reference
+            let (mut first, mut last) = (index.start(ref_traversal.start) - index.offset, index.start(ref_traversal.end) - index.offset);
+            while alleles.iter().all(|a| !a.is_empty()) && alleles.iter().all(|a| a.as_bytes().last() == alleles[0].as_bytes().last()) {
+                for a in alleles.iter_mut() {
+                    a.pop();
+                }
+                last -= 1;
+            }
+            while alleles.iter().all(|a| a.len() > 1 && a.as_bytes()[0] == alleles[0].as_bytes()[0]) {
+                for a in alleles.iter_mut() {
+                    a.remove(0);
+                }
+                first += 1;
+            }
+            if alleles.iter().any(|a| a.is_empty()) {
+                if first > 0 {
+                    let base = &ref_sequence[first - 1..first];
+                    alleles = alleles.into_iter().map(|a| format!("{}{}", base, a)).collect();
+                    first -= 1;
+                } else {
+                    let base = ref_sequence.get(last..last + 1).unwrap_or("N");
+                    alleles = alleles.into_iter().map(|a| format!("{}{}", a, base)).collect();
+                }
+            }
+            let pos = index.offset + first + 1;
+
+            let mut genotypes = haplotypes.iter().map(|h| vec![None; h.len()]).collect::<Vec<Vec<Option<usize>>>>();
+            let mut taken = haplotypes.iter().map(|h| vec![0; h.len()]).collect::<Vec<Vec<usize>>>();
+            for t in region.traversals.iter() {
+                if let Some((s, h)) = slot[t.path] {
+                    genotypes[s][h] = Some(vcf_allele[t.allele]);
+                    taken[s][h] += 1;
+                }
+            }
+            for (g, t) in genotypes.iter_mut().flatten().zip(taken.iter().flatten()) {
+                if *t != 1 {
+                    *g = None;
+                }
+            }
+
+            let mut ancestor = table.regions[r].parent;
+            while let Some(p) = ancestor {
+                if site_of[p].is_some() {
+                    break;
+                }
+                ancestor = table.regions[p].parent;
+            }
+            let outer = ancestor.map(|p| &sites[site_of[p].unwrap()]);
+            site_of[r] = Some(sites.len());
+            sites.push(Site {
+                region: r,
+                id: table.regions[r].id,
+                pos,
+                alleles,
+                walks,
+                level: outer.map_or(0, |s| s.level + 1),
+                parent: outer.map(|s| s.id),
+                genotypes,
+                variant_type: VariantType::Complex, // set below, once the nested sites are known
+            });
+        }
+        let parents = sites.iter().filter_map(|s| s.parent).collect::<HashSet<usize>>();
+        for site in sites.iter_mut() {
+            let alleles = &matrix.regions[site.region].alleles;
+            site.variant_type = classify(alleles, sequences, parents.contains(&site.id), false).unwrap_or(VariantType::Complex);
+        }
+        // regions come in preorder, so a stable sort keeps outer sites first
+        sites.sort_by_key(|s| s.pos);
+        Ok(Vcf { chrom: chrom.to_string(), length: index.start(path.steps.len()), samples, haplotypes, sites })
+    }
+
+    // VCF 4.2, with the walk of each allele, the nesting level, the parent site and the variant type in INFO
+    pub fn to_vcf(&self, bigraph: &BiGraph) -> String {
+        let mut vcf = String::new();
+        vcf.push_str("##fileformat=VCFv4.2\n");
+        vcf.push_str("##source=sesebub\n");
+        vcf.push_str(format!("##contig=<ID={},length={}>\n", self.chrom, self.length).as_str());
+        vcf.push_str("##INFO=<ID=AT,Number=R,Type=String,Description=\"Walk of each allele through the graph\">\n");
+        vcf.push_str("##INFO=<ID=LV,Number=1,Type=Integer,Description=\"Number of sites this site is nested in\">\n");
+        vcf.push_str("##INFO=<ID=PS,Number=1,Type=String,Description=\"ID of the innermost site this site is nested in\">\n");
+        vcf.push_str("##INFO=<ID=VT,Number=1,Type=String,Description=\"Variant type: SNP, MNP, INDEL, INV, CYCLIC or COMPLEX\">\n");
+        vcf.push_str("##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n");
+        vcf.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT");
+        for sample in self.samples.iter() {
+            vcf.push_str(format!("\t{}", sample).as_str());
+        }
+        vcf.push('\n');
+        for site in self.sites.iter() {
+            let walks = site.walks.iter()
+                .map(|w| w.iter().map(|h| walk_step(bigraph, *h)).collect::<String>())
+                .collect::<Vec<String>>();
+            let mut info = format!("AT={};LV={}", walks.join(","), site.level);
+            if let Some(parent) = site.parent {
+                info.push_str(format!(";PS={}", parent).as_str());
+            }
+            info.push_str(format!(";VT={}", site.variant_type).as_str());
+            vcf.push_str(format!("{}\t{}\t{}\t{}\t{}\t.\tPASS\t{}\tGT", self.chrom, site.pos, site.id, site.alleles[0], site.alleles[1..].join(","), info).as_str());
+            for genotype in site.genotypes.iter() {
+                let alleles = genotype.iter().map(|a| a.map_or(".".to_string(), |a| a.to_string())).collect::<Vec<String>>();
+                vcf.push_str(format!("\t{}", alleles.join("|")).as_str());
+            }
+            vcf.push('\n');
+        }
+        vcf
+    }
+}
+
+// a step written as in a GFA W line
+fn walk_step(bigraph: &BiGraph, h: Handle) -> String {
+    match h.orientation {
+        Orientation::Forward => format!(">{}", bigraph.names[h.node]),
+        Orientation::Reverse => format!("<{}", bigraph.names[h.node]),
+    }
+}
+
+fn handle_sequence(sequences: &[PackedSequence], h: Handle) -> String {
+    match h.orientation {
+        Orientation::Forward => sequences[h.node].to_string(),
+        Orientation::Reverse => sequences[h.node].reverse_complement(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    // s -> a -> (b1 -> (x | y) -> b2 | c) -> d -> t on GRCh38, with the second haplotype of HG002 walked
+    // backwards and a second contig for HG003 that misses the inner bubble
+    const GFA: &str = "S\ts\tACG\nS\ta\tT\nS\tb1\tGA\nS\tx\tC\nS\ty\tT\nS\tb2\tAG\nS\tc\tA\nS\td\tC\nS\tt\tGT\n\
+        L\ts\t+\ta\t+\t0M\nL\ta\t+\tb1\t+\t0M\nL\tb1\t+\tx\t+\t0M\nL\tb1\t+\ty\t+\t0M\nL\tx\t+\tb2\t+\t0M\n\
+        L\ty\t+\tb2\t+\t0M\nL\tb2\t+\td\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\tc\t+\td\t+\t0M\nL\td\t+\tt\t+\t0M\n\
+        P\tGRCh38#0#chr1\ts+,a+,b1+,x+,b2+,d+,t+\t*\n\
+        W\tHG002\t1\tchr1\t0\t7\t>s>a>c>d>t\n\
+        W\tHG002\t2\tchr1\t0\t12\t<t<d<b2<y<b1<a<s\n\
+        P\tHG003#1#chr1\ts+,a+,b1+,x+,b2+,d+,t+\t*\n";
+
+    #[test]
+    fn nested_sites() {
+        let bigraph = BiGraph::from_gfa(GFA).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let vcf = Vcf::new(&graph, &tree, &bigraph, "GRCh38#0#chr1").unwrap();
+        assert_eq!(vcf.samples, vec!["HG002", "HG003"]);
+        assert_eq!(vcf.length, 12);
+        assert_eq!(vcf.sites.len(), 2, "{:?}", vcf.sites);
+        let (outer, inner) = (&vcf.sites[0], &vcf.sites[1]);
+        // the alleles lose the bases every walk shares, T before the bubble and C after it
+        assert_eq!((outer.pos, outer.level, outer.parent), (5, 0, None));
+        assert_eq!(outer.alleles, vec!["GACAG", "A", "GATAG"]);
+        assert_eq!(outer.genotypes, vec![vec![Some(1), Some(2)], vec![Some(0)]]);
+        assert_eq!((inner.pos, inner.level, inner.parent), (7, 1, Some(outer.id)));
+        assert_eq!(inner.alleles, vec!["C", "T"]);
+        assert_eq!(inner.genotypes, vec![vec![None, Some(1)], vec![Some(0)]]);
+        assert_eq!((outer.variant_type, inner.variant_type), (VariantType::Complex, VariantType::Snp));
+
+        let text = vcf.to_vcf(&bigraph);
+        assert!(text.contains("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tHG002\tHG003\n"));
+        let record = format!("chr1\t7\t{}\tC\tT\t.\tPASS\tAT=>b1>x>b2,>b1>y>b2;LV=1;PS={};VT=SNP\tGT\t.|1\t0\n", inner.id, outer.id);
+        assert!(text.contains(&record), "{}", text);
+        assert!(Vcf::new(&graph, &tree, &bigraph, "CHM13").is_err());
+    }
+
+    #[test]
+    fn deletion() {
+        // a -> (b | ) -> c read by the reference backwards, so that every allele is reverse complemented
+        let gfa = "S\ta\tAAC\nS\tb\tG\nS\tc\tTT\nL\ta\t+\tb\t+\t0M\nL\tb\t+\tc\t+\t0M\nL\ta\t+\tc\t+\t0M\n\
+            P\tref\tc-,b-,a-\t*\nP\tx#1#ref\ta+,c+\t*\n";
+        let bigraph = BiGraph::from_gfa(gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let vcf = Vcf::new(&graph, &tree, &bigraph, "ref").unwrap();
+        assert_eq!(vcf.sites.len(), 1);
+        assert_eq!((vcf.sites[0].pos, &vcf.sites[0].alleles), (2, &vec!["AC".to_string(), "A".to_string()]));
+
+        // s -> p -> (b | e) -> q -> t where only s, b and t have sequence, so the empty allele takes the base before
+        let gfa = "S\ts\tAC\nS\tp\t*\nS\tb\tG\nS\te\t*\nS\tq\t*\nS\tt\tT\nL\ts\t+\tp\t+\t0M\nL\tp\t+\tb\t+\t0M\n\
+            L\tp\t+\te\t+\t0M\nL\tb\t+\tq\t+\t0M\nL\te\t+\tq\t+\t0M\nL\tq\t+\tt\t+\t0M\n\
+            P\tref\ts+,p+,b+,q+,t+\t*\nP\tx#1#ref\ts+,p+,e+,q+,t+\t*\n";
+        let bigraph = BiGraph::from_gfa(gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let vcf = Vcf::new(&graph, &tree, &bigraph, "ref").unwrap();
+        let sites = vcf.sites.iter().map(|s| (s.pos, s.alleles.join(","), s.genotypes.clone())).collect::<Vec<_>>();
+        assert!(sites.contains(&(2, "CG,C".to_string(), vec![vec![Some(1)]])), "{:?}", sites);
+        assert_eq!(vcf.sites[0].variant_type, VariantType::Indel);
+    }
+
+    #[test]
+    fn inversion() {
+        // a -> b -> c on the reference, with x taking b on the other strand
+        let gfa = "S\ta\tGA\nS\tb\tAAC\nS\tc\tTG\nL\ta\t+\tb\t+\t0M\nL\tb\t+\tc\t+\t0M\nL\ta\t+\tb\t-\t0M\n\
+            L\tb\t-\tc\t+\t0M\nP\tref\ta+,b+,c+\t*\nP\tx#1#ref\ta+,b-,c+\t*\n";
+        let bigraph = BiGraph::from_gfa(gfa).unwrap();
+        let mut graph = bigraph.to_flow_graph();
+        let tree = build_structure_tree(&mut graph);
+        let vcf = Vcf::new(&graph, &tree, &bigraph, "ref").unwrap();
+        assert_eq!(vcf.sites.len(), 1, "{:?}", vcf.sites);
+        let site = &vcf.sites[0];
+        assert_eq!((site.pos, &site.alleles, &site.genotypes), (3, &vec!["AAC".to_string(), "GTT".to_string()], &vec![vec![Some(1)]]));
+        assert_eq!(site.variant_type, VariantType::Inversion);
+        let record = format!("ref\t3\t{}\tAAC\tGTT\t.\tPASS\tAT=>a>b>c,>a<b>c;LV=0;VT=INV\tGT\t1\n", site.id);
+        assert!(vcf.to_vcf(&bigraph).contains(&record), "{}", vcf.to_vcf(&bigraph));
+    }
+}
@@ -0,0 +1,417 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+use std::fmt;
+
+use crate::{FlowGraph, StructureTree, add_graph_node, add_graph_edge, cycle_equivalence, dfs_tree};
+use crate::digraph::DiGraph;
+use crate::dominators::{DominatorTree, postdominators};
+use crate::equivalence::CycleEquivalence;
+
+// slow reference for cycle equivalence, meant for checking results on small graphs
+// two edges are cycle equivalent when no cycle contains one of them without the other
+// for each pair we look for a cycle through one edge that avoids the other, which is a
+// path between the endpoints of the first edge once both edges are removed
+
+// a pair of edges on which cycle_equivalence and the reference disagree
+#[derive(Debug)]
+pub struct Mismatch {
+    pub a: EdgeIndex,
+    pub b: EdgeIndex,
+    pub expected: bool, // whether the reference finds the edges equivalent
+    pub witness: Option<Vec<EdgeIndex>>, // a cycle through exactly one of the edges, when there is one
+}
+
+// result of checking a graph against the reference
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub edges: usize, // number of edges checked, capping backedges excluded
+    pub pairs: usize, // number of edge pairs checked
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+// which nodes can be reached from `from` without using the edges in `removed`?
+// capping backedges are ignored, and a directed search only follows edges from their `from` end
+pub fn reachable_without(graph: &FlowGraph, from: NodeIndex, removed: &[EdgeIndex], directed: bool) -> Vec<bool> {
+    let mut seen = vec![false; graph.node_count()];
+    let mut stack = vec![from];
+    seen[from.index()] = true;
+    while let Some(n) = stack.pop() {
+        for e in graph.edges(n) {
+            let edge = e.weight().borrow();
+            if removed.contains(&e.id()) || edge.is_capping || (directed && edge.from != n.index()) {
+                continue;
+            }
+            let other = if e.source() == n { e.target() } else { e.source() };
+            if !seen[other.index()] {
+                seen[other.index()] = true;
+                stack.push(other);
+            }
+        }
+    }
+    seen
+}
+
+// is there a cycle that contains edge e but not edge avoid?
+pub fn has_cycle_avoiding(graph: &FlowGraph, e: EdgeIndex, avoid: EdgeIndex) -> bool {
+    let (from, to) = graph.edge_endpoints(e).unwrap();
+    from == to || reachable_without(graph, from, &[e, avoid], false)[to.index()]
+}
+
+// reference test of cycle equivalence for a single pair of edges
+pub fn reference_equivalent(graph: &FlowGraph, a: EdgeIndex, b: EdgeIndex) -> bool {
+    a == b || (!has_cycle_avoiding(graph, a, b) && !has_cycle_avoiding(graph, b, a))
+}
+
+// partition the non-capping edges into cycle equivalence classes with the reference test
+// returns a class label per edge index, 0 for capping backedges, labels numbered from 1
+pub fn reference_classes(graph: &FlowGraph) -> Vec<usize> {
+    let edges = graph.edge_indices()
+        .filter(|e| !graph[*e].borrow().is_capping)
+        .collect::<Vec<EdgeIndex>>();
+    let mut labels = vec![0; graph.edge_count()];
+    let mut next_label = 1;
+    for (i, a) in edges.iter().enumerate() {
+        if labels[a.index()] != 0 {
+            continue;
+        }
+        labels[a.index()] = next_label;
+        for b in edges[i + 1..].iter() {
+            if labels[b.index()] == 0 && reference_equivalent(graph, *a, *b) {
+                labels[b.index()] = next_label;
+            }
+        }
+        next_label += 1;
+    }
+    labels
+}
+
+// compare the classes already assigned by cycle_equivalence with the reference on every pair of edges
+pub fn check_classes(graph: &FlowGraph) -> VerifyReport {
+    let query = CycleEquivalence::new(graph);
+    let expected = reference_classes(graph);
+    let edges = graph.edge_indices()
+        .filter(|e| !graph[*e].borrow().is_capping)
+        .collect::<Vec<EdgeIndex>>();
+    let mut report = VerifyReport { edges: edges.len(), ..Default::default() };
+    for (i, a) in edges.iter().enumerate() {
+        for b in edges[i + 1..].iter() {
+            report.pairs += 1;
+            let same = expected[a.index()] == expected[b.index()];
+            if same != query.equivalent(*a, *b) {
+                report.mismatches.push(Mismatch {
+                    a: *a,
+                    b: *b,
+                    expected: same,
+                    witness: query.witness_cycle(*a, *b),
+                });
+            }
+        }
+    }
+    report
+}
+
+// verify mode: run cycle_equivalence on the graph and check every pair of edges against the reference
+// the graph is annotated in place, exactly as build_structure_tree would do
+pub fn verify_cycle_equivalence(graph: &mut FlowGraph) -> VerifyReport {
+    let rev_order = dfs_tree(graph);
+    cycle_equivalence(graph, &rev_order);
+    check_classes(graph)
+}
+
+// small xorshift generator so that random graphs are reproducible from a seed
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // uniform value in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// random 2-edge-connected graph built from an ear decomposition
+// it starts from a cycle through the source and repeatedly adds ears, paths of fresh nodes
+// between two existing nodes (or closed back onto one node), each of which keeps the graph
+// 2-edge-connected; external ids are shuffled so they differ from node indexes
+pub fn random_two_edge_connected(rng: &mut Rng, max_nodes: usize, ears: usize) -> FlowGraph {
+    let max_nodes = max_nodes.max(2);
+    let mut ids = (0..max_nodes).collect::<Vec<usize>>();
+    for i in (1..ids.len()).rev() {
+        let j = rng.below(i + 1);
+        ids.swap(i, j);
+    }
+    let mut graph = FlowGraph::new_undirected();
+    let mut nodes = Vec::new();
+    let add_node = |graph: &mut FlowGraph, nodes: &mut Vec<NodeIndex>| {
+        let n = add_graph_node(graph, ids[nodes.len()]);
+        nodes.push(n);
+        n
+    };
+    // the initial cycle
+    let length = 2 + rng.below((max_nodes - 1).min(4));
+    let first = add_node(&mut graph, &mut nodes);
+    let mut prev = first;
+    for _ in 1..length {
+        let n = add_node(&mut graph, &mut nodes);
+        add_graph_edge(&mut graph, prev, n);
+        prev = n;
+    }
+    add_graph_edge(&mut graph, prev, first);
+    // the ears
+    for _ in 0..ears {
+        let from = nodes[rng.below(nodes.len())];
+        let to = nodes[rng.below(nodes.len())];
+        let fresh = rng.below(3).min(max_nodes - nodes.len());
+        if from == to && fresh == 0 {
+            // a self loop would be a valid ear, but the graphs we model have none
+            continue;
+        }
+        let mut prev = from;
+        for _ in 0..fresh {
+            let n = add_node(&mut graph, &mut nodes);
+            add_graph_edge(&mut graph, prev, n);
+            prev = n;
+        }
+        add_graph_edge(&mut graph, prev, to);
+    }
+    graph
+}
+
+// property check: generate random 2-edge-connected graphs and verify each one against the reference
+// returns the seed and report of the first graph that fails
+pub fn property_check(seed: u64, trials: usize, max_nodes: usize) -> Result<(), (u64, VerifyReport)> {
+    for trial in 0..trials {
+        let graph_seed = seed.wrapping_add(trial as u64);
+        let mut rng = Rng::new(graph_seed);
+        let ears = rng.below(2 * max_nodes + 1);
+        let mut graph = random_two_edge_connected(&mut rng, max_nodes, ears);
+        let report = verify_cycle_equivalence(&mut graph);
+        if !report.is_ok() {
+            return Err((graph_seed, report));
+        }
+    }
+    Ok(())
+}
+
+// reasons a region reported by build_structure_tree is not a SESE region
+#[derive(Clone,Debug,PartialEq)]
+pub enum RegionViolation {
+    MissingBoundary, // the tree did not record an entry or an exit edge
+    SameBoundary, // the entry and exit are the same edge
+    NotDominating, // the entry edge does not dominate the exit edge
+    NotPostdominating, // the exit edge does not postdominate the entry edge
+    NotCycleEquivalent, // the entry and exit edges are not cycle equivalent
+    ReversedBoundary, // the exit edge dominates the entry edge, so the region was recorded backwards
+}
+
+impl fmt::Display for RegionViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegionViolation::MissingBoundary => write!(f, "missing entry or exit edge"),
+            RegionViolation::SameBoundary => write!(f, "entry and exit are the same edge"),
+            RegionViolation::NotDominating => write!(f, "entry does not dominate exit"),
+            RegionViolation::NotPostdominating => write!(f, "exit does not postdominate entry"),
+            RegionViolation::NotCycleEquivalent => write!(f, "entry and exit are not cycle equivalent"),
+            RegionViolation::ReversedBoundary => write!(f, "entry and exit are reversed"),
+        }
+    }
+}
+
+// a region that failed verification, with its boundary edges as the tree recorded them
+#[derive(Debug)]
+pub struct RegionReport {
+    pub region: usize,
+    pub entry: Option<EdgeIndex>,
+    pub exit: Option<EdgeIndex>,
+    pub violations: Vec<RegionViolation>,
+}
+
+impl fmt::Display for RegionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reasons = self.violations.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+        write!(f, "region {} entry {:?} exit {:?}: {}", self.region, self.entry.map(|e| e.index()), self.exit.map(|e| e.index()), reasons.join(", "))
+    }
+}
+
+// check every region of the tree independently of how it was built
+// edge dominance is node dominance in the graph with a midpoint vertex on each edge
+// boundary edges are checked as recorded, so a region whose exit dominates its entry is reported as reversed
+// returns only the regions that fail, the root region is not checked
+pub fn verify_structure_tree(graph: &FlowGraph, tree: &StructureTree) -> Vec<RegionReport> {
+    let digraph = DiGraph::from_flow_graph(graph);
+    let split = digraph.edge_split_successors();
+    let dom = DominatorTree::new(&split, digraph.entry);
+    let end = match digraph.return_edge {
+        Some(r) => digraph.edge_vertex(r),
+        None => digraph.exit,
+    };
+    let pdom = postdominators(&split, end);
+    let mut reports = Vec::new();
+    for region in tree.regions() {
+        let region = region.borrow();
+        if region.parent.is_none() {
+            continue;
+        }
+        let (entry, exit) = (region.entry, region.exit);
+        let mut violations = Vec::new();
+        if entry.is_some() && entry == exit {
+            violations.push(RegionViolation::SameBoundary);
+        } else if let (Some(a), Some(b)) = (entry, exit) {
+            let (va, vb) = (digraph.edge_vertex(a.index()), digraph.edge_vertex(b.index()));
+            if dom.dominates(vb, va) && pdom.dominates(va, vb) {
+                violations.push(RegionViolation::ReversedBoundary);
+            } else {
+                if !dom.dominates(va, vb) {
+                    violations.push(RegionViolation::NotDominating);
+                }
+                if !pdom.dominates(vb, va) {
+                    violations.push(RegionViolation::NotPostdominating);
+                }
+            }
+            if !reference_equivalent(graph, a, b) {
+                violations.push(RegionViolation::NotCycleEquivalent);
+            }
+        } else {
+            violations.push(RegionViolation::MissingBoundary);
+        }
+        if !violations.is_empty() {
+            reports.push(RegionReport {
+                region: region.id,
+                entry,
+                exit,
+                violations,
+            });
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::test_utils::{examples, seeded_graphs};
+
+    #[test]
+    fn examples_agree_with_reference() {
+        for mut graph in examples() {
+            let report = verify_cycle_equivalence(&mut graph);
+            assert!(report.is_ok(), "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn nested_cycle() {
+        // the chord splits the cycle into two classes, and the chord and the parallel edges are one each;
+        // this graph used to fail both when a node was capped at itself (hi_2 equal to its dfsnum) and
+        // when the capping edge started at the node whose index equals the id
+        let mut graph = make_example_nested_cycle();
+        let report = verify_cycle_equivalence(&mut graph);
+        assert!(report.is_ok(), "{:?}", report);
+        let class = |e: usize| graph[EdgeIndex::new(e)].borrow().class;
+        assert_eq!([class(0), class(3), class(4)], [class(0); 3]);
+        assert_eq!(class(1), class(2));
+        assert_eq!(class(5), class(6));
+        let mut distinct = vec![class(0), class(1), class(5), class(7)];
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 4);
+    }
+
+    #[test]
+    fn random_graphs_agree_with_reference() {
+        if let Err((seed, report)) = property_check(1, 300, 12) {
+            panic!("graph from seed {} disagrees with the reference: {:?}", seed, report);
+        }
+    }
+
+    #[test]
+    fn witness_separates_non_equivalent_edges() {
+        let mut graph = make_example_fig1();
+        verify_cycle_equivalence(&mut graph);
+        let query = CycleEquivalence::new(&graph);
+        let edges = graph.edge_indices().filter(|e| !graph[*e].borrow().is_capping).collect::<Vec<_>>();
+        for a in edges.iter() {
+            for b in edges.iter() {
+                match query.witness_cycle(*a, *b) {
+                    Some(cycle) => {
+                        assert!(!query.equivalent(*a, *b));
+                        assert!(cycle.contains(a) != cycle.contains(b));
+                    }
+                    None => assert!(query.equivalent(*a, *b) || a == b),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn structure_trees_are_sese() {
+        for mut graph in [make_example_fig1(), make_example_diamond(), make_example_c(), make_nonplanar_1()] {
+            let tree = build_structure_tree(&mut graph);
+            let reports = verify_structure_tree(&graph, &tree);
+            assert!(reports.is_empty(), "{}", reports.iter().map(|r| r.to_string()).collect::<Vec<String>>().join("; "));
+        }
+        for mut graph in seeded_graphs(29, 10, 6).take(50) {
+            let tree = build_structure_tree(&mut graph);
+            assert!(verify_structure_tree(&graph, &tree).is_empty());
+        }
+    }
+
+    #[test]
+    fn broken_tree_is_reported() {
+        let mut graph = make_example_fig1();
+        let tree = build_structure_tree(&mut graph);
+        let regions = tree.regions();
+        let violations_of = |id: usize| {
+            let reports = verify_structure_tree(&graph, &tree);
+            reports.into_iter().find(|r| r.region == id).map(|r| r.violations).unwrap_or_default()
+        };
+        // a region recorded backwards
+        let (id, entry, exit) = {
+            let region = regions[1].borrow();
+            (region.id, region.entry, region.exit)
+        };
+        regions[1].borrow_mut().entry = exit;
+        regions[1].borrow_mut().exit = entry;
+        assert_eq!(violations_of(id), vec![RegionViolation::ReversedBoundary]);
+        let report = verify_structure_tree(&graph, &tree).into_iter().find(|r| r.region == id).unwrap();
+        assert_eq!((report.entry, report.exit), (exit, entry));
+        assert!(report.to_string().contains("reversed"));
+        // the same edge on both sides, and a missing exit
+        regions[1].borrow_mut().exit = exit;
+        assert_eq!(violations_of(id), vec![RegionViolation::SameBoundary]);
+        regions[1].borrow_mut().exit = None;
+        assert_eq!(violations_of(id), vec![RegionViolation::MissingBoundary]);
+        regions[1].borrow_mut().entry = entry;
+        regions[1].borrow_mut().exit = exit;
+        assert!(violations_of(id).is_empty());
+        // an exit from another class that the entry still dominates
+        let other = graph.edge_indices()
+            .filter(|e| !graph[*e].borrow().is_capping && !reference_equivalent(&graph, entry.unwrap(), *e))
+            .find(|e| {
+                regions[1].borrow_mut().exit = Some(*e);
+                violations_of(id).contains(&RegionViolation::NotCycleEquivalent)
+            });
+        assert!(other.is_some());
+        assert_eq!(verify_structure_tree(&graph, &tree).len(), 1);
+    }
+}